@@ -1,3 +1,4 @@
+import json
 import os
 import subprocess
 import re
@@ -12,11 +13,15 @@ class Benchmark:
         impl_name: str
         launch_server: list[str]
         listen_port: int
+        # the `--protocol` of h3-client, h2 goes over TLS on TCP
+        protocol: str
 
-        def __init__(self, impl_name: str, launch_server: list[str], listen_port: int):
+        def __init__(self, impl_name: str, launch_server: list[str], listen_port: int,
+                     protocol: str = "h3"):
             self.impl_name = impl_name
             self.listen_port = listen_port
             self.launch_server = launch_server
+            self.protocol = protocol
 
         def run(self) -> subprocess.Popen:
             # 在后台运行server
@@ -31,16 +36,34 @@ class Benchmark:
         success: int
         duration: float
         qps: float
-
-        def __init__(self, success: int, duration: float):
+        # payload bytes received by the client, from its `--report`
+        bytes: int
+        # user + system CPU seconds
+        client_cpu: float
+        server_cpu: float
+
+        def __init__(self, success: int, duration: float, bytes: int = 0,
+                     client_cpu: float = 0, server_cpu: float = 0):
             self.success, self.duration = success, duration
             self.qps = success / duration if duration > 0 else 0
+            self.bytes = bytes
+            self.client_cpu, self.server_cpu = client_cpu, server_cpu
+
+        def bytes_per_cpu_second(self, cpu: float) -> float:
+            return self.bytes / cpu if cpu > 0 else 0
+
+        def cycles_per_byte(self, cpu: float) -> float:
+            hz = cpu_hz()
+            return cpu * hz / self.bytes if hz and self.bytes > 0 else 0
 
         @staticmethod
         def average(results: list['Benchmark.Result']) -> 'Benchmark.Result':
             total_success = sum(result.success for result in results)
             total_duration = sum(result.duration for result in results)
-            return Benchmark.Result(total_success, total_duration)
+            return Benchmark.Result(total_success, total_duration,
+                                    sum(result.bytes for result in results),
+                                    sum(result.client_cpu for result in results),
+                                    sum(result.server_cpu for result in results))
 
 
 root = os.path.join(os.path.dirname(__file__), "..")
@@ -63,47 +86,45 @@ class RandomFiles:
 
 
 class Certs:
-    path = os.path.join(root, "certs")
-
-    root_cert = os.path.join(path, "root_cert.pem")
-    root_key = os.path.join(path, "root_key.pem")
-    server_cert = os.path.join(path, "server_cert.pem")
-    server_key = os.path.join(path, "server_key.pem")
-    server_csr = os.path.join(path, "server_csr.pem")
-    server_cert_der = os.path.join(path, "server_cert.der")
-    server_key_der = os.path.join(path, "server_key.der")
+    def __init__(self, key_type: str = "p256"):
+        # the key type decides what every handshake signs and verifies with
+        self.path = os.path.join(root, "certs", key_type)
+        self.root_cert = os.path.join(self.path, "root_cert.pem")
+        self.server_cert = os.path.join(self.path, "server_cert.pem")
+        self.server_key = os.path.join(self.path, "server_key.pem")
+        self.server_cert_der = os.path.join(self.path, "server_cert.der")
+        self.server_key_der = os.path.join(self.path, "server_key.der")
 
-    def __init__(self):
         if not os.path.exists(self.path):
             os.makedirs(self.path)
-            # CA
-            subprocess.run(
-                ["openssl", "ecparam", "-name", "prime256v1",
-                    "-genkey", "-out", self.root_key])
-            subprocess.run(
-                ["openssl", "req", "-new", "-x509", "-key", self.root_key, "-out", self.root_cert,
-                 "-days", "3650", "-subj", "/CN=localhost", "-addext", "subjectAltName=DNS:localhost"])
-            # Server
             subprocess.run(
-                ["openssl", "ecparam", "-name", "prime256v1", "-genkey", "-out", self.server_key])
-            subprocess.run(
-                ["openssl", "req", "-new", "-key", self.server_key, "-out",
-                 self.server_csr,  "-subj", "/CN=localhost", "-addext", "subjectAltName=DNS:localhost"])
-            subprocess.run(
-                ["openssl", "x509", "-req", "-in", self.server_csr, "-CA", self.root_cert,
-                 "-CAkey", self.root_key, "-CAcreateserial", "-out", self.server_cert,
-                 "-days", "365", "-copy_extensions", "copy"])
-            # Convert pem to der
-            subprocess.run(
-                ["openssl", "x509", "-in", self.server_cert, "-outform", "der",
-                 "-out", self.server_cert_der])
-            subprocess.run(
-                ["openssl", "ec", "-in", self.server_key, "-outform", "der",
-                 "-out", self.server_key_der])
+                ["cargo", "run", "--release", "--bin", "certgen", "--",
+                 "--key-type", key_type, "--days", "365",
+                 "--ca-cert", self.root_cert,
+                 "--cert", self.server_cert, "--key", self.server_key,
+                 "--cert-der", self.server_cert_der, "--key-der", self.server_key_der],
+                cwd=root, check=True)
+
+
+def cpu_hz() -> float | None:
+    """The clock rate `resources.rs` takes for cycles per byte."""
+    try:
+        with open("/sys/devices/system/cpu/cpu0/cpufreq/cpuinfo_max_freq") as f:
+            return float(f.read()) * 1e3
+    except (OSError, ValueError):
+        pass
+    try:
+        with open("/proc/cpuinfo") as f:
+            for line in f:
+                if line.startswith("cpu MHz"):
+                    return float(line.split(":")[1]) * 1e6
+    except OSError:
+        pass
+    return None
 
 
 rand_files = RandomFiles()
-ecc_certs = Certs()
+certs = Certs(os.environ.get("KEY_TYPE", "p256"))
 
 go_quic_dir = os.path.join(root, "go-quic")
 gm_quic_dir = os.path.join(root, "gm-quic")
@@ -122,12 +143,31 @@ def go_quic_runner() -> Benchmark.ServerRunner:
     binary = os.path.join(go_quic_dir, "quic_server")
     launch = [binary,
               "-a", "[::1]:4430",
-              "-c", ecc_certs.server_cert,
-              "-k", ecc_certs.server_key,]
+              "-c", certs.server_cert,
+              "-k", certs.server_key,]
 
     return Benchmark.ServerRunner('go-quic', launch, 4430)
 
 
+def tcp_runner() -> Benchmark.ServerRunner:
+    # the TCP baseline, this crate's h2-server
+    subprocess.run(
+        ["cargo", "build", "--release", "--bin", "h2-server"],
+        cwd=root
+    )
+
+    binary = os.path.join(root, "target", "release", "h2-server")
+
+    launch = [
+        binary,
+        "-c", certs.server_cert,
+        "-k", certs.server_key,
+        "-l", "[::1]:4435",
+    ]
+
+    return Benchmark.ServerRunner('tcp', launch, 4435, protocol="h2")
+
+
 def git_clone(owner: str, repo: str, branch: str) -> None:
     if not os.path.exists(repo):
         subprocess.run(["git", "clone", "--depth", "1", "--recursive", "--branch",
@@ -151,8 +191,8 @@ def gm_quic_runner() -> Benchmark.ServerRunner:
 
     launch = [
         binary,
-        "-c", ecc_certs.server_cert,
-        "-k", ecc_certs.server_key,
+        "-c", certs.server_cert,
+        "-k", certs.server_key,
         "-l", "[::1]:4431"
     ]
 
@@ -172,8 +212,8 @@ def tquic_runner() -> Benchmark.ServerRunner:
 
     launch = [
         binary,
-        "-c", ecc_certs.server_cert,
-        "-k", ecc_certs.server_key,
+        "-c", certs.server_cert,
+        "-k", certs.server_key,
         "-l", "[::1]:4432",
     ]
 
@@ -193,8 +233,8 @@ def quinn_runner() -> Benchmark.ServerRunner:
 
     launch = [
         binary,
-        "-c", ecc_certs.server_cert_der,
-        "-k", ecc_certs.server_key_der,
+        "-c", certs.server_cert_der,
+        "-k", certs.server_key_der,
         "-l", "[::1]:4433",
         "-d", "./"  # 实际上是rand-files
     ]
@@ -215,8 +255,8 @@ def quiche_runner() -> Benchmark.ServerRunner:
 
     launch = [
         binary,
-        "--key", ecc_certs.server_key,
-        "--cert", ecc_certs.server_cert,
+        "--key", certs.server_key,
+        "--cert", certs.server_cert,
         "--listen", "[::1]:4434",
         "--no-retry"
     ]
@@ -237,16 +277,26 @@ class H3Client:
         server = server_runner.run()
 
         uri = f'https://localhost:{server_runner.listen_port}/{rand_files.gen(file_size)}'
+        report_path = os.path.join(rand_files.path, "h3-client-report.json")
         result = subprocess.run(
             ["cargo", "run",
              "--release", "--bin", "h3-client", "--",
-             "-c", str(self.connections), "-r", str(self.requests), "--roots", ecc_certs.root_cert, uri],
+             "-c", str(self.connections), "-r", str(self.requests), "--roots", certs.root_cert,
+             "--protocol", server_runner.protocol,
+             "--report", report_path, uri],
             env={**os.environ, "RUST_LOG": "info"},
             stdout=subprocess.PIPE,
             text=True
         )
 
         server.kill()
+        # the server is our child, so the kernel kept its CPU time for us
+        _, _, server_usage = os.wait4(server.pid, 0)
+        server_cpu = server_usage.ru_utime + server_usage.ru_stime
+
+        with open(report_path) as f:
+            report = json.load(f)
+        client_cpu = report["user_time_s"] + report["system_time_s"]
 
         # Extract total_time and success_queries using regex
         output = result.stdout
@@ -255,7 +305,9 @@ class H3Client:
         if match:
             success_queries = int(match.group(1))
             total_time = float(match.group(2))
-            return Benchmark.Result(success=int(success_queries), duration=total_time)
+            return Benchmark.Result(success=int(success_queries), duration=total_time,
+                                    bytes=report["bytes"], client_cpu=client_cpu,
+                                    server_cpu=server_cpu)
         else:
             raise ValueError(f'Failed to parse benchmark output: {output}')
 
@@ -270,6 +322,7 @@ if __name__ == "__main__":
     runners = [
         go_quic_runner(),
         gm_quic_runner(),
+        tcp_runner(),
         tquic_runner(),
         quinn_runner(),
         quiche_runner()
@@ -277,12 +330,14 @@ if __name__ == "__main__":
 
     client = H3Client(conenctions=512, requests=64)
 
-    results = {}
-
+    print("impl\tsize_kb\tqps\tclient_bytes_per_cpu_s\tserver_bytes_per_cpu_s"
+          "\tclient_cycles_per_byte\tserver_cycles_per_byte")
     for runner in runners:
-        runner_results = []
         for file_size in [15, 30, 2048]:
-            runner_results.insert(file_size, client.run_many(3))
-        results.insert(runner.impl_name, runner_results)
-
-    print(results)
+            result = Benchmark.Result.average(client.run_many(runner, file_size, 3))
+            print(f"{runner.impl_name}\t{file_size}\t{result.qps:.1f}"
+                  f"\t{result.bytes_per_cpu_second(result.client_cpu):.0f}"
+                  f"\t{result.bytes_per_cpu_second(result.server_cpu):.0f}"
+                  f"\t{result.cycles_per_byte(result.client_cpu):.2f}"
+                  f"\t{result.cycles_per_byte(result.server_cpu):.2f}",
+                  flush=True)
@@ -0,0 +1,22 @@
+//! Parsers for command line values clap has no range for.
+
+use std::time::Duration;
+
+/// A rate or an interval, which `Duration` and `tokio::time::interval` take
+/// no zero, negative or infinite value of.
+pub fn positive(value: &str) -> Result<f64, String> {
+    let value = non_negative(value)?;
+    if value == 0.0 {
+        return Err("must be above 0".into());
+    }
+    Ok(value)
+}
+
+/// A delay or a timeout that may be 0.
+pub fn non_negative(value: &str) -> Result<f64, String> {
+    let value = value.parse::<f64>().map_err(|error| error.to_string())?;
+    match Duration::try_from_secs_f64(value) {
+        Ok(_) => Ok(value),
+        Err(_) => Err("must be a finite number from 0 on".into()),
+    }
+}
@@ -0,0 +1,94 @@
+use std::{fs, io, path::PathBuf};
+
+use big_file_test::certs::{Ca, CertSpec, KeyType};
+use clap::{Parser, ValueEnum};
+use time::Duration;
+
+/// Makes a throwaway CA and a server certificate it signs, for the servers'
+/// `--cert`/`--key` and the clients' `--roots`, and optionally a client
+/// certificate for mutual TLS. The CA key is never written.
+#[derive(Parser)]
+struct Options {
+    /// Key type of the server certificate, what the server signs the handshake with
+    #[arg(long, value_enum, default_value = "p256")]
+    key_type: KeyType,
+    /// Key type of the CA, the server certificate's if omitted
+    #[arg(long, value_enum)]
+    ca_key_type: Option<KeyType>,
+    /// Subject alternative names of the server, DNS names or IP addresses
+    #[arg(long = "san", default_values = ["localhost", "127.0.0.1", "::1"])]
+    sans: Vec<String>,
+    /// Days the certificates are valid for
+    #[arg(long, default_value = "30")]
+    days: i64,
+    /// Where to write the CA certificate (PEM)
+    #[arg(long, default_value = "ca.crt")]
+    ca_cert: PathBuf,
+    /// Where to write the server certificate (PEM)
+    #[arg(long, default_value = "server.crt")]
+    cert: PathBuf,
+    /// Where to write the server key (PKCS#8 PEM)
+    #[arg(long, default_value = "server.key")]
+    key: PathBuf,
+    /// Also write the server certificate in DER
+    #[arg(long)]
+    cert_der: Option<PathBuf>,
+    /// Also write the server key in DER
+    #[arg(long)]
+    key_der: Option<PathBuf>,
+    /// Also issue a client certificate, for the clients' `--client-cert` and
+    /// the servers' `--client-ca` (the CA certificate)
+    #[arg(long, requires = "client_key")]
+    client_cert: Option<PathBuf>,
+    /// Where to write the client key (PKCS#8 PEM)
+    #[arg(long, requires = "client_cert")]
+    client_key: Option<PathBuf>,
+    /// Name of the client, what the servers log it as
+    #[arg(long, default_value = "big-file-test-client")]
+    client_name: String,
+}
+
+fn main() -> io::Result<()> {
+    let options = Options::parse();
+    let sans = options.sans.iter().map(String::as_str).collect::<Vec<_>>();
+    let validity = Duration::days(options.days);
+
+    let ca_key_type = options.ca_key_type.unwrap_or(options.key_type);
+    let ca = Ca::new(&CertSpec::new(ca_key_type, &[], validity))?;
+    let server = ca.issue(&CertSpec::new(options.key_type, &sans, validity))?;
+
+    fs::write(&options.ca_cert, ca.cert().cert_pem())?;
+    fs::write(&options.cert, server.cert_pem())?;
+    fs::write(&options.key, server.key_pem())?;
+    if let Some(path) = &options.cert_der {
+        fs::write(path, server.cert_der())?;
+    }
+    if let Some(path) = &options.key_der {
+        fs::write(path, server.key_der().secret_der())?;
+    }
+
+    let name = |key_type: KeyType| key_type.to_possible_value().unwrap().get_name().to_owned();
+    println!(
+        "{} certificate for {} signed by a {} CA, valid for {} days: {}, {}, {}",
+        name(options.key_type),
+        sans.join(", "),
+        name(ca_key_type),
+        options.days,
+        options.ca_cert.display(),
+        options.cert.display(),
+        options.key.display(),
+    );
+    if let (Some(cert), Some(key)) = (&options.client_cert, &options.client_key) {
+        let spec = CertSpec::new(options.key_type, &[&options.client_name], validity);
+        let client = ca.issue_client(&spec)?;
+        fs::write(cert, client.cert_pem())?;
+        fs::write(key, client.key_pem())?;
+        println!(
+            "client certificate for {}: {}, {}",
+            options.client_name,
+            cert.display(),
+            key.display()
+        );
+    }
+    Ok(())
+}
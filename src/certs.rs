@@ -0,0 +1,167 @@
+//! Ephemeral certificates: a CA made on the spot and the leaves it signs, so
+//! no key has to be committed and a run can pick what its handshakes sign and
+//! verify with. `certgen` writes them out for the binaries, the tests keep
+//! them in memory.
+
+use std::io;
+
+use clap::ValueEnum;
+use rcgen::{
+    BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+    KeyUsagePurpose, PKCS_ECDSA_P256_SHA256, PKCS_ED25519, PKCS_RSA_SHA256,
+};
+use rsa::pkcs8::EncodePrivateKey;
+use rustls::{
+    RootCertStore,
+    pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer},
+};
+use time::{Duration, OffsetDateTime};
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyType {
+    #[default]
+    P256,
+    Ed25519,
+    Rsa2048,
+    Rsa4096,
+}
+
+impl KeyType {
+    pub fn generate(self) -> io::Result<KeyPair> {
+        let bits = match self {
+            KeyType::P256 => {
+                return KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256).map_err(io::Error::other);
+            }
+            KeyType::Ed25519 => {
+                return KeyPair::generate_for(&PKCS_ED25519).map_err(io::Error::other);
+            }
+            KeyType::Rsa2048 => 2048,
+            KeyType::Rsa4096 => 4096,
+        };
+        // ring signs with RSA keys but can't make them
+        let key =
+            rsa::RsaPrivateKey::new(&mut rsa::rand_core::OsRng, bits).map_err(io::Error::other)?;
+        let pkcs8 = key.to_pkcs8_der().map_err(io::Error::other)?;
+        KeyPair::from_pkcs8_der_and_sign_algo(
+            &PrivatePkcs8KeyDer::from(pkcs8.as_bytes()),
+            &PKCS_RSA_SHA256,
+        )
+        .map_err(io::Error::other)
+    }
+}
+
+/// What a certificate is made of besides who signs it.
+#[derive(Clone, Debug)]
+pub struct CertSpec {
+    pub key_type: KeyType,
+    /// DNS names and IP addresses, the first one is also the common name
+    pub sans: Vec<String>,
+    pub not_before: OffsetDateTime,
+    pub not_after: OffsetDateTime,
+}
+
+impl CertSpec {
+    /// Valid for `validity` from now on, backdated a minute for clock skew.
+    pub fn new(key_type: KeyType, sans: &[&str], validity: Duration) -> Self {
+        let now = OffsetDateTime::now_utc();
+        Self {
+            key_type,
+            sans: sans.iter().map(|san| san.to_string()).collect(),
+            not_before: now - Duration::minutes(1),
+            not_after: now + validity,
+        }
+    }
+
+    fn params(&self) -> io::Result<CertificateParams> {
+        let mut params = CertificateParams::new(self.sans.clone()).map_err(io::Error::other)?;
+        if let Some(name) = self.sans.first() {
+            params.distinguished_name.push(DnType::CommonName, name);
+        }
+        params.not_before = self.not_before;
+        params.not_after = self.not_after;
+        Ok(params)
+    }
+}
+
+/// A certificate and its key.
+pub struct Issued {
+    pub cert: rcgen::Certificate,
+    pub key: KeyPair,
+}
+
+impl Issued {
+    pub fn cert_der(&self) -> CertificateDer<'static> {
+        self.cert.der().clone()
+    }
+
+    pub fn key_der(&self) -> PrivateKeyDer<'static> {
+        PrivatePkcs8KeyDer::from(self.key.serialize_der()).into()
+    }
+
+    pub fn cert_pem(&self) -> String {
+        self.cert.pem()
+    }
+
+    pub fn key_pem(&self) -> String {
+        self.key.serialize_pem()
+    }
+}
+
+/// A self-signed CA living as long as the value, its key is never written out.
+pub struct Ca(Issued);
+
+impl Ca {
+    /// The CA is named `big-file-test CA`, `spec.sans` usually stays empty.
+    pub fn new(spec: &CertSpec) -> io::Result<Self> {
+        Self::named("big-file-test CA", spec)
+    }
+
+    /// A CA of another name, verifiers tell CAs apart by it.
+    pub fn named(name: &str, spec: &CertSpec) -> io::Result<Self> {
+        let mut params = spec.params()?;
+        params.distinguished_name.push(DnType::CommonName, name);
+        params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+        params.key_usages = vec![
+            KeyUsagePurpose::KeyCertSign,
+            KeyUsagePurpose::CrlSign,
+            KeyUsagePurpose::DigitalSignature,
+        ];
+        let key = spec.key_type.generate()?;
+        let cert = params.self_signed(&key).map_err(io::Error::other)?;
+        Ok(Self(Issued { cert, key }))
+    }
+
+    /// A server certificate for the names of `spec`.
+    pub fn issue(&self, spec: &CertSpec) -> io::Result<Issued> {
+        self.issue_for(spec, ExtendedKeyUsagePurpose::ServerAuth)
+    }
+
+    /// A client certificate for mutual TLS, its first name is who the server
+    /// logs the client as.
+    pub fn issue_client(&self, spec: &CertSpec) -> io::Result<Issued> {
+        self.issue_for(spec, ExtendedKeyUsagePurpose::ClientAuth)
+    }
+
+    fn issue_for(&self, spec: &CertSpec, usage: ExtendedKeyUsagePurpose) -> io::Result<Issued> {
+        let mut params = spec.params()?;
+        params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+        params.extended_key_usages = vec![usage];
+        params.use_authority_key_identifier_extension = true;
+        let key = spec.key_type.generate()?;
+        let cert = params
+            .signed_by(&key, &self.0.cert, &self.0.key)
+            .map_err(io::Error::other)?;
+        Ok(Issued { cert, key })
+    }
+
+    pub fn cert(&self) -> &Issued {
+        &self.0
+    }
+
+    /// A root store trusting only this CA.
+    pub fn roots(&self) -> io::Result<RootCertStore> {
+        let mut roots = RootCertStore::empty();
+        roots.add(self.0.cert_der()).map_err(io::Error::other)?;
+        Ok(roots)
+    }
+}
@@ -1,17 +1,24 @@
-use std::{io, path::PathBuf, sync::Arc, time::Duration};
-
+use std::{net::SocketAddr, path::PathBuf, sync::Arc, time::Instant};
+
+use big_file_test::{
+    congestion::CongestionOptions,
+    datagram::DatagramOptions,
+    echo,
+    eyeballs::{Candidates, EyeballsOptions},
+    logging::{LogDefaults, LogFormat, LogOptions},
+    migration::MigrationOptions,
+    multipath::MultipathOptions,
+    report::{Report, ReportOptions},
+    sockets::Sockets,
+    tls::{self, ClientCertOptions, ResumptionOptions},
+    transfer::Progress,
+};
 use clap::Parser;
-use gm_quic::ToCertificate;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use gm_quic::{Connection, QuicClient};
 use qlog::telemetry::{
     Log,
     handy::{DefaultSeqLogger, NullLogger},
 };
-use rustls::RootCertStore;
-use tokio::{
-    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
-    task::JoinSet,
-};
 
 #[derive(Parser)]
 struct Options {
@@ -25,30 +32,55 @@ struct Options {
     file: PathBuf,
     #[arg(short = 'p', long)]
     progress: bool,
+    /// Root certificates to verify the server with, see `certgen`
+    #[arg(long, default_value = "ca.crt")]
+    roots: Vec<PathBuf>,
+    #[command(flatten)]
+    client_cert: ClientCertOptions,
+    /// Bytes to echo once before the transfer, timing how long the handshake
+    /// makes the first echo wait
+    #[arg(long, value_name = "BYTES", default_value = "1024")]
+    ping: usize,
+    #[command(flatten)]
+    eyeballs: EyeballsOptions,
+    #[command(flatten)]
+    resumption: ResumptionOptions,
+    #[command(flatten)]
+    migration: MigrationOptions,
+    #[command(flatten)]
+    multipath: MultipathOptions,
+    #[command(flatten)]
+    datagram: DatagramOptions,
+    #[command(flatten)]
+    congestion: CongestionOptions,
+    /// `--log-file` may also use `{streams}` and `{size}` (file size in MB)
+    #[command(flatten)]
+    log: LogOptions,
+    #[command(flatten)]
+    report: ReportOptions,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let options = Options::parse();
     let file_size = options.file.metadata()?.len() / (1024 * 1024);
-    let output = format!("client-{}*{}M.output", options.streams, file_size);
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::DEBUG)
-        .with_ansi(false)
-        .with_writer(
-            std::fs::OpenOptions::new()
-                .create(true)
-                .truncate(true)
-                .write(true)
-                .open(output)?,
-        )
-        .init();
+    let log_defaults = LogDefaults {
+        filter: "debug",
+        format: LogFormat::Json,
+        file: Some("{bin}-{streams}x{size}M.output"),
+    };
+    options.log.init(
+        env!("CARGO_BIN_NAME"),
+        log_defaults,
+        &[("streams", &options.streams), ("size", &file_size)],
+    )?;
     run(options)
         .await
         .inspect_err(|error| tracing::error!(?error))
 }
 
 async fn run(options: Options) -> Result<(), Box<dyn std::error::Error>> {
+    let mut report = Report::start(env!("CARGO_BIN_NAME"));
     let qlogger = options
         .qlog_dir
         .as_ref()
@@ -59,159 +91,124 @@ async fn run(options: Options) -> Result<(), Box<dyn std::error::Error>> {
 
     let file = Arc::new(tokio::fs::read(&options.file).await?);
 
-    let pbs = MultiProgress::new();
-    if !options.progress {
-        pbs.set_draw_target(indicatif::ProgressDrawTarget::hidden());
-    }
-
-    let pb_stype = ProgressStyle::default_bar()
-        .template("{prefix} {wide_bar} {percent_precise}% {decimal_bytes_per_sec} ETA: {eta} {msg}")
-        .unwrap();
-
-    let tx_pbs = (0..options.streams)
-        .map(|idx| {
-            pbs.add(
-                ProgressBar::new(file.len() as u64)
-                    .with_style(pb_stype.clone())
-                    .with_prefix(format!("流{idx}↑")),
-            )
-        })
-        .collect::<Vec<_>>();
-
-    let total_tx_pb = pbs.add(
-        ProgressBar::new((file.len() * options.streams) as u64)
-            .with_style(pb_stype.clone())
-            .with_prefix("总↑"),
-    );
-
-    let rx_pbs = (0..options.streams)
-        .map(|idx| {
-            pbs.add(
-                ProgressBar::new(file.len() as u64)
-                    .with_style(pb_stype.clone())
-                    .with_prefix(format!("流{idx}↓")),
-            )
-        })
-        .collect::<Vec<_>>();
-
-    let total_rx_pb = pbs.add(
-        ProgressBar::new((file.len() * options.streams) as u64)
-            .with_style(pb_stype.clone())
-            .with_prefix("总↓"),
-    );
+    let progress = Arc::new(Progress::new(
+        options.streams,
+        file.len() as u64,
+        options.progress,
+    ));
 
     let uri = options.server.parse::<http::Uri>()?;
     let server_name = uri.host().ok_or("missing host")?;
-    let mut server_addrs = tokio::net::lookup_host(server_name).await?;
-    let server_addr = server_addrs.next().ok_or("DNS lookup failed")?;
-
-    let mut roots = RootCertStore::empty();
-    roots.add_parsable_certificates(include_bytes!("../ca.crt").to_certificate());
-
-    let client = Arc::new(
-        gm_quic::QuicClient::builder()
-            .with_root_certificates(roots)
-            .without_cert()
-            .with_parameters(client_stream_unlimited_parameters())
-            .with_qlog(qlogger)
-            .enable_sslkeylog()
-            .build(),
-    );
-
-    let connection = client.connect(server_name, server_addr)?;
-    tracing::info!("connecting to {server_name}[{server_addr}]");
-
-    let mut streams = JoinSet::new();
-    for (stream_idx, (tx_pb, rx_pb)) in (0..options.streams).zip(tx_pbs.into_iter().zip(rx_pbs)) {
-        let (_stream_id, (reader, writer)) = connection.open_bi_stream().await?.unwrap();
-        tracing::info!(stream_idx, "opened stream");
-
-        let file = file.clone();
-        let total_tx_pb = total_tx_pb.clone();
-        let total_rx_pb = total_rx_pb.clone();
-
-        streams.spawn(async move {
-            tokio::try_join!(
-                upload_stream(file.clone(), writer, tx_pb, total_tx_pb),
-                rx_stream(file.clone(), reader, rx_pb, total_rx_pb),
-            )
-        });
-    }
-
-    let ticker = {
-        let pbs = pbs.clone();
-        async move {
-            let mut interval = tokio::time::interval(Duration::from_millis(33));
-            loop {
-                pbs.suspend(|| ());
-                interval.tick().await;
-            }
-        }
-    };
-
-    tokio::select! {
-        all = streams.join_all() => { _ = all.into_iter().collect::<Result<Vec<_>, _>>()? },
-        _ = ticker => unreachable!(),
+    let candidates = options
+        .eyeballs
+        .resolve(server_name, uri.port_u16().unwrap_or(35467))
+        .await?;
+
+    let roots = tls::load_roots(&options.roots)?;
+    let identity = options.client_cert.identity()?;
+    let sockets = Sockets::default();
+    let client = echo::client(
+        roots,
+        identity.as_ref(),
+        &sockets,
+        &options.resumption,
+        &options.congestion,
+        qlogger,
+    )?;
+    let ping = vec![0x5a; options.ping];
+
+    let (connection, server_addr, first_echo) =
+        connect(&client, server_name, &candidates, &ping).await?;
+
+    let start_time = Instant::now();
+    let (mut migration, mut paths, mut datagrams) = (None, None, None);
+    if options.migration.enabled() {
+        let migrated = echo::echo_migrating(
+            &connection,
+            &sockets,
+            server_addr,
+            file.clone(),
+            progress,
+            &options.migration,
+        )
+        .await?;
+        migration = Some(migrated);
+    } else if options.multipath.enabled() {
+        let carried = echo::echo_multipath(
+            &connection,
+            &sockets,
+            server_addr,
+            file.clone(),
+            progress,
+            &options.multipath,
+        )
+        .await?;
+        paths = Some(carried);
+    } else if options.datagram.datagrams.is_some() {
+        let sent =
+            echo::send_datagrams(&connection, file.clone(), progress, &options.datagram).await?;
+        datagrams = Some(sent);
+    } else {
+        echo::echo_streams(&connection, file.clone(), progress).await?;
     }
 
-    total_tx_pb.finish_with_message("done");
-    total_rx_pb.finish_with_message("done");
-
+    let transfer_time = start_time.elapsed().as_secs_f64();
     connection.close("no error".into(), 0);
 
-    Ok(())
-}
-
-async fn rx_stream(
-    file: Arc<Vec<u8>>,
-    mut reader: impl AsyncRead + Unpin,
-    rx_pb: ProgressBar,
-    total_rx_pb: ProgressBar,
-) -> io::Result<()> {
-    let mut back = vec![];
-    loop {
-        let n = reader.read_buf(&mut back).await?;
-        rx_pb.inc(n as u64);
-        total_rx_pb.inc(n as u64);
-        if n == 0 {
-            break;
-        }
+    // every stream uploads the file and downloads it back, unless only
+    // datagrams went
+    let streamed = datagrams.is_none() || options.datagram.with_streams;
+    let mut bytes = if streamed {
+        (file.len() * options.streams * 2) as u64
+    } else {
+        0
+    };
+    bytes += datagrams.as_ref().map_or(0, |datagrams| datagrams.bytes());
+    report
+        .set("server", server_addr.to_string())
+        .set("streams", options.streams)
+        .set("file_bytes", file.len())
+        .set("transfer_time_s", transfer_time)
+        .set("throughput_bytes_per_s", bytes as f64 / transfer_time)
+        .set("client_cert", identity.is_some())
+        .set("ping_bytes", options.ping)
+        .set("first_echo_time_s", first_echo);
+    options.eyeballs.report(&mut report);
+    candidates.report(&mut report);
+    options.resumption.report(&mut report);
+    if let Some(migration) = migration {
+        options.migration.report(&mut report);
+        migration.report(&mut report);
     }
-
-    assert_eq!(back, *file);
-    rx_pb.finish_with_message("done");
-    io::Result::Ok(())
-}
-
-async fn upload_stream(
-    file: Arc<Vec<u8>>,
-    mut writer: impl AsyncWrite + Unpin,
-    tx_pb: ProgressBar,
-    total_tx_pb: ProgressBar,
-) -> Result<(), io::Error> {
-    let mut file = file.as_slice();
-    while !file.is_empty() {
-        let write = writer.write(file).await?;
-        tx_pb.inc(write as u64);
-        total_tx_pb.inc(write as u64);
-        file = &file[write..];
+    if let Some(paths) = paths {
+        paths.report(&mut report);
+    }
+    if let Some(datagrams) = datagrams {
+        options.datagram.report(&mut report);
+        datagrams.report(&mut report);
     }
-    tx_pb.set_message("shutdown...");
-    writer.shutdown().await?;
-    tx_pb.finish_with_message("done");
+    options.congestion.report(&mut report);
+    report.finish(bytes, &options.report)?;
+
     Ok(())
 }
 
-fn client_stream_unlimited_parameters() -> gm_quic::ClientParameters {
-    let mut params = gm_quic::ClientParameters::default();
-
-    params.set_initial_max_streams_bidi(100u32);
-    params.set_initial_max_streams_uni(100u32);
-    params.set_initial_max_data(1u32 << 20);
-    params.set_initial_max_stream_data_uni(1u32 << 20);
-    params.set_initial_max_stream_data_bidi_local(1u32 << 20);
-    params.set_initial_max_stream_data_bidi_remote(1u32 << 20);
-    params.set_max_idle_timeout(Duration::from_secs(10));
-
-    params
+/// Connect to the first of `candidates` to answer the ping, returning its
+/// address and the seconds until the ping came back.
+async fn connect(
+    client: &QuicClient,
+    server_name: &str,
+    candidates: &Candidates,
+    ping: &[u8],
+) -> Result<(Arc<Connection>, SocketAddr, f64), Box<dyn std::error::Error>> {
+    let start = Instant::now();
+    let (server_addr, connection) = candidates
+        .race(|server_addr| async move {
+            let connection = client.connect(server_name, server_addr)?;
+            tracing::info!("connecting to {server_name}[{server_addr}]");
+            echo::ping(&connection, ping).await?;
+            Ok::<_, Box<dyn std::error::Error>>(connection)
+        })
+        .await?;
+    Ok((connection, server_addr, start.elapsed().as_secs_f64()))
 }
@@ -0,0 +1,62 @@
+//! Congestion control of the QUIC binaries, the main question this harness
+//! answers being how the controllers compare on large transfers under
+//! impairment.
+//!
+//! Both ends take the same options, a connection runs the controller of the
+//! sending side per direction. The choice is recorded in the report, and
+//! `qlog-analyze` prints the `recovery:parameters_set` and
+//! `recovery:congestion_state_updated` events of the traces.
+//!
+//! gm-quic 0.2.2 runs NewReno with pacing and its own initial window on every
+//! path and takes no choice, so [`CongestionOptions::check`] refuses anything
+//! else rather than have a run report a controller it didn't use.
+
+use std::io;
+
+use clap::{Args, ValueEnum};
+
+use crate::report::Report;
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Algorithm {
+    Bbr,
+    #[default]
+    NewReno,
+}
+
+#[derive(Args, Clone, Debug, Default)]
+pub struct CongestionOptions {
+    /// Congestion controller
+    #[arg(long = "cc", value_enum, default_value = "new-reno")]
+    pub algorithm: Algorithm,
+    /// Initial congestion window in packets, the controller's default if omitted
+    #[arg(long, value_name = "PACKETS")]
+    pub initial_window: Option<u64>,
+    /// Send whatever the congestion window allows at once instead of pacing it
+    #[arg(long)]
+    pub no_pacing: bool,
+}
+
+impl CongestionOptions {
+    /// Fails unless the options are what gm-quic runs anyway.
+    pub fn check(&self) -> io::Result<()> {
+        let unsupported = if self.algorithm != Algorithm::NewReno {
+            "gm-quic 0.2.2 only runs NewReno"
+        } else if self.initial_window.is_some() {
+            "gm-quic 0.2.2 has no initial window to set"
+        } else if self.no_pacing {
+            "gm-quic 0.2.2 always paces"
+        } else {
+            return Ok(());
+        };
+        Err(io::Error::new(io::ErrorKind::Unsupported, unsupported))
+    }
+
+    pub fn report(&self, report: &mut Report) {
+        let algorithm = self.algorithm.to_possible_value().unwrap();
+        report
+            .set("congestion_control", algorithm.get_name())
+            .set("initial_window_packets", self.initial_window)
+            .set("pacing", !self.no_pacing);
+    }
+}
@@ -0,0 +1,317 @@
+//! The datagram mode of `client` and `server`: sequence-numbered QUIC
+//! DATAGRAM frames (RFC 9221), sent at a target rate or as fast as gm-quic
+//! takes them. The server counts every datagram and sends the ones asking for
+//! it back, stamped with when it got them, so the client sees both directions
+//! of each: what arrived, in which order and with how much jitter.
+//!
+//! A datagram starts with its sequence number, the client's send time, whether
+//! to reflect it and the server's receive time, the rest is padding. The times
+//! are microseconds since each side's own start, one-way jitter only needs
+//! them to tick at the same rate.
+
+use std::{
+    collections::BTreeSet,
+    future::Future,
+    io,
+    time::{Duration, Instant},
+};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use clap::{Args, ValueEnum};
+use tokio::time::{self, MissedTickBehavior};
+
+use crate::{args, report::Report};
+
+/// Sequence number, client send time, reflect flag and server receive time.
+pub const HEADER: usize = 8 + 8 + 1 + 8;
+/// How long reflected datagrams are waited for once all were sent.
+const LINGER: Duration = Duration::from_secs(1);
+
+/// What the server does with the datagrams.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DatagramMode {
+    /// Send them back, so the client measures both directions
+    Reflect,
+    /// Only count them, see the server's report
+    Count,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct DatagramOptions {
+    /// Send this many datagrams, instead of echoing the file or alongside it
+    /// with `--with-streams`
+    #[arg(
+        long,
+        value_name = "COUNT",
+        conflicts_with_all = ["migrate_after", "migrate_at", "paths"]
+    )]
+    pub datagrams: Option<u64>,
+    /// Bytes of every datagram, header included
+    #[arg(
+        long,
+        value_name = "BYTES",
+        default_value = "1024",
+        value_parser = clap::value_parser!(u16).range(HEADER as i64..)
+    )]
+    pub datagram_size: u16,
+    /// Datagrams per second, as fast as gm-quic takes them if omitted
+    #[arg(
+        long,
+        value_name = "PER_SECOND",
+        requires = "datagrams",
+        value_parser = args::positive
+    )]
+    pub datagram_rate: Option<f64>,
+    #[arg(long, value_enum, default_value = "reflect")]
+    pub datagram_mode: DatagramMode,
+    /// Echo the file over the streams while the datagrams go
+    #[arg(long, requires = "datagrams")]
+    pub with_streams: bool,
+}
+
+impl DatagramOptions {
+    pub fn report(&self, report: &mut Report) {
+        report
+            .set("datagram_size", self.datagram_size)
+            .set("datagram_rate_per_s", self.datagram_rate)
+            .set(
+                "datagram_mode",
+                format!("{:?}", self.datagram_mode).to_lowercase(),
+            )
+            .set("with_streams", self.with_streams);
+    }
+}
+
+/// Datagram `seq` sent `sent` into the run, padded to `size`.
+fn encode(seq: u64, sent: Duration, mode: DatagramMode, size: usize) -> Bytes {
+    let mut datagram = BytesMut::with_capacity(size);
+    datagram.put_u64(seq);
+    datagram.put_u64(sent.as_micros() as u64);
+    datagram.put_u8((mode == DatagramMode::Reflect) as u8);
+    datagram.put_u64(0);
+    datagram.resize(size.max(HEADER), 0);
+    datagram.freeze()
+}
+
+/// What the server sends back for a datagram it got `received` into its run,
+/// nothing if the client didn't ask for it.
+pub fn reflect(datagram: &[u8], received: Duration) -> Option<Bytes> {
+    if datagram.len() < HEADER || datagram[16] == 0 {
+        return None;
+    }
+    let mut reflected = BytesMut::from(datagram);
+    reflected[17..HEADER].copy_from_slice(&(received.as_micros() as u64).to_be_bytes());
+    Some(reflected.freeze())
+}
+
+/// A reflected datagram as it came back.
+#[derive(Clone, Copy, Debug)]
+struct Arrival {
+    seq: u64,
+    /// Microseconds into the client's run it was sent.
+    sent: u64,
+    /// Microseconds into the server's run it got there.
+    reflected: u64,
+    /// Microseconds into the client's run it came back.
+    returned: u64,
+    bytes: usize,
+}
+
+impl Arrival {
+    fn decode(datagram: &[u8], returned: Duration) -> Option<Self> {
+        let mut header = datagram.get(..HEADER)?;
+        let seq = header.get_u64();
+        let sent = header.get_u64();
+        header.advance(1);
+        Some(Self {
+            seq,
+            sent,
+            reflected: header.get_u64(),
+            returned: returned.as_micros() as u64,
+            bytes: datagram.len(),
+        })
+    }
+}
+
+/// The interarrival jitter of RFC 3550 over `transits`, in the order they
+/// arrived, in ms.
+fn jitter(transits: impl Iterator<Item = i64>) -> Option<f64> {
+    let mut transits = transits.peekable();
+    transits.peek()?;
+    let mut jitter = 0.0;
+    let mut last = None;
+    for transit in transits {
+        if let Some(last) = last {
+            let difference = (transit - last) as f64;
+            jitter += (difference.abs() - jitter) / 16.0;
+        }
+        last = Some(transit);
+    }
+    Some(jitter / 1e3)
+}
+
+/// What a datagram run sent and what came back.
+#[derive(Debug)]
+pub struct Datagrams {
+    sent: u64,
+    sent_bytes: u64,
+    /// How long sending took.
+    sending: Duration,
+    arrivals: Vec<Arrival>,
+    /// From the first datagram sent until the last one came back.
+    elapsed: Duration,
+}
+
+impl Datagrams {
+    /// Send `options.datagrams` with `send`, meanwhile taking in what comes
+    /// back from `receive`, until all reflected ones did or a second after
+    /// the last was sent.
+    pub async fn run<F>(
+        options: &DatagramOptions,
+        mut send: impl FnMut(Bytes) -> io::Result<()>,
+        mut receive: impl FnMut() -> F,
+    ) -> io::Result<Self>
+    where
+        F: Future<Output = io::Result<Bytes>>,
+    {
+        let count = options.datagrams.unwrap_or_default();
+        let size = options.datagram_size as usize;
+        let start = Instant::now();
+
+        let mut arrivals = vec![];
+        let expected = match options.datagram_mode {
+            DatagramMode::Reflect => count as usize,
+            DatagramMode::Count => 0,
+        };
+        let receiving = async {
+            while arrivals.len() < expected {
+                let datagram = receive().await?;
+                arrivals.extend(Arrival::decode(&datagram, start.elapsed()));
+            }
+            Ok::<_, io::Error>(())
+        };
+        let sending = async {
+            let mut rate = options.datagram_rate.map(|rate| {
+                let mut interval = time::interval(Duration::from_secs_f64(1.0 / rate));
+                interval.set_missed_tick_behavior(MissedTickBehavior::Burst);
+                interval
+            });
+            for seq in 0..count {
+                match &mut rate {
+                    Some(rate) => _ = rate.tick().await,
+                    // let the receiving and the streams have a go
+                    None => tokio::task::yield_now().await,
+                }
+                send(encode(seq, start.elapsed(), options.datagram_mode, size))?;
+            }
+            Ok::<_, io::Error>(start.elapsed())
+        };
+        // done with `arrivals` once all were sent and the last ones are in
+        let sending = {
+            tokio::pin!(receiving, sending);
+            let mut received_all = false;
+            let sending = loop {
+                tokio::select! {
+                    sent = &mut sending => break sent?,
+                    received = &mut receiving, if !received_all => {
+                        received?;
+                        received_all = true;
+                    }
+                }
+            };
+            if !received_all {
+                // what didn't come back by then was lost
+                time::timeout(LINGER, &mut receiving)
+                    .await
+                    .unwrap_or(Ok(()))?;
+            }
+            sending
+        };
+
+        let elapsed = arrivals
+            .iter()
+            .map(|arrival| Duration::from_micros(arrival.returned))
+            .max()
+            .unwrap_or(sending);
+        Ok(Self {
+            sent: count,
+            sent_bytes: count * size as u64,
+            sending,
+            arrivals,
+            elapsed,
+        })
+    }
+
+    /// Bytes of the datagrams sent.
+    pub fn bytes(&self) -> u64 {
+        self.sent_bytes
+    }
+
+    /// Datagrams that came back, each once.
+    pub fn received(&self) -> usize {
+        let seqs = self.arrivals.iter().map(|arrival| arrival.seq);
+        seqs.collect::<BTreeSet<_>>().len()
+    }
+
+    /// Sets what was sent and how fast, and for reflected datagrams the
+    /// delivery ratio, duplicates, reordering, round trip times, the jitter
+    /// of each direction and the goodput of what came back.
+    pub fn report(&self, report: &mut Report) {
+        report
+            .set("datagrams_sent", self.sent)
+            .set("datagram_bytes_sent", self.sent_bytes)
+            .set(
+                "datagram_send_rate_bytes_per_s",
+                self.sent_bytes as f64 / self.sending.as_secs_f64(),
+            );
+        if self.arrivals.is_empty() {
+            return;
+        }
+
+        let mut seen = vec![false; self.sent as usize];
+        let (mut unique, mut duplicates, mut reordered, mut bytes) = (0u64, 0u64, 0u64, 0u64);
+        let mut highest = None;
+        for arrival in &self.arrivals {
+            match seen.get_mut(arrival.seq as usize) {
+                Some(seen) if !*seen => {
+                    *seen = true;
+                    unique += 1;
+                    bytes += arrival.bytes as u64;
+                }
+                _ => duplicates += 1,
+            }
+            if highest.is_some_and(|highest| arrival.seq < highest) {
+                reordered += 1;
+            }
+            highest = highest.max(Some(arrival.seq));
+        }
+        let rtts = self
+            .arrivals
+            .iter()
+            .map(|arrival| (arrival.returned - arrival.sent) as f64 / 1e3)
+            .collect::<Vec<_>>();
+        let forward = self
+            .arrivals
+            .iter()
+            .map(|arrival| arrival.reflected as i64 - arrival.sent as i64);
+        let back = self
+            .arrivals
+            .iter()
+            .map(|arrival| arrival.returned as i64 - arrival.reflected as i64);
+        report
+            .set("datagrams_received", unique)
+            .set(
+                "datagram_delivery_ratio",
+                unique as f64 / self.sent.max(1) as f64,
+            )
+            .set("datagram_duplicates", duplicates)
+            .set("datagrams_reordered", reordered)
+            .set("datagram_forward_jitter_ms", jitter(forward))
+            .set("datagram_return_jitter_ms", jitter(back))
+            .set(
+                "datagram_goodput_bytes_per_s",
+                bytes as f64 / self.elapsed.as_secs_f64(),
+            )
+            .set_percentiles("datagram_rtt", "ms", &rtts);
+    }
+}
@@ -0,0 +1,318 @@
+//! The QUIC echo of `server` and `client`: every bidirectional stream the
+//! client opens sends the file back as it arrives.
+
+use std::{
+    future::{self, Future},
+    io,
+    net::SocketAddr,
+    sync::{Arc, atomic::Ordering},
+    time::{Duration, Instant},
+};
+
+use gm_quic::{Connection, QuicClient, QuicServer, StreamReader, StreamWriter};
+use qlog::telemetry::Log;
+use rustls::{RootCertStore, server::danger::ClientCertVerifier};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    task::JoinSet,
+};
+use tracing::Instrument;
+
+use crate::{
+    congestion::CongestionOptions,
+    datagram::{self, DatagramOptions, Datagrams},
+    migration::{Migration, MigrationOptions},
+    multipath::{MultipathOptions, Paths},
+    report::ServerStats,
+    sockets::{self, Sockets},
+    tls::{self, Identity, ResumptionOptions, TicketOptions},
+    transfer::{self, Progress},
+};
+
+/// `cert` and `key` are PEM, clients are asked for a certificate if
+/// `client_verifier` is given and handed session tickets as `tickets` says.
+pub fn listen(
+    bind: SocketAddr,
+    cert: &[u8],
+    key: &[u8],
+    client_verifier: Option<Arc<dyn ClientCertVerifier>>,
+    tickets: &TicketOptions,
+    congestion: &CongestionOptions,
+    qlogger: Arc<dyn Log + Send + Sync>,
+) -> io::Result<Arc<QuicServer>> {
+    congestion.check()?;
+    let config = tls::quic_server_config(cert, key, client_verifier, tickets)?;
+    gm_quic::QuicServer::builder_with_tls(config)
+        .with_parameters(server_stream_unlimited_parameters())
+        .with_qlog(qlogger)
+        .listen(bind)
+}
+
+/// Echo the streams of every connection `server` accepts, until `shutdown`
+/// resolves. Connections still open then are aborted.
+pub async fn serve(
+    server: &QuicServer,
+    stats: Arc<ServerStats>,
+    shutdown: impl Future<Output = ()>,
+) {
+    let mut connections = JoinSet::new();
+    let accept = async {
+        while let Ok((connection, pathway)) = server.accept().await {
+            tracing::info!(%pathway, "new connection");
+            stats.connections.fetch_add(1, Ordering::Relaxed);
+            connections.spawn(
+                for_each_conn(connection, stats.clone())
+                    .instrument(tracing::info_span!("conn", from = %pathway.remote())),
+            );
+        }
+    };
+
+    tokio::select! {
+        _ = accept => {},
+        _ = shutdown => {},
+    }
+}
+
+async fn for_each_conn(conn: Arc<Connection>, stats: Arc<ServerStats>) -> io::Result<()> {
+    // dropped with the connection task, so shutting down aborts the streams too
+    let mut streams = JoinSet::new();
+    streams.spawn(
+        reflect_datagrams(conn.clone(), stats.clone()).instrument(tracing::info_span!("datagrams")),
+    );
+    loop {
+        let Some((stream_id, (reader, writer))) = conn.accept_bi_stream().await? else {
+            return Ok(());
+        };
+
+        streams.spawn(
+            for_each_stream(reader, writer, stats.clone())
+                .instrument(tracing::info_span!("stream", %stream_id)),
+        );
+    }
+}
+
+/// Count the datagrams of `conn` and send back the ones asking for it.
+async fn reflect_datagrams(conn: Arc<Connection>, stats: Arc<ServerStats>) -> io::Result<()> {
+    let mut reader = conn.unreliable_reader()?;
+    let writer = conn.unreliable_writer().await?;
+    let start = Instant::now();
+    loop {
+        let received = reader.recv().await?;
+        stats.datagrams.fetch_add(1, Ordering::Relaxed);
+        stats
+            .datagram_bytes
+            .fetch_add(received.len() as u64, Ordering::Relaxed);
+        if let Some(reflected) = datagram::reflect(&received, start.elapsed()) {
+            // one that doesn't fit the path or the queue is lost like any other
+            _ = writer.send_bytes(reflected);
+        }
+    }
+}
+
+async fn for_each_stream(
+    mut reader: StreamReader,
+    mut writer: StreamWriter,
+    stats: Arc<ServerStats>,
+) -> io::Result<()> {
+    let mut buffer = [0; 4096];
+
+    let mut echoed = 0;
+    loop {
+        match reader.read(&mut buffer).await? {
+            0 => break,
+            n => {
+                echoed += n;
+                writer.write_all(&buffer[..n]).await?;
+                stats.bytes.fetch_add(n as u64, Ordering::Relaxed);
+            }
+        }
+    }
+
+    tracing::info!(echoed, "transfer completed, waiting for ack");
+    writer.shutdown().await?;
+    tracing::info!("done");
+    Ok(())
+}
+
+/// `identity` is presented if the server asks for a certificate, the sockets
+/// are bound through `sockets`. Every connection does a full handshake, see
+/// [`ResumptionOptions::check_quic`].
+pub fn client(
+    roots: RootCertStore,
+    identity: Option<&Identity>,
+    sockets: &Sockets,
+    resumption: &ResumptionOptions,
+    congestion: &CongestionOptions,
+    qlogger: Arc<dyn Log + Send + Sync>,
+) -> io::Result<QuicClient> {
+    congestion.check()?;
+    resumption.check_quic()?;
+    let builder = gm_quic::QuicClient::builder().with_root_certificates(roots);
+    let builder = match identity {
+        Some(identity) => builder.with_cert(identity.cert_chain.clone(), identity.key.clone_key()),
+        None => builder.without_cert(),
+    };
+    Ok(builder
+        .with_iface_factory(sockets.clone())
+        .with_parameters(client_stream_unlimited_parameters())
+        .with_qlog(qlogger)
+        .enable_sslkeylog()
+        .build())
+}
+
+/// Echo `file` over one stream per bar of `progress`.
+pub async fn echo_streams(
+    connection: &Connection,
+    file: Arc<Vec<u8>>,
+    progress: Arc<Progress>,
+) -> io::Result<()> {
+    let mut streams = JoinSet::new();
+    for (stream_idx, (tx_pb, rx_pb)) in progress.streams.iter().cloned().enumerate() {
+        let (_stream_id, (reader, writer)) = connection
+            .open_bi_stream()
+            .await?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "connection closed"))?;
+        tracing::info!(stream_idx, "opened stream");
+
+        let file = file.clone();
+        let progress = progress.clone();
+
+        streams.spawn(async move {
+            tokio::try_join!(
+                transfer::upload(&file, writer, tx_pb, progress.total_tx.clone()),
+                transfer::receive(&file, reader, rx_pb, progress.total_rx.clone()),
+            )
+        });
+    }
+
+    tokio::select! {
+        all = streams.join_all() => { _ = all.into_iter().collect::<Result<Vec<_>, _>>()? },
+        _ = progress.tick() => unreachable!(),
+    }
+    progress.finish();
+    Ok(())
+}
+
+/// [`echo_streams`], moving `connection` to a new socket of `sockets` partway
+/// through when `options` say so.
+pub async fn echo_migrating(
+    connection: &Connection,
+    sockets: &Sockets,
+    remote: SocketAddr,
+    file: Arc<Vec<u8>>,
+    progress: Arc<Progress>,
+    options: &MigrationOptions,
+) -> io::Result<Migration> {
+    let mut migration = Migration::default();
+    let received = progress.total_rx.clone();
+    let rebind = |local| {
+        let old = sockets.local_addrs();
+        let local = sockets.add_path(connection, local, remote)?;
+        // the old path is given up, gm-quic validates the new one and only
+        // probes on it until then. Paths the connection never had are no loss
+        for old in old {
+            connection.del_path(&sockets::pathway(old, remote))?;
+        }
+        Ok(local)
+    };
+    tokio::select! {
+        echoed = echo_streams(connection, file, progress) => echoed?,
+        error = migration.run(options, remote, &received, rebind) => return Err(error),
+    }
+    Ok(migration)
+}
+
+/// [`echo_streams`] over a path to `remote` and one more from a new socket
+/// of `sockets` for each of `options`, counting what every path carried.
+pub async fn echo_multipath(
+    connection: &Connection,
+    sockets: &Sockets,
+    remote: SocketAddr,
+    file: Arc<Vec<u8>>,
+    progress: Arc<Progress>,
+    options: &MultipathOptions,
+) -> io::Result<Paths> {
+    for path in &options.paths {
+        // gm-quic validates the path before sending anything but probes on it
+        sockets.add_path(connection, path.local, path.remote.unwrap_or(remote))?;
+    }
+    echo_streams(connection, file, progress).await?;
+    Ok(Paths::new(sockets.traffic()))
+}
+
+/// Send the datagrams `options` ask for, echoing `file` over one stream per
+/// bar of `progress` meanwhile if `options.with_streams`.
+pub async fn send_datagrams(
+    connection: &Connection,
+    file: Arc<Vec<u8>>,
+    progress: Arc<Progress>,
+    options: &DatagramOptions,
+) -> io::Result<Datagrams> {
+    let reader = &connection.unreliable_reader()?;
+    let writer = connection.unreliable_writer().await?;
+    let datagrams = Datagrams::run(
+        options,
+        |datagram| writer.send_bytes(datagram),
+        move || future::poll_fn(move |cx| reader.poll_recv(cx)),
+    );
+    if !options.with_streams {
+        return datagrams.await;
+    }
+    let (datagrams, ()) = tokio::try_join!(datagrams, echo_streams(connection, file, progress))?;
+    Ok(datagrams)
+}
+
+/// Echo `payload` over one stream and wait for all of it to come back, which
+/// on a connection still doing its handshake goes as 0-RTT data if it can.
+pub async fn ping(connection: &Connection, payload: &[u8]) -> io::Result<()> {
+    let (_stream_id, (mut reader, mut writer)) = connection
+        .open_bi_stream()
+        .await?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "connection closed"))?;
+    writer.write_all(payload).await?;
+    writer.shutdown().await?;
+    let mut echoed = Vec::with_capacity(payload.len());
+    reader.read_to_end(&mut echoed).await?;
+    if echoed != payload {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "ping came back different",
+        ));
+    }
+    Ok(())
+}
+
+fn server_stream_unlimited_parameters() -> gm_quic::ServerParameters {
+    let mut params = gm_quic::ServerParameters::default();
+
+    params.set_initial_max_streams_bidi(100u32);
+    params.set_initial_max_streams_uni(100u32);
+    params.set_initial_max_data(1u32 << 20);
+    params.set_initial_max_stream_data_uni(1u32 << 20);
+    params.set_initial_max_stream_data_bidi_local(1u32 << 20);
+    params.set_initial_max_stream_data_bidi_remote(1u32 << 20);
+    // DATAGRAM frames of any size, see `crate::datagram`
+    params.set_max_datagram_frame_size(u16::MAX as u32);
+    // a connection ID of the peer for every path `client` opens
+    params.set_active_connection_id_limit(8u32);
+
+    params
+}
+
+fn client_stream_unlimited_parameters() -> gm_quic::ClientParameters {
+    let mut params = gm_quic::ClientParameters::default();
+
+    params.set_initial_max_streams_bidi(100u32);
+    params.set_initial_max_streams_uni(100u32);
+    params.set_initial_max_data(1u32 << 20);
+    params.set_initial_max_stream_data_uni(1u32 << 20);
+    params.set_initial_max_stream_data_bidi_local(1u32 << 20);
+    params.set_initial_max_stream_data_bidi_remote(1u32 << 20);
+    // DATAGRAM frames of any size, see `crate::datagram`
+    params.set_max_datagram_frame_size(u16::MAX as u32);
+    // a connection ID of the peer for every path `client` opens
+    params.set_active_connection_id_limit(8u32);
+    params.set_max_idle_timeout(Duration::from_secs(10));
+
+    params
+}
@@ -0,0 +1,183 @@
+//! Happy eyeballs (RFC 8305) for `client` and `h3-client`: all the addresses
+//! the server name resolves to are tried, alternating between IPv6 and IPv4.
+//! The next attempt starts when the one before failed or after the attempt
+//! delay, and the first connection to be of use wins, so an address that is
+//! blackholed only costs that delay.
+
+use std::{collections::BTreeMap, future::Future, io, net::SocketAddr, sync::Arc, time::Duration};
+
+use clap::Args;
+use futures::{FutureExt, StreamExt, stream::FuturesUnordered};
+use serde_json::Map;
+use tokio::time;
+
+use crate::{args, report::Report};
+
+#[derive(Args, Clone, Debug)]
+pub struct EyeballsOptions {
+    /// Try the next resolved address after this long unless an earlier one
+    /// connected or failed by then
+    #[arg(
+        long,
+        value_name = "MILLISECONDS",
+        default_value = "250",
+        value_parser = args::non_negative
+    )]
+    pub attempt_delay: f64,
+}
+
+impl EyeballsOptions {
+    /// Resolve `host`, an IP address in brackets too, and order the addresses
+    /// to try them in.
+    pub async fn resolve(&self, host: &str, port: u16) -> io::Result<Candidates> {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let mut addrs = vec![];
+        for addr in tokio::net::lookup_host((host, port)).await? {
+            if !addrs.contains(&addr) {
+                addrs.push(addr);
+            }
+        }
+        if addrs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "dns found no addresses",
+            ));
+        }
+        let candidates = Candidates::new(addrs, Duration::from_secs_f64(self.attempt_delay / 1e3));
+        tracing::info!(host, addrs = ?candidates.addrs(), "resolved");
+        Ok(candidates)
+    }
+
+    pub fn report(&self, report: &mut Report) {
+        report.set("attempt_delay_ms", self.attempt_delay);
+    }
+}
+
+/// The addresses of a server in the order to try them, and the name it goes
+/// by there if not the host of the URI.
+#[derive(Clone, Debug)]
+pub struct Candidates {
+    addrs: Arc<[SocketAddr]>,
+    attempt_delay: Duration,
+    server_name: Option<Arc<str>>,
+}
+
+impl From<SocketAddr> for Candidates {
+    fn from(addr: SocketAddr) -> Self {
+        Self::new(vec![addr], Duration::ZERO)
+    }
+}
+
+impl Candidates {
+    /// Interleave the address families of `addrs`, starting with the family
+    /// of the first one.
+    pub fn new(addrs: Vec<SocketAddr>, attempt_delay: Duration) -> Self {
+        let first_ipv6 = addrs.first().is_some_and(SocketAddr::is_ipv6);
+        let (preferred, other): (Vec<_>, Vec<_>) = addrs
+            .into_iter()
+            .partition(|addr| addr.is_ipv6() == first_ipv6);
+        let (mut preferred, mut other) = (preferred.into_iter(), other.into_iter());
+        let mut addrs = vec![];
+        loop {
+            match (preferred.next(), other.next()) {
+                (None, None) => break,
+                (first, second) => addrs.extend(first.into_iter().chain(second)),
+            }
+        }
+        Self {
+            addrs: addrs.into(),
+            attempt_delay,
+            server_name: None,
+        }
+    }
+
+    /// Present `server_name` in the handshakes rather than the host.
+    pub fn with_server_name(self, server_name: Option<&str>) -> Self {
+        Self {
+            server_name: server_name.map(Arc::from),
+            ..self
+        }
+    }
+
+    pub fn addrs(&self) -> &[SocketAddr] {
+        &self.addrs
+    }
+
+    /// The name to present in the handshakes with `host`.
+    pub fn server_name<'a>(&'a self, host: &'a str) -> &'a str {
+        self.server_name.as_deref().unwrap_or(host)
+    }
+
+    /// Race `connect` to the addresses, returning the first connection and
+    /// its address, or the last error if none connected.
+    pub async fn race<T, E, F>(
+        &self,
+        mut connect: impl FnMut(SocketAddr) -> F,
+    ) -> Result<(SocketAddr, T), E>
+    where
+        F: Future<Output = Result<T, E>>,
+        E: From<io::Error> + std::fmt::Debug,
+    {
+        let mut attempt = |addr| connect(addr).map(move |result| (addr, result));
+        let mut remaining = self.addrs.iter().copied().peekable();
+        let mut attempts = FuturesUnordered::new();
+        let mut error = None;
+        loop {
+            if attempts.is_empty() {
+                let Some(addr) = remaining.next() else {
+                    return Err(error.unwrap_or_else(|| {
+                        io::Error::new(io::ErrorKind::NotFound, "no addresses to connect to").into()
+                    }));
+                };
+                attempts.push(attempt(addr));
+            }
+            let attempt_delay = time::sleep(self.attempt_delay);
+            tokio::select! {
+                Some((addr, result)) = attempts.next() => match result {
+                    Ok(connection) => {
+                        tracing::info!(%addr, "connected");
+                        return Ok((addr, connection));
+                    }
+                    Err(failed) => {
+                        tracing::info!(%addr, error = ?failed, "connecting failed");
+                        error = Some(failed);
+                        if let Some(addr) = remaining.next() {
+                            attempts.push(attempt(addr));
+                        }
+                    }
+                },
+                _ = attempt_delay, if remaining.peek().is_some() => {
+                    let addr = remaining.next().unwrap();
+                    tracing::info!(%addr, "no connection yet, trying the next address");
+                    attempts.push(attempt(addr));
+                }
+            }
+        }
+    }
+
+    /// Sets `resolved_addrs`.
+    pub fn report(&self, report: &mut Report) {
+        let addrs = self.addrs.iter().map(ToString::to_string);
+        report.set("resolved_addrs", addrs.collect::<Vec<_>>());
+    }
+}
+
+/// How many connections went to each address.
+#[derive(Debug, Default)]
+pub struct Winners(BTreeMap<SocketAddr, usize>);
+
+impl Winners {
+    pub fn record(&mut self, addr: SocketAddr) {
+        *self.0.entry(addr).or_default() += 1;
+    }
+
+    /// Sets `connected_addrs` to the connections to each address.
+    pub fn report(&self, report: &mut Report) {
+        let addrs = self
+            .0
+            .iter()
+            .map(|(addr, count)| (addr.to_string(), (*count).into()))
+            .collect::<Map<_, _>>();
+        report.set("connected_addrs", addrs);
+    }
+}
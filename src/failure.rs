@@ -0,0 +1,315 @@
+//! Why the connections and requests of `h3-client` fail, counted by class
+//! rather than keeping the last error, and the timeouts and retries that
+//! keep a stuck or flaky server from hanging or sinking a run.
+
+use std::{collections::BTreeMap, fmt, future::Future, io, ops::RangeInclusive, time::Duration};
+
+use clap::Args;
+use h3::error::ErrorLevel;
+use tokio::time::{self, Instant, error::Elapsed};
+
+use crate::{args, fetch::Error, report::Report};
+
+/// The error codes of RFC 9114 that only end a request stream,
+/// H3_REQUEST_REJECTED to H3_VERSION_FALLBACK.
+const REQUEST_CODES: RangeInclusive<u64> = 0x10b..=0x110;
+
+/// Where doubling the retry backoff stops, unless it started above.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// What went wrong with a connection or a request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Failure {
+    /// The server couldn't be reached.
+    Connect,
+    /// The TLS or QUIC handshake failed.
+    Handshake,
+    /// The request's stream was reset, with the error code if there was one.
+    StreamReset(Option<u64>),
+    /// The connection closed under the request, with the error code if
+    /// there was one.
+    ConnectionClose(Option<u64>),
+    Timeout,
+    /// The response didn't have the expected status, any 2xx if none was
+    /// given.
+    Status(u16),
+    /// The body wasn't as long as expected or its Content-Length said.
+    Length,
+    /// The body wasn't the expected content.
+    Content,
+}
+
+impl Failure {
+    /// Why connecting failed with `error`: an I/O error of its own is the
+    /// server not being reached, anything else the handshake.
+    pub fn of_connect(error: &Error) -> Self {
+        match Self::find(&**error) {
+            Some(failure @ (Failure::Connect | Failure::Handshake | Failure::Timeout)) => failure,
+            None if error.is::<io::Error>() => Failure::Connect,
+            // a QUIC handshake ends in a connection close
+            _ => Failure::Handshake,
+        }
+    }
+
+    /// Why a request failed with `error`, a connection that went away unless
+    /// the error tells otherwise.
+    pub fn of_request(error: &Error) -> Self {
+        Self::find(&**error).unwrap_or(Failure::ConnectionClose(None))
+    }
+
+    /// The class `error` or one of its sources tell.
+    fn find(error: &(dyn std::error::Error + 'static)) -> Option<Self> {
+        let mut next = Some(error);
+        while let Some(error) = next {
+            if let Some(classified) = error.downcast_ref::<Classified>() {
+                return Some(classified.failure);
+            }
+            if error.is::<Elapsed>() {
+                return Some(Failure::Timeout);
+            }
+            if error.is::<rustls::Error>() {
+                return Some(Failure::Handshake);
+            }
+            if let Some(error) = error.downcast_ref::<h3::Error>() {
+                let code = error.try_get_code().map(|code| code.value());
+                // h3 reports the code of a reset stream at the connection
+                // level as well, the request codes tell it apart
+                let reset = error.get_error_level() == ErrorLevel::StreamError
+                    || code.is_some_and(|code| REQUEST_CODES.contains(&code));
+                return Some(if reset {
+                    Failure::StreamReset(code)
+                } else {
+                    Failure::ConnectionClose(code)
+                });
+            }
+            if let Some(error) = error.downcast_ref::<h2::Error>() {
+                let code = error.reason().map(|reason| u32::from(reason) as u64);
+                if error.is_reset() {
+                    return Some(Failure::StreamReset(code));
+                }
+                if error.is_go_away() {
+                    return Some(Failure::ConnectionClose(code));
+                }
+            }
+            if error
+                .downcast_ref::<hyper::Error>()
+                .is_some_and(hyper::Error::is_timeout)
+            {
+                return Some(Failure::Timeout);
+            }
+            if let Some(error) = error.downcast_ref::<io::Error>() {
+                if error.kind() == io::ErrorKind::TimedOut {
+                    return Some(Failure::Timeout);
+                }
+                // tokio-rustls hands TLS errors over in an I/O error
+                if let Some(inner) = error.get_ref() {
+                    next = Some(inner as &(dyn std::error::Error + 'static));
+                    continue;
+                }
+            }
+            next = error.source();
+        }
+        None
+    }
+
+    /// `error`, known to be of this class.
+    pub fn error(self, error: impl Into<Error>) -> Error {
+        Box::new(Classified {
+            failure: self,
+            error: error.into(),
+        })
+    }
+
+    /// Whether trying again may help. A response that wasn't as expected
+    /// would come again and a closed connection takes no more requests.
+    fn retryable(self) -> bool {
+        !matches!(
+            self,
+            Failure::Status(_) | Failure::Length | Failure::Content | Failure::ConnectionClose(_)
+        )
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Connect => write!(f, "connect"),
+            Failure::Handshake => write!(f, "handshake"),
+            Failure::StreamReset(Some(code)) => write!(f, "stream reset {code:#x}"),
+            Failure::StreamReset(None) => write!(f, "stream reset"),
+            Failure::ConnectionClose(Some(code)) => write!(f, "connection close {code:#x}"),
+            Failure::ConnectionClose(None) => write!(f, "connection close"),
+            Failure::Timeout => write!(f, "timeout"),
+            Failure::Status(status) => write!(f, "status {status}"),
+            Failure::Length => write!(f, "length mismatch"),
+            Failure::Content => write!(f, "content mismatch"),
+        }
+    }
+}
+
+/// An error whose class is known where it happens.
+#[derive(Debug)]
+struct Classified {
+    failure: Failure,
+    error: Error,
+}
+
+impl fmt::Display for Classified {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.failure, self.error)
+    }
+}
+
+impl std::error::Error for Classified {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&*self.error)
+    }
+}
+
+/// Every failed attempt by class, and how many were tried again.
+#[derive(Debug, Default)]
+pub struct Failures {
+    pub by_class: BTreeMap<Failure, usize>,
+    /// Failed attempts that were tried again.
+    pub retries: usize,
+}
+
+impl Failures {
+    pub fn record(&mut self, failure: Failure, retried: bool) {
+        *self.by_class.entry(failure).or_default() += 1;
+        self.retries += retried as usize;
+    }
+
+    pub fn merge(&mut self, other: Failures) {
+        for (failure, count) in other.by_class {
+            *self.by_class.entry(failure).or_default() += count;
+        }
+        self.retries += other.retries;
+    }
+
+    /// Sets `failures`, the failed attempts by class, and `retries`.
+    pub fn report(&self, report: &mut Report) {
+        report
+            .set(
+                "failures",
+                self.by_class
+                    .iter()
+                    .map(|(failure, count)| (failure.to_string(), (*count).into()))
+                    .collect::<serde_json::Map<_, _>>(),
+            )
+            .set("retries", self.retries);
+    }
+}
+
+/// A connection that didn't get to send requests, and what its attempts ran
+/// into.
+#[derive(Debug)]
+pub struct Failed {
+    pub error: Error,
+    pub failures: Failures,
+}
+
+impl<E: Into<Error>> From<E> for Failed {
+    fn from(error: E) -> Self {
+        Self {
+            error: error.into(),
+            failures: Failures::default(),
+        }
+    }
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct RetryOptions {
+    /// Give up on a request after this long
+    #[arg(long, value_name = "SECONDS", value_parser = args::positive)]
+    pub request_timeout: Option<f64>,
+    /// Give up on a connection this long after it started connecting, the
+    /// requests still going then time out
+    #[arg(long, value_name = "SECONDS", value_parser = args::positive)]
+    pub connection_timeout: Option<f64>,
+    /// Try connecting or a request again this many times. Requests aren't
+    /// after an unexpected response or when their connection closed
+    #[arg(long, default_value = "0")]
+    pub retries: usize,
+    /// Wait this long before the first retry, twice as long before every
+    /// further one up to a minute
+    #[arg(
+        long,
+        value_name = "MILLISECONDS",
+        default_value = "100",
+        value_parser = args::non_negative
+    )]
+    pub retry_backoff: f64,
+}
+
+impl Default for RetryOptions {
+    fn default() -> Self {
+        Self {
+            request_timeout: None,
+            connection_timeout: None,
+            retries: 0,
+            retry_backoff: 100.0,
+        }
+    }
+}
+
+impl RetryOptions {
+    /// When a connection starting now has to be done.
+    pub fn deadline(&self) -> Option<Instant> {
+        let timeout = Duration::from_secs_f64(self.connection_timeout?);
+        Some(Instant::now() + timeout)
+    }
+
+    /// Run `attempt` until it succeeds, fails in a way that won't go away
+    /// or the retries run out, recording every failure as `classify` puts
+    /// it. An attempt times out with the request timeout if
+    /// `request`, and at `deadline`.
+    pub async fn retry<T, F>(
+        &self,
+        request: bool,
+        deadline: Option<Instant>,
+        classify: impl Fn(&Error) -> Failure,
+        mut record: impl FnMut(Failure, bool),
+        mut attempt: impl FnMut() -> F,
+    ) -> Result<T, Error>
+    where
+        F: Future<Output = Result<T, Error>>,
+    {
+        let mut backoff = Duration::from_secs_f64(self.retry_backoff / 1e3);
+        let mut retries = 0;
+        loop {
+            let timeout = self
+                .request_timeout
+                .filter(|_| request)
+                .map(|timeout| Instant::now() + Duration::from_secs_f64(timeout));
+            let result = match timeout.into_iter().chain(deadline).min() {
+                Some(until) => time::timeout_at(until, attempt())
+                    .await
+                    .unwrap_or_else(|elapsed| Err(elapsed.into())),
+                None => attempt().await,
+            };
+            let error = match result {
+                Ok(done) => return Ok(done),
+                Err(error) => error,
+            };
+            let failure = classify(&error);
+            let retry = retries < self.retries && failure.retryable();
+            record(failure, retry);
+            if !retry {
+                return Err(error);
+            }
+            tracing::info!(%error, retries, "trying again");
+            time::sleep(backoff).await;
+            backoff = backoff.saturating_mul(2).min(MAX_BACKOFF.max(backoff));
+            retries += 1;
+        }
+    }
+
+    pub fn report(&self, report: &mut Report) {
+        report
+            .set("request_timeout_s", self.request_timeout)
+            .set("connection_timeout_s", self.connection_timeout)
+            .set("max_retries", self.retries)
+            .set("retry_backoff_ms", self.retry_backoff);
+    }
+}
@@ -0,0 +1,703 @@
+//! The client side of [`crate::files`]: `h3-client` drives a file server with
+//! concurrent GETs over HTTP/3, HTTP/2 or HTTP/1.1.
+
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, OnceLock},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use bytes::{Buf, Bytes};
+use clap::ValueEnum;
+use futures::FutureExt;
+use http::{Request, Response, StatusCode, Uri, header};
+use http_body_util::{BodyExt, Empty};
+use hyper::client::conn::{http1, http2};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use rustls::{ClientConnection, RootCertStore, pki_types::ServerName};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::TcpStream,
+    sync::Mutex,
+    task::JoinSet,
+};
+use tokio_rustls::{TlsConnector, client::TlsStream};
+use tracing::{Instrument, info_span};
+
+use crate::{
+    congestion::CongestionOptions,
+    eyeballs::Candidates,
+    failure::{Failed, Failure, Failures, RetryOptions},
+    priority::{Finish, Mix, PRIORITY},
+    report::{self, Report},
+    tls::{self, Identity, ResumptionOptions},
+    workload::Workload,
+};
+
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// How requests reach the server. HTTP/1.1 can't multiplex, its requests queue
+/// up on their connection and go out one after another.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    H3,
+    H2,
+    Http1,
+}
+
+#[derive(Clone)]
+pub enum Connector {
+    Quic(Arc<gm_quic::QuicClient>),
+    Tls(TlsConnector, Protocol),
+}
+
+impl Connector {
+    /// `congestion` is only used by h3. `identity` is presented if the server
+    /// asks for a certificate. Clones share the session tickets `resumption`
+    /// keeps, h3 refuses to keep any, see [`ResumptionOptions::check_quic`].
+    pub fn new(
+        protocol: Protocol,
+        roots: RootCertStore,
+        identity: Option<&Identity>,
+        resumption: &ResumptionOptions,
+        congestion: &CongestionOptions,
+    ) -> Result<Self, Error> {
+        let connector = match protocol {
+            Protocol::H3 => {
+                congestion.check()?;
+                resumption.check_quic()?;
+                let builder = gm_quic::QuicClient::builder().with_root_certificates(roots);
+                let builder = match identity {
+                    Some(identity) => {
+                        builder.with_cert(identity.cert_chain.clone(), identity.key.clone_key())
+                    }
+                    None => builder.without_cert(),
+                };
+                Connector::Quic(Arc::new(
+                    builder
+                        .with_parameters(client_parameters())
+                        .with_alpns([b"h3" as &[u8], b"hq-29"])
+                        .enable_sslkeylog()
+                        .build(),
+                ))
+            }
+            protocol => {
+                let provider = Arc::new(rustls::crypto::ring::default_provider());
+                let builder = rustls::ClientConfig::builder_with_provider(provider)
+                    .with_protocol_versions(&[&rustls::version::TLS13])?
+                    .with_root_certificates(roots);
+                let mut config = tls::with_identity(builder, identity)?;
+                config.alpn_protocols = match protocol {
+                    Protocol::H2 => vec![b"h2".to_vec()],
+                    _ => vec![b"http/1.1".to_vec()],
+                };
+                config.key_log = Arc::new(rustls::KeyLogFile::new());
+                resumption.apply(&mut config);
+                let connector =
+                    TlsConnector::from(Arc::new(config)).early_data(resumption.early_data);
+                Connector::Tls(connector, protocol)
+            }
+        };
+        Ok(connector)
+    }
+
+    /// Connect to `host` at `addr`, returning once requests can be sent. With
+    /// early data that is before the handshake completed, the first request
+    /// goes along with it.
+    pub async fn connect(
+        &self,
+        addr: SocketAddr,
+        host: &str,
+    ) -> Result<(Requester, Handshake), Error> {
+        match self {
+            Connector::Quic(client) => {
+                let quic = client.connect(host, addr)?;
+                let connection = h3_shim::QuicConnection::new(quic.clone()).await;
+                let (mut conn, send_request) = h3::client::new(connection).await?;
+                tracing::info!("conenction established");
+
+                let driver = async move {
+                    core::future::poll_fn(|cx| conn.poll_close(cx))
+                        .await
+                        .map_err(Error::from)
+                };
+                let _driver = tokio::spawn(driver);
+                Ok((Requester::H3(send_request, quic), Handshake::full()))
+            }
+            Connector::Tls(connector, protocol) => {
+                let stream = TcpStream::connect(addr).await?;
+                let stream = connector
+                    .connect(ServerName::try_from(host.to_owned())?, stream)
+                    .await?;
+                let handshake = Handshake::default();
+                handshake.observe(stream.get_ref().1);
+                let io = TokioIo::new(Observed {
+                    stream,
+                    handshake: handshake.clone(),
+                });
+                tracing::info!("conenction established");
+
+                match protocol {
+                    Protocol::H2 => {
+                        let (send_request, conn) =
+                            http2::handshake::<_, _, Empty<Bytes>>(TokioExecutor::new(), io)
+                                .await?;
+                        let _driver = tokio::spawn(conn);
+                        Ok((Requester::H2(send_request), handshake))
+                    }
+                    _ => {
+                        let (send_request, conn) = http1::handshake::<_, Empty<Bytes>>(io).await?;
+                        let _driver = tokio::spawn(conn);
+                        let requester = Requester::Http1(Arc::new(Mutex::new(send_request)));
+                        Ok((requester, handshake))
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// How the handshake of a connection went.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HandshakeKind {
+    Full,
+    Resumed,
+    /// Resumed, and the server took the early data
+    EarlyData,
+}
+
+impl HandshakeKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            HandshakeKind::Full => "full",
+            HandshakeKind::Resumed => "resumed",
+            HandshakeKind::EarlyData => "early_data",
+        }
+    }
+}
+
+/// Tells how the handshake of a connection went once it completed, which with
+/// early data is only after the first response started to arrive. gm-quic
+/// clients never resume, so h3 handshakes are full from the start.
+#[derive(Clone, Debug, Default)]
+pub struct Handshake(Arc<OnceLock<HandshakeKind>>);
+
+impl Handshake {
+    fn full() -> Self {
+        Self(Arc::new(OnceLock::from(HandshakeKind::Full)))
+    }
+
+    pub fn kind(&self) -> Option<HandshakeKind> {
+        self.0.get().copied()
+    }
+
+    fn observe(&self, session: &ClientConnection) {
+        if session.is_handshaking() {
+            return;
+        }
+        self.0.get_or_init(|| match session.handshake_kind() {
+            Some(rustls::HandshakeKind::Resumed) if session.is_early_data_accepted() => {
+                HandshakeKind::EarlyData
+            }
+            Some(rustls::HandshakeKind::Resumed) => HandshakeKind::Resumed,
+            _ => HandshakeKind::Full,
+        });
+    }
+}
+
+/// A client connection noting in its [`Handshake`] how the handshake went, as
+/// soon as that is known.
+struct Observed {
+    stream: TlsStream<TcpStream>,
+    handshake: Handshake,
+}
+
+impl AsyncRead for Observed {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let read = Pin::new(&mut this.stream).poll_read(cx, buf);
+        this.handshake.observe(this.stream.get_ref().1);
+        read
+    }
+}
+
+impl AsyncWrite for Observed {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().stream).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().stream).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+}
+
+/// Times of connections, by how their handshakes went.
+#[derive(Debug, Default)]
+pub struct ByHandshake(BTreeMap<Option<HandshakeKind>, Vec<f64>>);
+
+impl ByHandshake {
+    pub fn record(&mut self, kind: Option<HandshakeKind>, secs: f64) {
+        self.0.entry(kind).or_default().push(secs);
+    }
+
+    /// Sets `{kind}_connections` and the percentiles of `{kind}_{name}` in
+    /// milliseconds for every kind there was, and for all but full ones
+    /// `{kind}_saved_ms`, how much faster their median was than that of the
+    /// full ones. Connections under the same load compare best, `baseline`
+    /// seconds stand in if all of them resumed.
+    pub fn report(&self, report: &mut Report, name: &str, baseline: Option<f64>) {
+        let median = |secs: &[f64]| {
+            let mut sorted = secs.to_vec();
+            sorted.sort_by(f64::total_cmp);
+            report::percentile(&sorted, 0.5)
+        };
+        let full = self
+            .0
+            .get(&Some(HandshakeKind::Full))
+            .and_then(|secs| median(secs))
+            .or(baseline);
+        for (kind, secs) in &self.0 {
+            let kind = kind.map_or("unknown", HandshakeKind::name);
+            let millis = secs.iter().map(|secs| secs * 1e3).collect::<Vec<_>>();
+            report
+                .set(&format!("{kind}_connections"), secs.len())
+                .set_percentiles(&format!("{kind}_{name}"), "ms", &millis);
+            if kind == "full" {
+                continue;
+            }
+            if let (Some(full), Some(median)) = (full, median(secs)) {
+                report.set(&format!("{kind}_saved_ms"), (full - median) * 1e3);
+            }
+        }
+    }
+}
+
+/// [`Requester::send`] over h3, with h3's error.
+async fn send_h3(
+    send_request: &mut h3::client::SendRequest<H3OpenStreams, Bytes>,
+    request: Request<()>,
+    mut on_data: impl FnMut(&[u8]),
+) -> Result<Response<()>, h3::Error> {
+    let mut request_stream = send_request.send_request(request).await?;
+    request_stream.finish().await?;
+    let resp = request_stream.recv_response().await?;
+    while let Some(mut chunk) = request_stream.recv_data().await? {
+        while chunk.has_remaining() {
+            let bytes = chunk.chunk();
+            on_data(bytes);
+            let len = bytes.len();
+            chunk.advance(len);
+        }
+    }
+    Ok(resp)
+}
+
+type H3OpenStreams = <h3_shim::QuicConnection as h3::quic::Connection<Bytes>>::OpenStreams;
+
+/// Sends requests over one connection, clones share it.
+#[derive(Clone)]
+pub enum Requester {
+    /// With the QUIC connection underneath, to tell what failed a request.
+    H3(
+        h3::client::SendRequest<H3OpenStreams, Bytes>,
+        Arc<gm_quic::Connection>,
+    ),
+    H2(http2::SendRequest<Empty<Bytes>>),
+    Http1(Arc<Mutex<http1::SendRequest<Empty<Bytes>>>>),
+}
+
+impl Requester {
+    /// GET `uri`, handing every chunk of the body to `on_data` as it arrives.
+    pub async fn get(
+        &mut self,
+        uri: &Uri,
+        on_data: impl FnMut(&[u8]),
+    ) -> Result<StatusCode, Error> {
+        let request = Request::get(uri.clone()).body(())?;
+        Ok(self.send(request, on_data).await?.status())
+    }
+
+    /// Send `request`, handing every chunk of the response body to `on_data`
+    /// as it arrives, and return the head of the response.
+    pub async fn send(
+        &mut self,
+        request: Request<()>,
+        on_data: impl FnMut(&[u8]),
+    ) -> Result<Response<()>, Error> {
+        match self {
+            Requester::H3(send_request, quic) => {
+                send_h3(send_request, request, on_data)
+                    .await
+                    .map_err(|error| match error.try_get_code() {
+                        // h3-shim 0.2.2 drops the codes of resets and closes
+                        // alike, so a reset is known by the connection outliving it
+                        None if quic.is_active() => Failure::StreamReset(None).error(error),
+                        _ => error.into(),
+                    })
+            }
+            Requester::H2(send_request) => {
+                let request = request.map(|()| Empty::new());
+                let resp = send_request.send_request(request).await?;
+                let (parts, body) = resp.into_parts();
+                read_body(body, on_data).await?;
+                Ok(Response::from_parts(parts, ()))
+            }
+            Requester::Http1(send_request) => {
+                // HTTP/1.1 wants the origin form and a Host header
+                let (mut parts, ()) = request.into_parts();
+                let host = parts.uri.authority().map_or("", |auth| auth.as_str());
+                if !parts.headers.contains_key(header::HOST) {
+                    parts.headers.insert(header::HOST, host.parse()?);
+                }
+                parts.uri = parts
+                    .uri
+                    .path_and_query()
+                    .map_or("/", |path| path.as_str())
+                    .parse()?;
+                let request = Request::from_parts(parts, Empty::new());
+                // the whole response has to be read before the next request
+                let mut send_request = send_request.lock().await;
+                send_request.ready().await?;
+                let resp = send_request.send_request(request).await?;
+                let (parts, body) = resp.into_parts();
+                read_body(body, on_data).await?;
+                Ok(Response::from_parts(parts, ()))
+            }
+        }
+    }
+}
+
+async fn read_body(
+    mut body: hyper::body::Incoming,
+    mut on_data: impl FnMut(&[u8]),
+) -> Result<(), Error> {
+    while let Some(frame) = body.frame().await {
+        if let Some(chunk) = frame?.data_ref() {
+            on_data(chunk);
+        }
+    }
+    Ok(())
+}
+
+fn client_parameters() -> gm_quic::ClientParameters {
+    let mut params = gm_quic::ClientParameters::default();
+
+    params.set_initial_max_streams_bidi(100u32);
+    params.set_initial_max_streams_uni(100u32);
+    params.set_initial_max_data(1u32 << 20);
+    params.set_initial_max_stream_data_uni(1u32 << 20);
+    params.set_initial_max_stream_data_bidi_local(1u32 << 20);
+    params.set_initial_max_stream_data_bidi_remote(1u32 << 20);
+    // WebTransport datagrams of any size, see `crate::webtransport`
+    params.set_max_datagram_frame_size(u16::MAX as u32);
+    params.set_max_idle_timeout(Duration::from_secs(10));
+
+    params
+}
+
+/// What the requests of one connection got.
+pub struct Fetched {
+    /// Requests that succeeded.
+    pub requests: usize,
+    /// Body bytes received.
+    pub bytes: u64,
+    /// Seconds until requests could be sent, the handshake for h2 and http1.
+    pub connect_time: f64,
+    /// Seconds from starting to connect until the first request completed,
+    /// what early data shortens.
+    pub first_response: Option<f64>,
+    pub handshake: Option<HandshakeKind>,
+    /// The address that won the race.
+    pub addr: SocketAddr,
+    /// When the requests that succeeded started and finished.
+    pub finishes: Vec<Finish>,
+    /// What the failed attempts to connect and request ran into.
+    pub failures: Failures,
+}
+
+/// Connect once to the first of `candidates` to answer and make `reqs`
+/// requests of `workload` concurrently, the ones of connection `idx`, and
+/// what `mix` adds. Connecting and every request are timed out and tried
+/// again as `retry` says, a response that isn't what its target expects
+/// fails its request. The URI of the mix is only held to the expected status.
+#[allow(clippy::too_many_arguments)]
+pub async fn for_each_connection(
+    connector: Connector,
+    candidates: Candidates,
+    workload: Workload,
+    idx: usize,
+    reqs: usize,
+    mix: Mix,
+    retry: RetryOptions,
+    total_pb: ProgressBar,
+    pbs: MultiProgress,
+) -> Result<Fetched, Failed> {
+    let conn_pb = pbs.insert_after(
+        &total_pb,
+        ProgressBar::new(0)
+            .with_message("connecting")
+            .with_style(ProgressStyle::with_template("{prefix} {spinner} {msg}")?),
+    );
+    let host = candidates
+        .server_name(workload.uri().host().unwrap_or_default())
+        .to_owned();
+    let connect_start = Instant::now();
+    let deadline = retry.deadline();
+    let mut failures = Failures::default();
+    let connected = retry
+        .retry(
+            false,
+            deadline,
+            Failure::of_connect,
+            |failure, retried| failures.record(failure, retried),
+            || candidates.race(|addr| connector.connect(addr, &host)),
+        )
+        .await;
+    let (addr, (requester, handshake)) = match connected {
+        Ok(connected) => connected,
+        Err(error) => return Err(Failed { error, failures }),
+    };
+    let connect_time = connect_start.elapsed().as_secs_f64();
+
+    let first_response = Arc::new(OnceLock::new());
+    let finishes = Arc::new(std::sync::Mutex::new(vec![]));
+    let failures = Arc::new(std::sync::Mutex::new(failures));
+    let urgent_reqs = mix.urgent.as_ref().map_or(0, |urgent| urgent.reqs);
+    let urgent_expect = workload.target(0).expect.status_only();
+    let ready = Instant::now();
+    let (requests, bytes) = for_each_request(reqs + urgent_reqs, total_pb, conn_pb, {
+        let first_response = first_response.clone();
+        let finishes = finishes.clone();
+        let failures = failures.clone();
+        move |req_id| {
+            let requester = requester.clone();
+            let (uri, priority, after, expect, class) = match &mix.urgent {
+                Some(urgent) if req_id >= reqs => (
+                    urgent.uri.clone(),
+                    Some(urgent.priority),
+                    urgent.after,
+                    urgent_expect.clone(),
+                    None,
+                ),
+                _ => {
+                    let target = workload.target(idx * reqs + req_id);
+                    let (uri, expect) = (target.uri.clone(), target.expect.clone());
+                    (
+                        uri,
+                        mix.priority,
+                        Duration::ZERO,
+                        expect,
+                        Some(target.class),
+                    )
+                }
+            };
+            let first_response = first_response.clone();
+            let finishes = finishes.clone();
+            let failures = failures.clone();
+            let retry = retry.clone();
+            let workload = workload.clone();
+            async move {
+                tokio::time::sleep(after).await;
+                let started = ready.elapsed().as_secs_f64();
+                let bytes = retry
+                    .retry(
+                        true,
+                        deadline,
+                        Failure::of_request,
+                        |failure, retried| failures.lock().unwrap().record(failure, retried),
+                        || {
+                            let mut requester = requester.clone();
+                            let request = workload.request(uri.clone(), &expect);
+                            async move {
+                                let (mut request, expect) = request?;
+                                if let Some(priority) = priority {
+                                    request.headers_mut().insert(&PRIORITY, priority.header());
+                                }
+                                let mut body = expect.body();
+                                let response =
+                                    requester.send(request, |chunk| body.chunk(chunk)).await?;
+                                expect.check(&response, body)
+                            }
+                        },
+                    )
+                    .await?;
+                first_response.get_or_init(|| connect_start.elapsed().as_secs_f64());
+                finishes.lock().unwrap().push(Finish {
+                    urgent: req_id >= reqs,
+                    started,
+                    finished: ready.elapsed().as_secs_f64(),
+                    bytes,
+                    class,
+                });
+                Ok(bytes)
+            }
+        }
+    })
+    .await
+    .map_err(|error| Failed {
+        error,
+        failures: std::mem::take(&mut *failures.lock().unwrap()),
+    })?;
+    let finishes = std::mem::take(&mut *finishes.lock().unwrap());
+    let failures = std::mem::take(&mut *failures.lock().unwrap());
+    Ok(Fetched {
+        requests,
+        bytes,
+        connect_time,
+        first_response: first_response.get().copied(),
+        handshake: handshake.kind(),
+        addr,
+        finishes,
+        failures,
+    })
+}
+
+/// How a single connection went.
+#[derive(Debug)]
+pub struct Probe {
+    /// Seconds until requests could be sent.
+    pub connect_time: f64,
+    /// Seconds from starting to connect until the response was read, if there
+    /// was a request.
+    pub first_response: Option<f64>,
+    pub handshake: Option<HandshakeKind>,
+    /// The address that won the race.
+    pub addr: SocketAddr,
+    /// Body bytes of the response.
+    pub bytes: u64,
+}
+
+impl Probe {
+    /// How long the connection took to be of use.
+    pub fn time(&self) -> f64 {
+        self.first_response.unwrap_or(self.connect_time)
+    }
+}
+
+/// Connect to the host of `uri` at the first of `candidates` to answer, GET
+/// it if `request` and close. A session ticket the server sends is only taken
+/// in when reading a response. Errors are classified, so that
+/// [`Failure::of_request`] tells whether connecting or the request failed.
+pub async fn probe(
+    connector: &Connector,
+    candidates: &Candidates,
+    uri: &Uri,
+    request: bool,
+) -> Result<Probe, Error> {
+    let start = Instant::now();
+    let host = candidates.server_name(uri.host().unwrap_or_default());
+    let (addr, (mut requester, handshake)) = candidates
+        .race(|addr| connector.connect(addr, host))
+        .await
+        .map_err(|error| Failure::of_connect(&error).error(error))?;
+    let connect_time = start.elapsed().as_secs_f64();
+
+    let mut bytes = 0;
+    let mut first_response = None;
+    if request {
+        let status = requester
+            .get(uri, |chunk| bytes += chunk.len() as u64)
+            .await
+            .map_err(|error| Failure::of_request(&error).error(error))?;
+        if !status.is_success() {
+            return Err(Failure::Status(status.as_u16()).error(format!("status {status}")));
+        }
+        first_response = Some(start.elapsed().as_secs_f64());
+    }
+    // dropping the last handle of a connection closes it
+    Ok(Probe {
+        connect_time,
+        first_response,
+        handshake: handshake.kind(),
+        addr,
+        bytes,
+    })
+}
+
+/// Issue `reqs` concurrent requests made by `request` from their index,
+/// returning how many succeeded and the body bytes received.
+async fn for_each_request<F, R>(
+    reqs: usize,
+    total_pb: ProgressBar,
+    conn_pb: ProgressBar,
+    request: F,
+) -> Result<(usize, u64), Error>
+where
+    F: Fn(usize) -> R,
+    R: Future<Output = Result<u64, Error>> + Send + 'static,
+{
+    total_pb.inc_length(reqs as u64);
+    conn_pb.set_style(ProgressStyle::with_template(
+        "{prefix} {wide_bar} {pos}/{len}",
+    )?);
+
+    let mut requests = JoinSet::new();
+    for req_id in 0..reqs {
+        let conn_pb = conn_pb.clone();
+        conn_pb.inc_length(1);
+        requests.spawn(
+            request(req_id)
+                .inspect(move |result| match result {
+                    Ok(_) => conn_pb.inc(1),
+                    Err(_) => conn_pb.dec_length(1),
+                })
+                .instrument(info_span!("request", req_id)),
+        );
+    }
+
+    let mut error = None;
+
+    let mut success_queries = 0;
+    let mut received = 0;
+    while let Some(res) = requests.join_next().await {
+        match res {
+            Ok(Ok(bytes)) => {
+                success_queries += 1;
+                received += bytes;
+                total_pb.inc(1);
+            }
+            Ok(Err(err)) => {
+                total_pb.dec_length(1);
+                error = Some(err);
+            }
+            Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+            Err(err) => panic!("{err}"),
+        }
+    }
+    conn_pb.finish_and_clear();
+    if success_queries != 0 {
+        Ok((success_queries, received))
+    } else {
+        Err(error.unwrap())
+    }
+}
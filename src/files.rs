@@ -0,0 +1,481 @@
+//! The file serving of `h3-server` and `h2-server`: a GET of a path under the
+//! root directory gets the file, anything with `..` in it or that can't be
+//! opened gets a 404. `/gen/<bytes>[/<seed>]` gets that many bytes generated
+//! from the seed, 0 if none, without a file, and a 400 above the servers'
+//! `--gen-max`.
+
+use std::{
+    convert::Infallible,
+    future::Future,
+    io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{Arc, atomic::Ordering},
+    task::{Context, Poll},
+};
+
+use bytes::{Bytes, BytesMut};
+use gm_quic::{Connection, QuicServer};
+use h3::{error::ErrorLevel, quic::BidiStream, server::RequestStream};
+use http::{Request, Response, StatusCode, header};
+use http_body_util::{BodyExt, Empty, StreamBody, combinators::BoxBody};
+use hyper::body::{Frame, Incoming};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto,
+};
+use rustls::{
+    pki_types::{CertificateDer, PrivateKeyDer},
+    server::danger::ClientCertVerifier,
+};
+use tokio::{
+    fs::File,
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf},
+    net::{TcpListener, TcpStream},
+    task::JoinSet,
+};
+use tokio_rustls::{TlsAcceptor, server};
+use tracing::{error, info};
+
+use crate::{
+    congestion::CongestionOptions,
+    priority::{Priority, Scheduler, Turn},
+    report::ServerStats,
+    tls::{self, TicketOptions, peer_identity},
+    validate::Generated,
+    webtransport,
+};
+
+static ALPN: &[u8] = b"h3";
+
+/// What a response body is read from.
+pub type Content = Box<dyn AsyncRead + Send + Sync + Unpin>;
+
+/// The content to answer a request for `path` with and its length, what
+/// the response's Content-Length says. Generated bodies are `gen_max` bytes
+/// at most.
+pub async fn open(root: &Path, gen_max: u64, path: &str) -> (StatusCode, Option<(Content, u64)>) {
+    if path.contains("..") {
+        return (StatusCode::NOT_FOUND, None);
+    }
+    if let Some(generate) = path.strip_prefix("/gen/") {
+        let (len, seed) = generate.split_once('/').unwrap_or((generate, "0"));
+        return match (len.parse(), seed.parse()) {
+            (Ok(len), Ok(_)) if len > gen_max => (StatusCode::BAD_REQUEST, None),
+            (Ok(len), Ok(seed)) => (
+                StatusCode::OK,
+                Some((Box::new(Generated::new(seed, len)), len)),
+            ),
+            _ => (StatusCode::NOT_FOUND, None),
+        };
+    }
+    let to_serve = root.join(path.strip_prefix('/').unwrap_or(""));
+    let opened = async {
+        let file = File::open(&to_serve).await?;
+        let len = file.metadata().await?.len();
+        io::Result::Ok((file, len))
+    };
+    match opened.await {
+        Ok((file, len)) => (StatusCode::OK, Some((Box::new(file), len))),
+        Err(e) => {
+            error!("failed to open: \"{}\": {}", to_serve.to_string_lossy(), e);
+            (StatusCode::NOT_FOUND, None)
+        }
+    }
+}
+
+/// `cert` and `key` are PEM, clients are asked for a certificate if
+/// `client_verifier` is given and handed session tickets as `tickets` says.
+pub fn listen_h3(
+    listen: &[SocketAddr],
+    cert: &[u8],
+    key: &[u8],
+    client_verifier: Option<Arc<dyn ClientCertVerifier>>,
+    tickets: &TicketOptions,
+    congestion: &CongestionOptions,
+) -> io::Result<Arc<QuicServer>> {
+    congestion.check()?;
+    let config = tls::quic_server_config(cert, key, client_verifier, tickets)?;
+    gm_quic::QuicServer::builder_with_tls(config)
+        .with_parameters(server_parameters())
+        .with_alpns([ALPN.to_vec()])
+        .listen(listen)
+}
+
+/// Serve the HTTP/3 connections `server` accepts until `shutdown` resolves,
+/// sending the most urgent responses of each first if `priorities` and
+/// taking WebTransport sessions if `webtransport`. Connections still open
+/// then are aborted.
+pub async fn serve_h3(
+    server: &QuicServer,
+    root: Arc<PathBuf>,
+    gen_max: u64,
+    priorities: bool,
+    webtransport: bool,
+    stats: Arc<ServerStats>,
+    shutdown: impl Future<Output = ()>,
+) {
+    let mut builder = h3::server::builder();
+    if webtransport {
+        builder
+            .enable_webtransport(true)
+            .enable_extended_connect(true)
+            .enable_datagram(true)
+            .max_webtransport_sessions(1);
+    }
+    let mut connections = JoinSet::new();
+    let accept = async {
+        while let Ok((new_conn, _pathway)) = server.accept().await {
+            let quic = new_conn.clone();
+            let h3_conn = match builder
+                .build(h3_shim::QuicConnection::new(new_conn).await)
+                .await
+            {
+                Ok(h3_conn) => {
+                    info!("new connection established");
+                    h3_conn
+                }
+                Err(error) => {
+                    tracing::error!("failed to establish h3 connection: {}", error);
+                    continue;
+                }
+            };
+            stats.connections.fetch_add(1, Ordering::Relaxed);
+            let scheduler = priorities.then(Scheduler::default);
+            connections.spawn(handle_connection(
+                root.clone(),
+                gen_max,
+                h3_conn,
+                quic,
+                scheduler,
+                webtransport,
+                stats.clone(),
+            ));
+            while connections.try_join_next().is_some() {}
+        }
+    };
+
+    tokio::select! {
+        _ = accept => {},
+        _ = shutdown => {},
+    }
+}
+
+fn server_parameters() -> gm_quic::ServerParameters {
+    let mut params = gm_quic::ServerParameters::default();
+
+    params.set_initial_max_streams_bidi(100u32);
+    params.set_initial_max_streams_uni(100u32);
+    params.set_initial_max_data(1u32 << 20);
+    params.set_initial_max_stream_data_uni(1u32 << 20);
+    params.set_initial_max_stream_data_bidi_local(1u32 << 20);
+    params.set_initial_max_stream_data_bidi_remote(1u32 << 20);
+    // WebTransport datagrams of any size, see `crate::webtransport`
+    params.set_max_datagram_frame_size(u16::MAX as u32);
+
+    params
+}
+
+async fn handle_connection(
+    serve_root: Arc<PathBuf>,
+    gen_max: u64,
+    mut connection: h3::server::Connection<h3_shim::QuicConnection, Bytes>,
+    quic: Arc<Connection>,
+    scheduler: Option<Scheduler>,
+    webtransport: bool,
+    stats: Arc<ServerStats>,
+) {
+    let mut requests = JoinSet::new();
+    loop {
+        match connection.accept().await {
+            Ok(Some((req, stream))) => {
+                info!("new request: {:#?}", req);
+                if let Some(mode) = webtransport::Mode::of_request(&req).filter(|_| webtransport) {
+                    // the session takes the connection over, the responses
+                    // under way still go
+                    let session =
+                        webtransport::serve_session(mode, req, stream, connection, quic, stats);
+                    if let Err(e) = session.await {
+                        error!("serving session failed: {}", e);
+                    }
+                    break;
+                }
+                let serve_root = serve_root.clone();
+                let stats = stats.clone();
+                // in the order the requests came
+                let turn = scheduler
+                    .as_ref()
+                    .map(|scheduler| scheduler.register(Priority::of(req.headers())));
+                requests.spawn(async move {
+                    if let Err(e) =
+                        handle_h3_request(req, stream, serve_root, gen_max, turn, stats).await
+                    {
+                        error!("handling request failed: {}", e);
+                    }
+                });
+                while requests.try_join_next().is_some() {}
+            }
+            Ok(None) => break,
+            Err(error) => match error.get_error_level() {
+                ErrorLevel::ConnectionError => break,
+                ErrorLevel::StreamError => continue,
+            },
+        }
+    }
+    // let the last responses finish before the connection goes
+    while requests.join_next().await.is_some() {}
+}
+
+#[tracing::instrument(skip_all)]
+async fn handle_h3_request<T>(
+    req: Request<()>,
+    mut stream: RequestStream<T, Bytes>,
+    serve_root: Arc<PathBuf>,
+    gen_max: u64,
+    turn: Option<Turn>,
+    stats: Arc<ServerStats>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+where
+    T: BidiStream<Bytes>,
+{
+    let (status, to_serve) = open(&serve_root, gen_max, req.uri().path()).await;
+
+    let mut resp = http::Response::builder().status(status);
+    if let Some((_, len)) = &to_serve {
+        resp = resp.header(header::CONTENT_LENGTH, *len);
+    }
+    stream.send_response(resp.body(())?).await?;
+
+    if let Some((mut file, _)) = to_serve {
+        loop {
+            if let Some(turn) = &turn {
+                turn.wait().await;
+            }
+            let mut buf = BytesMut::with_capacity(4096 * 10);
+            let read = file.read_buf(&mut buf).await?;
+            if read == 0 {
+                break;
+            }
+            stream.send_data(buf.freeze()).await?;
+            stats.bytes.fetch_add(read as u64, Ordering::Relaxed);
+        }
+    }
+
+    stream.finish().await?;
+    Ok(())
+}
+
+/// TLS 1.3 offering h2 and http/1.1 over ALPN, asking clients for a
+/// certificate if `client_verifier` is given and handing out session tickets
+/// as `tickets` says.
+pub fn h2_acceptor(
+    cert_chain: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+    client_verifier: Option<Arc<dyn ClientCertVerifier>>,
+    tickets: &TicketOptions,
+) -> io::Result<TlsAcceptor> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = rustls::ServerConfig::builder_with_provider(provider)
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .map_err(io::Error::other)?;
+    let mut config = tls::with_client_verifier(builder, client_verifier)
+        .with_single_cert(cert_chain, key)
+        .map_err(io::Error::other)?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    tickets.apply(&mut config)?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Serve HTTP/2 and HTTP/1.1 on every listener until `shutdown` resolves or
+/// one of them fails, sending the most urgent responses of each connection
+/// first if `priorities`. Connections still open then are aborted.
+pub async fn serve_h2(
+    listeners: Vec<TcpListener>,
+    acceptor: TlsAcceptor,
+    root: Arc<PathBuf>,
+    gen_max: u64,
+    priorities: bool,
+    stats: Arc<ServerStats>,
+    shutdown: impl Future<Output = ()>,
+) -> io::Result<()> {
+    let mut serving = JoinSet::new();
+    for listener in listeners {
+        serving.spawn(serve_listener(
+            listener,
+            acceptor.clone(),
+            root.clone(),
+            gen_max,
+            priorities,
+            stats.clone(),
+        ));
+    }
+
+    tokio::select! {
+        Some(joined) = serving.join_next() => Err(joined.unwrap_or_else(io::Error::other)),
+        _ = shutdown => Ok(()),
+    }
+}
+
+type Body = BoxBody<Bytes, io::Error>;
+
+async fn serve_listener(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    root: Arc<PathBuf>,
+    gen_max: u64,
+    priorities: bool,
+    stats: Arc<ServerStats>,
+) -> io::Error {
+    let mut connections = JoinSet::new();
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(error) => return error,
+        };
+        stats.connections.fetch_add(1, Ordering::Relaxed);
+        let acceptor = acceptor.clone();
+        let root = root.clone();
+        let stats = stats.clone();
+        connections.spawn(async move {
+            let mut stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(error) => {
+                    error!("TLS handshake with {} failed: {}", peer, error);
+                    return;
+                }
+            };
+            let (_, session) = stream.get_mut();
+            let mut early_data = vec![];
+            if let Some(mut early) = session.early_data() {
+                _ = io::Read::read_to_end(&mut early, &mut early_data);
+            }
+            let client = session
+                .peer_certificates()
+                .map(|certs| peer_identity(&certs[0]));
+            let handshake = session.handshake_kind();
+            info!(
+                ?client,
+                ?handshake,
+                early_data = early_data.len(),
+                "new connection established from {}",
+                peer
+            );
+            let stream = WithEarlyData {
+                early_data: early_data.into(),
+                stream,
+            };
+            let scheduler = priorities.then(Scheduler::default);
+            let service = hyper::service::service_fn(move |req: Request<Incoming>| {
+                let turn = scheduler
+                    .as_ref()
+                    .map(|scheduler| scheduler.register(Priority::of(req.headers())));
+                handle_h2_request(req, root.clone(), gen_max, turn, stats.clone())
+            });
+            if let Err(error) = auto::Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                error!("connection from {} failed: {}", peer, error);
+            }
+        });
+        while connections.try_join_next().is_some() {}
+    }
+}
+
+/// A server connection reading the early data of its handshake first.
+/// tokio-rustls leaves that in the session, where hyper doesn't look.
+struct WithEarlyData {
+    early_data: Bytes,
+    stream: server::TlsStream<TcpStream>,
+}
+
+impl AsyncRead for WithEarlyData {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.early_data.is_empty() {
+            return Pin::new(&mut this.stream).poll_read(cx, buf);
+        }
+        let len = this.early_data.len().min(buf.remaining());
+        buf.put_slice(&this.early_data.split_to(len));
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for WithEarlyData {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().stream).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().stream).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn handle_h2_request(
+    req: Request<Incoming>,
+    serve_root: Arc<PathBuf>,
+    gen_max: u64,
+    turn: Option<Turn>,
+    stats: Arc<ServerStats>,
+) -> Result<Response<Body>, Infallible> {
+    info!("new request: {:#?}", req);
+    let (status, to_serve) = open(&serve_root, gen_max, req.uri().path()).await;
+
+    let content_length = to_serve.as_ref().map(|(_, len)| *len);
+    let body = match to_serve {
+        Some((file, _)) => {
+            let chunks = futures::stream::try_unfold((file, turn), move |(mut file, turn)| {
+                let stats = stats.clone();
+                async move {
+                    if let Some(turn) = &turn {
+                        turn.wait().await;
+                    }
+                    let mut buf = BytesMut::with_capacity(4096 * 10);
+                    let read = file.read_buf(&mut buf).await?;
+                    if read == 0 {
+                        return Ok(None);
+                    }
+                    stats.bytes.fetch_add(read as u64, Ordering::Relaxed);
+                    Ok(Some((Frame::data(buf.freeze()), (file, turn))))
+                }
+            });
+            StreamBody::new(chunks).boxed()
+        }
+        None => Empty::new().map_err(|never| match never {}).boxed(),
+    };
+
+    let mut resp = Response::new(body);
+    *resp.status_mut() = status;
+    if let Some(len) = content_length {
+        // hyper holds the body to it
+        resp.headers_mut()
+            .insert(header::CONTENT_LENGTH, len.into());
+    }
+    Ok(resp)
+}
@@ -0,0 +1,103 @@
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+
+use big_file_test::{
+    files,
+    logging::{LogDefaults, LogOptions},
+    report::{Report, ReportOptions, ServerStats},
+    tls::{self, ClientAuthOptions, TicketOptions},
+};
+use clap::Parser;
+use tokio::net::TcpListener;
+use tracing::info;
+
+/// HTTP/1.1 and HTTP/2 over TLS, serving files the way `h3-server` does.
+#[derive(Parser, Debug)]
+struct Options {
+    /// Root directory of the files to serve
+    #[arg(name = "dir", short, long, default_value = "./")]
+    root: PathBuf,
+    /// What address:port to listen for new connections
+    #[arg(short, long, default_values = ["127.0.0.1:4431", "[::1]:4431"])]
+    listen: Vec<SocketAddr>,
+    /// Certificate for TLS
+    #[arg(long, short, default_value = "server.crt")]
+    cert: PathBuf,
+    /// Private key for the certificate
+    #[arg(long, short, default_value = "server.key")]
+    key: PathBuf,
+    #[command(flatten)]
+    client_auth: ClientAuthOptions,
+    #[command(flatten)]
+    tickets: TicketOptions,
+    /// Answer /gen/ requests for more bytes than this with 400
+    #[arg(long, value_name = "BYTES", default_value = "1073741824")]
+    gen_max: u64,
+    /// Send the responses of a connection side by side, ignoring the
+    /// priority of their requests
+    #[arg(long)]
+    ignore_priority: bool,
+    #[command(flatten)]
+    log: LogOptions,
+    #[command(flatten)]
+    report: ReportOptions,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let options = Options::parse();
+    options
+        .log
+        .init(env!("CARGO_BIN_NAME"), LogDefaults::INFO, &[])?;
+    rustls::crypto::ring::default_provider()
+        .install_default()
+        .expect("Failed to install rustls crypto provider");
+
+    let mut report = Report::start(env!("CARGO_BIN_NAME"));
+    info!("serving {}", options.root.display());
+    let root = Arc::new(options.root);
+    if !root.is_dir() {
+        return Err(format!("{}: is not a readable directory", root.display()).into());
+    }
+
+    let acceptor = files::h2_acceptor(
+        tls::load_certs(&options.cert)?,
+        tls::load_key(&options.key)?,
+        options.client_auth.verifier()?,
+        &options.tickets,
+    )?;
+
+    let mut listeners = vec![];
+    for addr in &options.listen {
+        let listener = TcpListener::bind(addr).await?;
+        info!("listening on {:?}", listener.local_addr()?);
+        listeners.push(listener);
+    }
+
+    let stats = Arc::new(ServerStats::default());
+    let priorities = !options.ignore_priority;
+    files::serve_h2(
+        listeners,
+        acceptor,
+        root,
+        options.gen_max,
+        priorities,
+        stats.clone(),
+        async {
+            _ = tokio::signal::ctrl_c().await;
+            info!("interrupted");
+        },
+    )
+    .await?;
+
+    let served = stats.bytes();
+    report
+        .set("connections", stats.connections())
+        .set("served_bytes", served)
+        .set("gen_max_bytes", options.gen_max)
+        .set("priorities", priorities);
+    options.client_auth.report(&mut report);
+    options.tickets.report(&mut report);
+    report.finish(served, &options.report)?;
+
+    Ok(())
+}
@@ -1,13 +1,25 @@
-use std::{
-    sync::Arc,
-    time::{Duration, Instant},
-};
+use std::{path::PathBuf, sync::Arc, time::Instant};
 
+use big_file_test::{
+    congestion::CongestionOptions,
+    eyeballs::{Candidates, EyeballsOptions, Winners},
+    failure::{Failures, RetryOptions},
+    fetch::{self, ByHandshake, Connector, Error, Protocol},
+    logging::{LogDefaults, LogOptions},
+    priority::{Finishes, PriorityOptions},
+    report::{Report, ReportOptions},
+    request::RequestOptions,
+    storm::{self, StormOptions},
+    tls::{self, ClientCertOptions, ResumptionOptions},
+    transfer::Progress,
+    validate::ValidateOptions,
+    webtransport::{Mode, Session, WebTransportOptions},
+    workload::{ByClass, WorkloadOptions},
+};
 use clap::Parser;
-use gm_quic::ToCertificate;
+use gm_quic::QuicClient;
 use http::Uri;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use rustls::RootCertStore;
 use tokio::task::JoinSet;
 use tracing::{Instrument, info_span};
 
@@ -18,64 +30,142 @@ struct Options {
     #[arg(long, short = 'c', default_value = "64")]
     conns: usize,
     #[arg(long, default_value = "ca.crt")]
-    roots: Vec<String>,
+    roots: Vec<PathBuf>,
+    #[command(flatten)]
+    client_cert: ClientCertOptions,
+    #[command(flatten)]
+    resumption: ResumptionOptions,
     #[arg(short = 'p', long)]
     progress: bool,
+    /// HTTP version to drive the server with, h2 and http1 go over TLS on TCP
+    #[arg(long, value_enum, default_value = "h3")]
+    protocol: Protocol,
+    /// Only used by h3
+    #[command(flatten)]
+    congestion: CongestionOptions,
+    #[command(flatten)]
+    eyeballs: EyeballsOptions,
+    #[command(flatten)]
+    priority: PriorityOptions,
+    #[command(flatten)]
+    retry: RetryOptions,
+    #[command(flatten)]
+    validate: ValidateOptions,
+    #[command(flatten)]
+    workload: WorkloadOptions,
+    #[command(flatten)]
+    request: RequestOptions,
+    #[command(flatten)]
+    storm: StormOptions,
+    #[command(flatten)]
+    webtransport: WebTransportOptions,
     #[arg(default_value = "https://localhost:4431/rand-file-15K")]
     uri: String,
+    #[command(flatten)]
+    log: LogOptions,
+    #[command(flatten)]
+    report: ReportOptions,
 }
 
 #[tokio::main]
 async fn main() {
-    tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .with_ansi(false)
-        // .with_writer(
-        //     std::fs::OpenOptions::new()
-        //         .create(true)
-        //         .truncate(true)
-        //         .write(true)
-        //         .open("h3-client.log")
-        //         .unwrap(),
-        // )
-        .init();
-    if let Err(error) = run(Options::parse()).await {
+    let options = Options::parse();
+    let log_defaults = LogDefaults {
+        filter: "error",
+        ..LogDefaults::INFO
+    };
+    if let Err(error) = options.log.init(env!("CARGO_BIN_NAME"), log_defaults, &[]) {
+        panic!("{error:?}");
+    }
+    if let Err(error) = run(options).await {
         tracing::error!(?error);
         panic!("{error:?}");
     };
 }
 
-type Error = Box<dyn std::error::Error + Send + Sync>;
-
 async fn run(options: Options) -> Result<(), Error> {
+    let mut report = Report::start(env!("CARGO_BIN_NAME"));
     let uri = options.uri.parse::<Uri>()?;
-    let auth = uri.authority().unwrap();
-    let addr = tokio::net::lookup_host((auth.host(), auth.port_u16().unwrap_or(443)))
+    let (host, port) = options.request.destination(&uri)?;
+    let candidates = options
+        .eyeballs
+        .resolve(host, port)
         .await?
-        .next()
-        .ok_or("dns found no addresses")?;
-    tracing::info!("DNS lookup for {:?}: {:?}", auth.host(), addr);
-
-    let mut roots = RootCertStore::empty();
-    for root in options.roots {
-        let cert = tokio::fs::read(root).await?;
-        roots.add_parsable_certificates(cert.to_certificate());
+        .with_server_name(options.request.sni.as_deref());
+
+    let roots = tls::load_roots(&options.roots)?;
+    let identity = options.client_cert.identity()?;
+    let connector = Connector::new(
+        options.protocol,
+        roots,
+        identity.as_ref(),
+        &options.resumption,
+        &options.congestion,
+    )?;
+
+    report
+        .set("uri", uri.to_string())
+        .set("protocol", format!("{:?}", options.protocol).to_lowercase())
+        .set("client_cert", identity.is_some());
+    options.eyeballs.report(&mut report);
+    candidates.report(&mut report);
+    options.request.report(&mut report);
+    options.resumption.report(&mut report);
+    if options.protocol == Protocol::H3 {
+        options.congestion.report(&mut report);
     }
 
-    let client = Arc::new(
-        gm_quic::QuicClient::builder()
-            .with_root_certificates(roots)
-            .without_cert()
-            .with_parameters(client_parameters())
-            .with_alpns([b"h3" as &[u8], b"hq-29"])
-            .enable_sslkeylog()
-            .build(),
-    );
+    if options.webtransport.webtransport {
+        let Connector::Quic(client) = &connector else {
+            return Err("WebTransport needs --protocol=h3".into());
+        };
+        return webtransport(client, &candidates, &uri, &options, report).await;
+    }
+
+    let warmup = options
+        .resumption
+        .warmup(fetch::probe(&connector, &candidates, &uri, true))
+        .await?;
+    if let Some(warmup) = &warmup {
+        report
+            .set("warmup_connect_time_s", warmup.connect_time)
+            .set("warmup_time_s", warmup.time());
+    }
 
     let pbs = MultiProgress::new();
     if !options.progress {
         pbs.set_draw_target(indicatif::ProgressDrawTarget::hidden());
     }
+
+    if options.storm.storm.is_some() {
+        let pb = pbs.add(ProgressBar::new(0).with_prefix("handshakes").with_style(
+            ProgressStyle::with_template("{prefix} {wide_bar} {pos}/{len} {per_sec} {eta}")?,
+        ));
+        let storm = storm::storm(connector, candidates, uri, &options.storm, pb).await;
+        tracing::info!(
+            target: "counting",
+            handshakes = storm.handshakes.len(),
+            failures = ?storm.failures.by_class,
+            "done!"
+        );
+        report
+            .set("storm_concurrency", options.storm.storm_concurrency)
+            .set("storm_rate_per_s", options.storm.storm_rate)
+            .set("storm_request", options.storm.storm_request);
+        // the storm's connections were of use once connected if they didn't
+        // request anything
+        let baseline = warmup.map(|warmup| {
+            if options.storm.storm_request {
+                warmup.time()
+            } else {
+                warmup.connect_time
+            }
+        });
+        storm.report(&mut report, baseline);
+        report.finish(storm.bytes, &options.report)?;
+        return Ok(());
+    }
+
     let conns_pb = pbs.add(ProgressBar::new(0).with_prefix("connections").with_style(
         ProgressStyle::with_template("{prefix} {wide_bar} {pos}/{len}")?,
     ));
@@ -83,29 +173,65 @@ async fn run(options: Options) -> Result<(), Error> {
         ProgressStyle::with_template("{prefix} {wide_bar} {pos}/{len} {per_sec} {eta}")?,
     ));
 
+    let mix = options.priority.mix();
+    let workload = options
+        .workload
+        .load(&uri, options.validate.expect()?)?
+        .with_request(&options.request);
     let start_time = Instant::now();
     let mut connections = JoinSet::new();
     for idx in 0..options.conns {
         conns_pb.inc_length(1);
 
-        let connection = client.connect(auth.host(), addr)?;
-        let uri = uri.clone();
+        let connector = connector.clone();
+        let candidates = candidates.clone();
 
         connections.spawn(
-            for_each_connection(connection, uri, options.reqs, total_pb.clone(), pbs.clone())
-                .instrument(info_span!("connection", idx)),
+            fetch::for_each_connection(
+                connector,
+                candidates,
+                workload.clone(),
+                idx,
+                options.reqs,
+                mix.clone(),
+                options.retry.clone(),
+                total_pb.clone(),
+                pbs.clone(),
+            )
+            .instrument(info_span!("connection", idx)),
         );
     }
 
     let mut success_queries = 0;
+    let mut received = 0;
+    let mut connect_times = vec![];
+    let mut by_handshake = ByHandshake::default();
+    let mut winners = Winners::default();
+    let mut finishes = Finishes::default();
+    let mut request_bytes = vec![];
+    let mut by_class = ByClass::default();
+    let mut failures = Failures::default();
+    let mut failed_connections = 0;
     while let Some(res) = connections.join_next().await {
         match res {
-            Ok(Ok(queries)) => {
-                success_queries += queries;
+            Ok(Ok(fetched)) => {
+                success_queries += fetched.requests;
+                received += fetched.bytes;
+                connect_times.push(fetched.connect_time);
+                winners.record(fetched.addr);
+                request_bytes.extend(fetched.finishes.iter().map(|finish| finish.bytes as f64));
+                by_class.record(&fetched.finishes);
+                finishes.record(fetched.finishes);
+                failures.merge(fetched.failures);
+                if let Some(first_response) = fetched.first_response {
+                    by_handshake.record(fetched.handshake, first_response);
+                }
                 conns_pb.inc(1);
             }
-            Ok(Err(err)) => {
-                tracing::error!(error = ?err,"conenction failed");
+            Ok(Err(failed)) => {
+                tracing::error!(error = ?failed.error, "conenction failed");
+                failures.merge(failed.failures);
+                failed_connections += 1;
                 conns_pb.dec_length(1);
             }
             Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
@@ -118,105 +244,89 @@ async fn run(options: Options) -> Result<(), Error> {
 
     let total_time = start_time.elapsed().as_secs_f64();
     let qps = success_queries as f64 / total_time;
+    let goodput = received as f64 / total_time;
 
-    tracing::info!(target: "counting" ,success_queries ,total_time ,qps, "done!");
+    tracing::info!(target: "counting" ,success_queries ,total_time ,qps, goodput, "done!");
 
-    Ok(())
-}
-
-fn client_parameters() -> gm_quic::ClientParameters {
-    let mut params = gm_quic::ClientParameters::default();
-
-    params.set_initial_max_streams_bidi(100u32);
-    params.set_initial_max_streams_uni(100u32);
-    params.set_initial_max_data(1u32 << 20);
-    params.set_initial_max_stream_data_uni(1u32 << 20);
-    params.set_initial_max_stream_data_bidi_local(1u32 << 20);
-    params.set_initial_max_stream_data_bidi_remote(1u32 << 20);
-    params.set_max_idle_timeout(Duration::from_secs(10));
-
-    params
-}
-
-async fn for_each_connection(
-    connection: Arc<gm_quic::Connection>,
-    uri: Uri,
-    reqs: usize,
-    total_pb: ProgressBar,
-    pbs: MultiProgress,
-) -> Result<usize, Error> {
-    // let origin_dcid = connection.origin_dcid()?;
-    let conn_pb = pbs.insert_after(
-        &total_pb,
-        ProgressBar::new(0)
-            // .with_prefix(format!("{origin_dcid:x}"))
-            .with_message("connecting")
-            .with_style(ProgressStyle::with_template("{prefix} {spinner} {msg}")?),
+    report
+        .set("connections", options.conns)
+        .set("requests_per_connection", options.reqs)
+        .set("success_queries", success_queries)
+        .set("failed_connections", failed_connections)
+        .set("total_time_s", total_time)
+        .set("qps", qps)
+        .set("goodput_bytes_per_s", goodput)
+        .set_percentiles("request", "bytes", &request_bytes);
+    if !connect_times.is_empty() {
+        let mean = connect_times.iter().sum::<f64>() / connect_times.len() as f64;
+        report.set("connect_time_s", mean);
+    }
+    by_handshake.report(
+        &mut report,
+        "first_response",
+        warmup.map(|warmup| warmup.time()),
     );
+    winners.report(&mut report);
+    options.priority.report(&mut report);
+    finishes.report(&mut report);
+    options.retry.report(&mut report);
+    options.validate.report(&mut report);
+    options.workload.report(&mut report);
+    if options.workload.workload.is_some() {
+        by_class.report(&mut report, &workload, total_time);
+    }
+    failures.report(&mut report);
+    report.finish(received, &options.report)?;
 
-    let connection = h3_shim::QuicConnection::new(connection).await;
-    let (mut conn, send_request) = h3::client::new(connection).await?;
-    tracing::info!("conenction established");
+    Ok(())
+}
 
-    total_pb.inc_length(reqs as u64);
-    conn_pb.set_style(ProgressStyle::with_template(
-        "{prefix} {wide_bar} {pos}/{len}",
-    )?);
+/// One session, the way `client` runs one connection.
+async fn webtransport(
+    client: &QuicClient,
+    candidates: &Candidates,
+    uri: &Uri,
+    options: &Options,
+    mut report: Report,
+) -> Result<(), Error> {
+    let wt = &options.webtransport;
+    let file = Arc::new(tokio::fs::read(&wt.wt_file).await?);
+    let progress = Arc::new(Progress::new(
+        wt.wt_bidi,
+        file.len() as u64,
+        options.progress,
+    ));
 
-    let driver = async move {
-        core::future::poll_fn(|cx| conn.poll_close(cx))
-            .await
-            .map_err(Error::from)
-    };
+    let start_time = Instant::now();
+    let session = Session::open(client, candidates, uri).await?;
+    let session_time = start_time.elapsed().as_secs_f64();
+    let start_time = Instant::now();
+    let datagrams = session.run(file.clone(), progress, wt).await?;
+    let transfer_time = start_time.elapsed().as_secs_f64();
+    session.close();
 
-    let _driver = tokio::spawn(driver);
-
-    let mut requests = JoinSet::new();
-    for req_id in 0..reqs {
-        let conn_pb = conn_pb.clone();
-        let request = http::Request::builder().uri(uri.clone()).body(())?;
-        let mut send_request = send_request.clone();
-
-        requests.spawn(
-            async move {
-                let mut request_stream = send_request.send_request(request).await?;
-                let request = async {
-                    request_stream.finish().await?;
-                    conn_pb.inc_length(1);
-                    let _resp = request_stream.recv_response().await?;
-                    while request_stream.recv_data().await?.is_some() {}
-                    Result::<(), Error>::Ok(())
-                };
-                request
-                    .await
-                    .inspect(|()| conn_pb.inc(1))
-                    .inspect_err(|_| conn_pb.dec_length(1))
-            }
-            .instrument(info_span!("request", req_id)),
-        );
+    // the echoes of the bidirectional streams come back, the rest only goes
+    let mut streams = wt.wt_bidi + wt.wt_uni;
+    if session.mode() == Mode::Echo {
+        streams += wt.wt_bidi;
     }
+    let mut bytes = (file.len() * streams) as u64;
+    bytes += datagrams.as_ref().map_or(0, |datagrams| datagrams.bytes());
+    tracing::info!(target: "counting", session_time, transfer_time, "done!");
 
-    let mut error = None;
-
-    let mut success_queries = 0;
-    while let Some(res) = requests.join_next().await {
-        match res {
-            Ok(Ok(())) => {
-                success_queries += 1;
-                total_pb.inc(1);
-            }
-            Ok(Err(err)) => {
-                total_pb.dec_length(1);
-                error = Some(err);
-            }
-            Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
-            Err(err) => panic!("{err}"),
-        }
-    }
-    conn_pb.finish_and_clear();
-    if success_queries != 0 {
-        Ok(success_queries)
-    } else {
-        Err(error.unwrap())
+    report
+        .set(
+            "webtransport",
+            format!("{:?}", session.mode()).to_lowercase(),
+        )
+        .set("file_bytes", file.len())
+        .set("session_time_s", session_time)
+        .set("transfer_time_s", transfer_time)
+        .set("throughput_bytes_per_s", bytes as f64 / transfer_time);
+    wt.report(&mut report);
+    if let Some(datagrams) = datagrams {
+        datagrams.report(&mut report);
     }
+    report.finish(bytes, &options.report)?;
+    Ok(())
 }
@@ -1,11 +1,14 @@
-use std::{net::SocketAddr, ops::Deref, path::PathBuf, sync::Arc};
-
-use bytes::{Bytes, BytesMut};
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+
+use big_file_test::{
+    congestion::CongestionOptions,
+    files,
+    logging::{LogDefaults, LogOptions},
+    report::{Report, ReportOptions, ServerStats},
+    tls::{ClientAuthOptions, TicketOptions},
+};
 use clap::Parser;
-use h3::{error::ErrorLevel, quic::BidiStream, server::RequestStream};
-use http::{Request, StatusCode};
-use tokio::{fs::File, io::AsyncReadExt};
-use tracing::{error, info};
+use tracing::info;
 
 #[derive(Parser, Debug)]
 #[structopt(name = "server")]
@@ -30,6 +33,43 @@ pub struct Options {
 
     #[structopt(flatten)]
     pub certs: Certs,
+
+    #[structopt(flatten)]
+    pub client_auth: ClientAuthOptions,
+
+    #[structopt(flatten)]
+    pub tickets: TicketOptions,
+
+    #[structopt(
+        long,
+        value_name = "BYTES",
+        default_value = "1073741824",
+        help = "Answer /gen/ requests for more bytes than this with 400."
+    )]
+    pub gen_max: u64,
+
+    #[structopt(
+        long,
+        help = "Send the responses of a connection side by side, ignoring \
+                the priority of their requests."
+    )]
+    pub ignore_priority: bool,
+
+    #[structopt(
+        long,
+        help = "Take WebTransport sessions to /echo and /sink. \
+                A session takes its connection over."
+    )]
+    pub webtransport: bool,
+
+    #[structopt(flatten)]
+    pub congestion: CongestionOptions,
+
+    #[structopt(flatten)]
+    pub log: LogOptions,
+
+    #[structopt(flatten)]
+    pub report: ReportOptions,
 }
 
 #[derive(Parser, Debug)]
@@ -51,29 +91,24 @@ pub struct Certs {
     pub key: PathBuf,
 }
 
-static ALPN: &[u8] = b"h3";
-
 #[cfg_attr(test, allow(unused))]
 #[tokio::main(flavor = "current_thread")]
 // #[tokio::main]
 pub async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    // tracing_subscriber::fmt()
-    //     .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-    //     .with_ansi(false)
-    //     .init();
-    // console_subscriber::Builder::default()
-    //     .server_addr("127.0.0.1:16669".parse::<SocketAddr>().unwrap())
-    //     .init();
+    // process cli arguments
+    let options = Options::parse();
+    options
+        .log
+        .init(env!("CARGO_BIN_NAME"), LogDefaults::INFO, &[])?;
     rustls::crypto::ring::default_provider()
         .install_default()
         .expect("Failed to install rustls crypto provider");
-    // process cli arguments
-    let options = Options::parse();
 
     run(options).await
 }
 
 pub async fn run(options: Options) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut report = Report::start(env!("CARGO_BIN_NAME"));
     info!("serving {}", options.root.display());
     let root = Arc::new(options.root);
     if !root.is_dir() {
@@ -81,111 +116,50 @@ pub async fn run(options: Options) -> Result<(), Box<dyn std::error::Error + Sen
     }
     let Certs { cert, key } = options.certs;
 
-    let quic_server = ::gm_quic::QuicServer::builder()
-        .without_client_cert_verifier()
-        .with_parameters(server_parameters())
-        .enable_sni()
-        .add_host("localhost", cert.as_path(), key.as_path())
-        .with_alpns([ALPN.to_vec()])
-        .listen(&options.listen[..])?;
+    let quic_server = files::listen_h3(
+        &options.listen,
+        &std::fs::read(&cert)?,
+        &std::fs::read(&key)?,
+        options.client_auth.verifier()?,
+        &options.tickets,
+        &options.congestion,
+    )?;
     info!("listening on {:?}", quic_server.addresses());
 
     // handle incoming connections and requests
-    while let Ok((new_conn, _pathway)) = quic_server.accept().await {
-        let h3_conn =
-            match h3::server::Connection::new(h3_shim::QuicConnection::new(new_conn).await).await {
-                Ok(h3_conn) => {
-                    info!("new connection established");
-                    h3_conn
-                }
-                Err(error) => {
-                    tracing::error!("failed to establish h3 connection: {}", error);
-                    continue;
-                }
-            };
-        let root = root.clone();
-        tokio::spawn(handle_connection(root, h3_conn));
-    }
-
-    Ok(())
-}
-
-fn server_parameters() -> gm_quic::ServerParameters {
-    let mut params = gm_quic::ServerParameters::default();
-
-    params.set_initial_max_streams_bidi(100u32);
-    params.set_initial_max_streams_uni(100u32);
-    params.set_initial_max_data(1u32 << 20);
-    params.set_initial_max_stream_data_uni(1u32 << 20);
-    params.set_initial_max_stream_data_bidi_local(1u32 << 20);
-    params.set_initial_max_stream_data_bidi_remote(1u32 << 20);
-
-    params
-}
-
-async fn handle_connection<T>(
-    serve_root: Arc<PathBuf>,
-    mut connection: h3::server::Connection<T, Bytes>,
-) where
-    T: h3::quic::Connection<Bytes>,
-    <T as h3::quic::OpenStreams<Bytes>>::BidiStream: h3::quic::BidiStream<Bytes> + Send + 'static,
-{
-    loop {
-        match connection.accept().await {
-            Ok(Some((req, stream))) => {
-                info!("new request: {:#?}", req);
-                let serve_root = serve_root.clone();
-                tokio::spawn(async move {
-                    if let Err(e) = handle_request(req, stream, serve_root).await {
-                        error!("handling request failed: {}", e);
-                    }
-                });
-            }
-            Ok(None) => break,
-            Err(error) => match error.get_error_level() {
-                ErrorLevel::ConnectionError => break,
-                ErrorLevel::StreamError => continue,
-            },
-        }
-    }
-}
-
-#[tracing::instrument(skip_all)]
-async fn handle_request<T>(
-    req: Request<()>,
-    mut stream: RequestStream<T, Bytes>,
-    serve_root: Arc<PathBuf>,
-) -> Result<(), Box<dyn std::error::Error>>
-where
-    T: BidiStream<Bytes>,
-{
-    let (status, to_serve) = match serve_root.deref() {
-        _ if req.uri().path().contains("..") => (StatusCode::NOT_FOUND, None),
-        root => {
-            let to_serve = root.join(req.uri().path().strip_prefix('/').unwrap_or(""));
-            match File::open(&to_serve).await {
-                Ok(file) => (StatusCode::OK, Some(file)),
-                Err(e) => {
-                    error!("failed to open: \"{}\": {}", to_serve.to_string_lossy(), e);
-                    (StatusCode::NOT_FOUND, None)
-                }
-            }
-        }
-    };
-
-    let resp = http::Response::builder().status(status).body(())?;
-    stream.send_response(resp).await?;
-
-    if let Some(mut file) = to_serve {
-        loop {
-            let mut buf = BytesMut::with_capacity(4096 * 10);
-            if file.read_buf(&mut buf).await? == 0 {
-                break;
-            }
-            stream.send_data(buf.freeze()).await?;
-        }
+    let stats = Arc::new(ServerStats::default());
+    let priorities = !options.ignore_priority;
+    files::serve_h3(
+        &quic_server,
+        root,
+        options.gen_max,
+        priorities,
+        options.webtransport,
+        stats.clone(),
+        async {
+            _ = tokio::signal::ctrl_c().await;
+            info!("interrupted");
+        },
+    )
+    .await;
+
+    let served = stats.bytes();
+    report
+        .set("connections", stats.connections())
+        .set("served_bytes", served)
+        .set("gen_max_bytes", options.gen_max)
+        .set("priorities", priorities)
+        .set("webtransport", options.webtransport);
+    if options.webtransport {
+        report
+            .set("sessions", stats.sessions())
+            .set("datagrams", stats.datagrams())
+            .set("datagram_bytes", stats.datagram_bytes());
     }
+    options.client_auth.report(&mut report);
+    options.tickets.report(&mut report);
+    options.congestion.report(&mut report);
+    report.finish(served, &options.report)?;
 
-    stream.finish().await?;
     Ok(())
 }
@@ -0,0 +1,28 @@
+pub mod args;
+pub mod certs;
+pub mod congestion;
+pub mod datagram;
+pub mod echo;
+pub mod eyeballs;
+pub mod failure;
+pub mod fetch;
+pub mod files;
+pub mod logging;
+pub mod migration;
+pub mod multipath;
+pub mod nat;
+pub mod priority;
+pub mod report;
+pub mod request;
+pub mod resources;
+pub mod runtime_metrics;
+pub mod sockets;
+pub mod storm;
+pub mod tcp;
+pub mod tcp_echo;
+pub mod tls;
+pub mod trace_events;
+pub mod transfer;
+pub mod validate;
+pub mod webtransport;
+pub mod workload;
@@ -0,0 +1,151 @@
+//! Logging options shared by all binaries, so every binary writes the same
+//! schema and analysis tooling only has to understand one.
+
+use std::{
+    fmt::Display,
+    fs,
+    io::{self, IsTerminal},
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use clap::{Args, ValueEnum};
+use tracing_subscriber::{
+    EnvFilter, Layer,
+    fmt::{format::FmtSpan, writer::BoxMakeWriter},
+    layer::SubscriberExt,
+    util::SubscriberInitExt,
+};
+
+use crate::{runtime_metrics, trace_events::RUNTIME_METRICS};
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct LogOptions {
+    /// Format of the log records
+    #[arg(long, value_enum)]
+    pub log_format: Option<LogFormat>,
+    /// Write logs to this file, `-` for stdout. `{bin}`, `{pid}`, `{time}`
+    /// and the binary's own keys (see `--help` of each binary) are substituted
+    #[arg(long)]
+    pub log_file: Option<String>,
+    /// env-filter directives, overriding `RUST_LOG` and the binary's default level
+    #[arg(long)]
+    pub log_filter: Option<String>,
+    /// Log a record with busy and idle time whenever a span closes
+    #[arg(long)]
+    pub span_timing: bool,
+    /// Serve tokio-console on this address
+    #[arg(long, value_name = "ADDR", num_args = 0..=1, default_missing_value = "127.0.0.1:6669")]
+    pub console: Option<SocketAddr>,
+    /// Log tokio runtime metrics every this many milliseconds
+    #[arg(long, value_name = "MS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub runtime_metrics: Option<u64>,
+}
+
+/// What a binary logs when no logging option is given.
+#[derive(Clone, Copy, Debug)]
+pub struct LogDefaults<'a> {
+    pub filter: &'a str,
+    pub format: LogFormat,
+    pub file: Option<&'a str>,
+}
+
+impl LogDefaults<'static> {
+    pub const INFO: Self = Self {
+        filter: "info",
+        format: LogFormat::Text,
+        file: None,
+    };
+}
+
+impl LogOptions {
+    /// Install the global subscriber. `vars` are the template keys `--log-file`
+    /// may use in addition to `{bin}`, `{pid}` and `{time}`.
+    pub fn init(
+        &self,
+        bin: &str,
+        defaults: LogDefaults,
+        vars: &[(&str, &dyn Display)],
+    ) -> io::Result<()> {
+        let filter = match &self.log_filter {
+            Some(directives) => EnvFilter::try_new(directives),
+            None => {
+                EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new(defaults.filter))
+            }
+        }
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let filter = match self.runtime_metrics {
+            // make sure the samples asked for are not filtered out
+            Some(_) => filter.add_directive(format!("{RUNTIME_METRICS}=info").parse().unwrap()),
+            None => filter,
+        };
+
+        let file = match self.log_file.as_deref().or(defaults.file) {
+            Some("-") | None => None,
+            Some(template) => Some(render_template(template, bin, vars)),
+        };
+        let writer = match &file {
+            Some(path) => BoxMakeWriter::new(Arc::new(fs::File::create(path)?)),
+            None => BoxMakeWriter::new(io::stdout),
+        };
+
+        let span_events = if self.span_timing {
+            FmtSpan::CLOSE
+        } else {
+            FmtSpan::NONE
+        };
+        let layer = tracing_subscriber::fmt::layer()
+            .with_writer(writer)
+            .with_ansi(file.is_none() && io::stdout().is_terminal())
+            .with_span_events(span_events);
+        let layer = match self.log_format.unwrap_or(defaults.format) {
+            LogFormat::Text => layer.boxed(),
+            LogFormat::Json => layer
+                .json()
+                .with_current_span(true)
+                .with_span_list(true)
+                .boxed(),
+        };
+
+        let console = self.console.map(|addr| {
+            console_subscriber::ConsoleLayer::builder()
+                .server_addr(addr)
+                .spawn()
+        });
+
+        tracing_subscriber::registry()
+            .with(console)
+            .with(layer.with_filter(filter))
+            .init();
+
+        if let Some(addr) = self.console {
+            tracing::info!(%addr, "serving tokio-console");
+        }
+        if let Some(interval) = self.runtime_metrics {
+            runtime_metrics::spawn(Duration::from_millis(interval));
+        }
+        Ok(())
+    }
+}
+
+fn render_template(template: &str, bin: &str, vars: &[(&str, &dyn Display)]) -> String {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let builtin: [(&str, &dyn Display); 3] =
+        [("bin", &bin), ("pid", &std::process::id()), ("time", &time)];
+
+    let mut rendered = template.to_owned();
+    for (key, value) in builtin.iter().chain(vars) {
+        rendered = rendered.replace(&format!("{{{key}}}"), &value.to_string());
+    }
+    rendered
+}
@@ -0,0 +1,164 @@
+//! Connection migration of `client`: partway through the transfer the
+//! connection moves to a new local address, and how the echoed bytes kept
+//! coming back around that moment tells what the move cost.
+
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+use clap::Args;
+use indicatif::ProgressBar;
+use tokio::time::{self, MissedTickBehavior};
+
+use crate::report::Report;
+
+/// How often the received bytes are sampled.
+const SAMPLE: Duration = Duration::from_millis(10);
+/// Throughput before and after the move is compared over this long.
+const WINDOW: Duration = Duration::from_secs(1);
+/// The dip is the slowest slice of this long in the window after the move.
+const SLICE: Duration = Duration::from_millis(100);
+
+#[derive(Args, Clone, Debug, Default)]
+pub struct MigrationOptions {
+    /// Move the connection to a new local address this long into the transfer
+    #[arg(long, value_name = "SECONDS", conflicts_with = "migrate_at")]
+    pub migrate_after: Option<f64>,
+    /// Move the connection to a new local address once this many bytes came
+    /// back
+    #[arg(long, value_name = "BYTES")]
+    pub migrate_at: Option<u64>,
+    /// Local address to move to, a new port of any address if omitted
+    #[arg(long, value_name = "ADDR")]
+    pub migrate_to: Option<SocketAddr>,
+}
+
+impl MigrationOptions {
+    pub fn enabled(&self) -> bool {
+        self.migrate_after.is_some() || self.migrate_at.is_some()
+    }
+
+    fn due(&self, elapsed: Duration, received: u64) -> bool {
+        let after = self
+            .migrate_after
+            .is_some_and(|secs| elapsed.as_secs_f64() >= secs);
+        let at = self.migrate_at.is_some_and(|bytes| received >= bytes);
+        after || at
+    }
+
+    /// Where to move a connection to `remote`.
+    pub fn local(&self, remote: SocketAddr) -> SocketAddr {
+        self.migrate_to.unwrap_or_else(|| {
+            let ip = match remote {
+                SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            };
+            SocketAddr::new(ip, 0)
+        })
+    }
+
+    pub fn report(&self, report: &mut Report) {
+        report
+            .set("migrate_after_s", self.migrate_after)
+            .set("migrate_at_bytes", self.migrate_at);
+    }
+}
+
+/// The received bytes of a transfer over time, and when it moved.
+#[derive(Debug, Default)]
+pub struct Migration {
+    /// Time into the transfer and bytes received by then.
+    samples: Vec<(Duration, u64)>,
+    /// When the connection moved, and to which local address.
+    moved: Option<(Duration, SocketAddr)>,
+}
+
+impl Migration {
+    /// Sample `received` until dropped, calling `rebind` with the local
+    /// address to move to once `options` say so. `rebind` returns the address
+    /// it bound. Only returns if that fails, race it with the transfer.
+    pub async fn run(
+        &mut self,
+        options: &MigrationOptions,
+        remote: SocketAddr,
+        received: &ProgressBar,
+        mut rebind: impl FnMut(SocketAddr) -> io::Result<SocketAddr>,
+    ) -> io::Error {
+        let start = Instant::now();
+        let mut interval = time::interval(SAMPLE);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let (elapsed, bytes) = (start.elapsed(), received.position());
+            self.samples.push((elapsed, bytes));
+            if self.moved.is_some() || !options.due(elapsed, bytes) {
+                continue;
+            }
+            match rebind(options.local(remote)) {
+                Ok(local) => {
+                    tracing::info!(%local, bytes, "migrated");
+                    self.moved = Some((elapsed, local));
+                }
+                Err(error) => return error,
+            }
+        }
+    }
+
+    /// Seconds from the move until bytes came back again.
+    pub fn latency(&self) -> Option<f64> {
+        let (moved, _) = self.moved?;
+        let before = self.bytes_at(moved);
+        let (resumed, _) = self
+            .samples
+            .iter()
+            .find(|(at, bytes)| *at > moved && *bytes > before)?;
+        Some((*resumed - moved).as_secs_f64())
+    }
+
+    /// Received bytes per second from `from` to `to`, as far as sampled.
+    fn throughput(&self, from: Duration, to: Duration) -> Option<f64> {
+        let (first, _) = self.samples.first()?;
+        let (last, _) = self.samples.last()?;
+        let secs = (to.min(*last).saturating_sub(from.max(*first))).as_secs_f64();
+        let bytes = self.bytes_at(to) - self.bytes_at(from);
+        (secs > 0.0).then(|| bytes as f64 / secs)
+    }
+
+    /// Bytes received by `at`.
+    fn bytes_at(&self, at: Duration) -> u64 {
+        let idx = self.samples.partition_point(|(sampled, _)| *sampled <= at);
+        idx.checked_sub(1).map_or(0, |idx| self.samples[idx].1)
+    }
+
+    /// Sets whether the connection moved and, if it did, where to, when, the
+    /// latency and the throughput a second before and after. `migration_dip`
+    /// is how much slower than before the slowest tenth of a second after
+    /// was, 1 for a stall.
+    pub fn report(&self, report: &mut Report) {
+        report.set("migrated", self.moved.is_some());
+        let Some((moved, local)) = self.moved else {
+            return;
+        };
+        report
+            .set("migration_local", local.to_string())
+            .set("migration_time_s", moved.as_secs_f64())
+            .set(
+                "migration_latency_ms",
+                self.latency().map(|secs| secs * 1e3),
+            );
+        let before = self.throughput(moved.saturating_sub(WINDOW), moved);
+        let after = self.throughput(moved, moved + WINDOW);
+        report
+            .set("throughput_before_bytes_per_s", before)
+            .set("throughput_after_bytes_per_s", after);
+        let slowest = (0..WINDOW.as_millis() / SLICE.as_millis())
+            .map(|slice| moved + SLICE * slice as u32)
+            .filter_map(|from| self.throughput(from, from + SLICE))
+            .min_by(f64::total_cmp);
+        if let (Some(before), Some(slowest)) = (before, slowest) {
+            report.set("migration_dip", 1.0 - slowest / before);
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Multipath mode of `client`: besides the path to the resolved server
+//! address, the connection opens one from every `--path`, and the transfer
+//! goes over all of them at once. What each path carried is counted at its
+//! socket: gm-quic 0.2.2 keeps no statistics of its paths, so there is no loss
+//! or RTT to tell.
+
+use std::{
+    net::{AddrParseError, SocketAddr},
+    str::FromStr,
+};
+
+use clap::Args;
+use serde_json::{Map, Value};
+
+use crate::{report::Report, sockets::Traffic};
+
+/// A local address to open a path from and where it goes, the server if not
+/// given. Written `LOCAL` or `LOCAL,REMOTE`.
+#[derive(Clone, Copy, Debug)]
+pub struct PathSpec {
+    pub local: SocketAddr,
+    pub remote: Option<SocketAddr>,
+}
+
+impl FromStr for PathSpec {
+    type Err = AddrParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (local, remote) = match s.split_once(',') {
+            Some((local, remote)) => (local, Some(remote.parse()?)),
+            None => (s, None),
+        };
+        Ok(Self {
+            local: local.parse()?,
+            remote,
+        })
+    }
+}
+
+#[derive(Args, Clone, Debug, Default)]
+pub struct MultipathOptions {
+    /// Also transfer over a path from LOCAL to REMOTE, a `nat-proxy` impairing
+    /// it for example, or to the server. Repeat for more paths
+    #[arg(
+        long = "path",
+        value_name = "LOCAL[,REMOTE]",
+        conflicts_with_all = ["migrate_after", "migrate_at"]
+    )]
+    pub paths: Vec<PathSpec>,
+}
+
+impl MultipathOptions {
+    pub fn enabled(&self) -> bool {
+        !self.paths.is_empty()
+    }
+}
+
+/// What each path of a connection carried.
+#[derive(Debug, Default)]
+pub struct Paths(Vec<(String, Traffic)>);
+
+impl Paths {
+    /// `traffic` of every path, local and remote address.
+    pub fn new(traffic: impl IntoIterator<Item = (String, Traffic)>) -> Self {
+        Self(traffic.into_iter().collect())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(String, Traffic)> {
+        self.0.iter()
+    }
+
+    /// Sets `paths` to the bytes and packets every path sent and received.
+    pub fn report(&self, report: &mut Report) {
+        let paths = self
+            .0
+            .iter()
+            .map(|(path, traffic)| {
+                let mut fields = Map::new();
+                fields.insert("sent_bytes".into(), traffic.sent_bytes.into());
+                fields.insert("received_bytes".into(), traffic.received_bytes.into());
+                fields.insert("sent_packets".into(), traffic.sent_packets.into());
+                fields.insert("received_packets".into(), traffic.received_packets.into());
+                (path.clone(), Value::Object(fields))
+            })
+            .collect::<Map<_, _>>();
+        report.set("path_count", paths.len()).set("paths", paths);
+    }
+}
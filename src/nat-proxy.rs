@@ -0,0 +1,70 @@
+use std::{io, net::SocketAddr, sync::Arc, time::Duration};
+
+use big_file_test::{
+    args,
+    logging::{LogDefaults, LogOptions},
+    nat::{self, Impairment, NatStats},
+    report::{Report, ReportOptions},
+};
+use clap::Parser;
+use tokio::net::UdpSocket;
+
+/// Relays UDP between clients and `server`, moving each client to a new
+/// source port every `--rebind-every` as a NAT that forgets its mappings, and
+/// impairing both directions.
+#[derive(Parser)]
+struct Options {
+    /// Where clients send to
+    #[arg(long, default_value = "[::1]:35468")]
+    listen: SocketAddr,
+    /// Where the datagrams go on to
+    #[arg(long, default_value = "[::1]:35467")]
+    server: SocketAddr,
+    /// Move every client to a new source port this often, never if omitted
+    #[arg(long, value_name = "SECONDS", value_parser = args::positive)]
+    rebind_every: Option<f64>,
+    #[command(flatten)]
+    impairment: Impairment,
+    #[command(flatten)]
+    log: LogOptions,
+    #[command(flatten)]
+    report: ReportOptions,
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let options = Options::parse();
+    options
+        .log
+        .init(env!("CARGO_BIN_NAME"), LogDefaults::INFO, &[])?;
+
+    let mut report = Report::start(env!("CARGO_BIN_NAME"));
+
+    let listener = UdpSocket::bind(options.listen).await?;
+    tracing::info!(server = %options.server, "relaying from {:?}", listener.local_addr()?);
+
+    let stats = Arc::new(NatStats::default());
+    nat::relay(
+        listener,
+        options.server,
+        options.rebind_every.map(Duration::from_secs_f64),
+        options.impairment.clone(),
+        stats.clone(),
+        async {
+            _ = tokio::signal::ctrl_c().await;
+            tracing::info!("interrupted");
+        },
+    )
+    .await?;
+
+    report
+        .set("server", options.server.to_string())
+        .set("rebind_every_s", options.rebind_every)
+        .set("clients", stats.clients())
+        .set("rebinds", stats.rebinds())
+        .set("forwarded_bytes", stats.forwarded())
+        .set("returned_bytes", stats.returned())
+        .set("dropped_datagrams", stats.dropped());
+    options.impairment.report(&mut report);
+    report.finish(stats.forwarded() + stats.returned(), &options.report)
+}
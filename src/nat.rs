@@ -0,0 +1,310 @@
+//! The UDP relay of `nat-proxy`: the datagrams of every client go on to the
+//! server from a port of the relay, which it swaps for a new one now and then
+//! the way a NAT forgets its mappings. To the server the client moves while
+//! it stays put, so gm-quic's path validation sees a NAT rebinding.
+//!
+//! Both directions can also be delayed, rate limited and lossy, so relays
+//! with different impairments make different paths to the same server.
+
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    future::Future,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use bytes::Bytes;
+use clap::Args;
+use rand::Rng;
+use tokio::{
+    net::UdpSocket,
+    sync::mpsc,
+    task::JoinHandle,
+    time::{self, Instant, MissedTickBehavior},
+};
+
+use crate::{args, report::Report};
+
+/// What the relay counts, reported when it shuts down.
+#[derive(Debug, Default)]
+pub struct NatStats {
+    pub clients: AtomicU64,
+    /// Mappings swapped for a new port.
+    pub rebinds: AtomicU64,
+    /// Bytes relayed from clients to the server.
+    pub forwarded: AtomicU64,
+    /// Bytes relayed from the server back to clients.
+    pub returned: AtomicU64,
+    /// Datagrams lost on purpose or for a full queue, both directions.
+    pub dropped: AtomicU64,
+}
+
+impl NatStats {
+    pub fn clients(&self) -> u64 {
+        self.clients.load(Ordering::Relaxed)
+    }
+
+    pub fn rebinds(&self) -> u64 {
+        self.rebinds.load(Ordering::Relaxed)
+    }
+
+    pub fn forwarded(&self) -> u64 {
+        self.forwarded.load(Ordering::Relaxed)
+    }
+
+    pub fn returned(&self) -> u64 {
+        self.returned.load(Ordering::Relaxed)
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// What the relay does to the datagrams of each direction, like netem.
+#[derive(Args, Clone, Debug)]
+pub struct Impairment {
+    /// One-way delay added to every datagram
+    #[arg(
+        long,
+        value_name = "MILLISECONDS",
+        default_value = "0",
+        value_parser = args::non_negative
+    )]
+    pub delay: f64,
+    /// Bandwidth of each direction, unlimited if omitted
+    #[arg(long, value_name = "BYTES_PER_SECOND", value_parser = args::positive)]
+    pub rate: Option<f64>,
+    /// Drop datagrams at random with this probability
+    #[arg(long, value_name = "0..1", default_value = "0")]
+    pub loss: f64,
+    /// Drop datagrams that would wait longer than this for `--rate`
+    #[arg(
+        long,
+        value_name = "MILLISECONDS",
+        default_value = "100",
+        value_parser = args::non_negative
+    )]
+    pub queue: f64,
+}
+
+impl Default for Impairment {
+    fn default() -> Self {
+        Self {
+            delay: 0.0,
+            rate: None,
+            loss: 0.0,
+            queue: 100.0,
+        }
+    }
+}
+
+impl Impairment {
+    pub fn report(&self, report: &mut Report) {
+        report
+            .set("delay_ms", self.delay)
+            .set("rate_bytes_per_s", self.rate)
+            .set("loss", self.loss)
+            .set("queue_ms", self.queue);
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Direction {
+    Forward,
+    Return,
+}
+
+/// One direction of a mapping: datagrams wait for the bandwidth and the delay
+/// in a queue, and a task sends them when they are due.
+struct Link {
+    impairment: Impairment,
+    /// When the bandwidth is free for the next datagram.
+    free: Instant,
+    queue: mpsc::UnboundedSender<(Instant, Bytes)>,
+    stats: Arc<NatStats>,
+}
+
+impl Link {
+    /// Send through `socket`, to `to` or where it is connected.
+    fn new(
+        impairment: &Impairment,
+        socket: Arc<UdpSocket>,
+        to: Option<SocketAddr>,
+        direction: Direction,
+        stats: Arc<NatStats>,
+    ) -> Self {
+        let (queue, mut due) = mpsc::unbounded_channel::<(Instant, Bytes)>();
+        tokio::spawn({
+            let stats = stats.clone();
+            async move {
+                while let Some((at, datagram)) = due.recv().await {
+                    time::sleep_until(at).await;
+                    let sent = match to {
+                        Some(to) => socket.send_to(&datagram, to).await,
+                        None => socket.send(&datagram).await,
+                    };
+                    // a refused datagram is no reason to give up on the client
+                    if sent.is_ok() {
+                        let counter = match direction {
+                            Direction::Forward => &stats.forwarded,
+                            Direction::Return => &stats.returned,
+                        };
+                        counter.fetch_add(datagram.len() as u64, Ordering::Relaxed);
+                    }
+                }
+            }
+        });
+        Self {
+            impairment: impairment.clone(),
+            free: Instant::now(),
+            queue,
+            stats,
+        }
+    }
+
+    fn send(&mut self, datagram: &[u8]) {
+        let now = Instant::now();
+        let impairment = &self.impairment;
+        let start = self.free.max(now);
+        let queued = (start - now).as_secs_f64() * 1e3;
+        if rand::rng().random_bool(impairment.loss.clamp(0.0, 1.0)) || queued > impairment.queue {
+            self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let serialization = impairment.rate.map_or(Duration::ZERO, |rate| {
+            Duration::from_secs_f64(datagram.len() as f64 / rate)
+        });
+        self.free = start + serialization;
+        let due = self.free + Duration::from_secs_f64(impairment.delay / 1e3);
+        _ = self.queue.send((due, Bytes::copy_from_slice(datagram)));
+    }
+}
+
+/// The port the datagrams of one client leave from.
+struct Mapping {
+    upstream: Arc<UdpSocket>,
+    forward: Link,
+    returning: JoinHandle<()>,
+}
+
+impl Mapping {
+    /// Bind a new port towards `server` and relay what comes back on it to
+    /// `client` through `listener`.
+    async fn new(
+        listener: Arc<UdpSocket>,
+        client: SocketAddr,
+        server: SocketAddr,
+        impairment: &Impairment,
+        stats: Arc<NatStats>,
+    ) -> io::Result<Self> {
+        let unspecified = match server {
+            SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+        let upstream = Arc::new(UdpSocket::bind((unspecified, 0)).await?);
+        upstream.connect(server).await?;
+        let forward = Link::new(
+            impairment,
+            upstream.clone(),
+            None,
+            Direction::Forward,
+            stats.clone(),
+        );
+        let mut back = Link::new(impairment, listener, Some(client), Direction::Return, stats);
+        let returning = tokio::spawn({
+            let upstream = upstream.clone();
+            async move {
+                let mut buf = vec![0; u16::MAX as usize];
+                while let Ok(len) = upstream.recv(&mut buf).await {
+                    back.send(&buf[..len]);
+                }
+            }
+        });
+        Ok(Self {
+            upstream,
+            forward,
+            returning,
+        })
+    }
+
+    fn port(&self) -> u16 {
+        self.upstream.local_addr().map_or(0, |addr| addr.port())
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        // what the server still sends to the old port goes nowhere
+        self.returning.abort();
+    }
+}
+
+/// Relay the datagrams clients send to `listener` on to `server` and back,
+/// moving every client to a new port each `rebind_every`, until `shutdown`
+/// resolves.
+pub async fn relay(
+    listener: UdpSocket,
+    server: SocketAddr,
+    rebind_every: Option<Duration>,
+    impairment: Impairment,
+    stats: Arc<NatStats>,
+    shutdown: impl Future<Output = ()>,
+) -> io::Result<()> {
+    let listener = Arc::new(listener);
+    let mut mappings = HashMap::<SocketAddr, Mapping>::new();
+    let mut rebind = rebind_every.map(|every| {
+        let mut interval = time::interval_at(Instant::now() + every, every);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        interval
+    });
+
+    let relaying = async {
+        let mut buf = vec![0; u16::MAX as usize];
+        loop {
+            let rebind_due = async {
+                match &mut rebind {
+                    Some(rebind) => _ = rebind.tick().await,
+                    None => std::future::pending().await,
+                }
+            };
+            tokio::select! {
+                received = listener.recv_from(&mut buf) => {
+                    let (len, client) = received?;
+                    let mapping = match mappings.entry(client) {
+                        Entry::Occupied(entry) => entry.into_mut(),
+                        Entry::Vacant(entry) => {
+                            let mapping =
+                                Mapping::new(listener.clone(), client, server, &impairment, stats.clone())
+                                    .await?;
+                            tracing::info!(%client, port = mapping.port(), "new client");
+                            stats.clients.fetch_add(1, Ordering::Relaxed);
+                            entry.insert(mapping)
+                        }
+                    };
+                    mapping.forward.send(&buf[..len]);
+                }
+                _ = rebind_due => {
+                    for (client, mapping) in &mut mappings {
+                        let moved =
+                            Mapping::new(listener.clone(), *client, server, &impairment, stats.clone()).await?;
+                        let (from, to) = (mapping.port(), moved.port());
+                        tracing::info!(%client, from, to, "rebound");
+                        *mapping = moved;
+                        stats.rebinds.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+        }
+    };
+
+    tokio::select! {
+        result = relaying => result,
+        _ = shutdown => Ok(()),
+    }
+}
@@ -0,0 +1,309 @@
+//! Extensible priorities (RFC 9218) for `h3-client` and the file servers.
+//! Requests carry a `priority` header with their urgency and whether they are
+//! incremental, and the servers send the data of the most urgent responses of
+//! a connection first. `h3-client` can mix urgent small requests into the big
+//! ones and tell whether they got ahead.
+//!
+//! The h3 crate doesn't hand PRIORITY_UPDATE frames to the server, so a
+//! request keeps the priority it came with.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use clap::Args;
+use http::{HeaderMap, HeaderName, HeaderValue, Uri};
+use tokio::sync::Notify;
+
+use crate::{args, report::Report};
+
+pub static PRIORITY: HeaderName = HeaderName::from_static("priority");
+
+/// The priority parameters of a request, `u=3` and not incremental unless it
+/// says otherwise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Priority {
+    /// 0 is the most urgent, 7 the least.
+    pub urgency: u8,
+    /// Whether the response is of use in parts, so it may share the
+    /// connection with others of its urgency.
+    pub incremental: bool,
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Self {
+            urgency: 3,
+            incremental: false,
+        }
+    }
+}
+
+impl Priority {
+    /// The priority `headers` ask for, members that don't parse are left at
+    /// their defaults as the RFC says.
+    pub fn of(headers: &HeaderMap) -> Self {
+        let mut priority = Self::default();
+        let Some(value) = headers.get(&PRIORITY).and_then(|value| value.to_str().ok()) else {
+            return priority;
+        };
+        for member in value.split(',') {
+            let (key, value) = match member.trim().split_once('=') {
+                Some((key, value)) => (key, Some(value)),
+                None => (member.trim(), None),
+            };
+            match (key, value) {
+                ("u", Some(urgency)) => {
+                    if let Ok(urgency @ 0..=7) = urgency.parse() {
+                        priority.urgency = urgency;
+                    }
+                }
+                ("i", None | Some("?1")) => priority.incremental = true,
+                ("i", Some("?0")) => priority.incremental = false,
+                _ => {}
+            }
+        }
+        priority
+    }
+
+    pub fn header(self) -> HeaderValue {
+        HeaderValue::from_str(&self.to_string()).expect("a valid header value")
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "u={}", self.urgency)?;
+        if self.incremental {
+            write!(f, ", i")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct PriorityOptions {
+    /// Urgency of the URI's requests, 0 the most urgent. No priority header
+    /// unless given or `--incremental`
+    #[arg(long, value_name = "0..7", value_parser = clap::value_parser!(u8).range(0..=7))]
+    pub urgency: Option<u8>,
+    /// Mark the URI's requests incremental
+    #[arg(long)]
+    pub incremental: bool,
+    /// Also GET this on every connection, `--urgent-reqs` times after the
+    /// URI's requests went out
+    #[arg(long, value_name = "URI")]
+    pub urgent_uri: Option<Uri>,
+    #[arg(long, default_value = "1", requires = "urgent_uri")]
+    pub urgent_reqs: usize,
+    /// Urgency of the urgent requests
+    #[arg(
+        long,
+        value_name = "0..7",
+        default_value = "0",
+        value_parser = clap::value_parser!(u8).range(0..=7)
+    )]
+    pub urgent_urgency: u8,
+    #[arg(long, requires = "urgent_uri")]
+    pub urgent_incremental: bool,
+    /// Send the urgent requests this long after the others, so the big
+    /// responses are under way
+    #[arg(
+        long,
+        value_name = "MILLISECONDS",
+        default_value = "0",
+        value_parser = args::non_negative
+    )]
+    pub urgent_after: f64,
+}
+
+impl PriorityOptions {
+    pub fn mix(&self) -> Mix {
+        let priority = (self.urgency.is_some() || self.incremental).then(|| Priority {
+            urgency: self.urgency.unwrap_or(Priority::default().urgency),
+            incremental: self.incremental,
+        });
+        let urgent = self.urgent_uri.clone().map(|uri| Urgent {
+            uri,
+            reqs: self.urgent_reqs,
+            priority: Priority {
+                urgency: self.urgent_urgency,
+                incremental: self.urgent_incremental,
+            },
+            after: Duration::from_secs_f64(self.urgent_after / 1e3),
+        });
+        Mix { priority, urgent }
+    }
+
+    pub fn report(&self, report: &mut Report) {
+        let mix = self.mix();
+        report.set(
+            "priority",
+            mix.priority.map(|priority| priority.to_string()),
+        );
+        if let Some(urgent) = mix.urgent {
+            report
+                .set("urgent_uri", urgent.uri.to_string())
+                .set("urgent_reqs", urgent.reqs)
+                .set("urgent_priority", urgent.priority.to_string())
+                .set("urgent_after_ms", self.urgent_after);
+        }
+    }
+}
+
+/// The priority of the requests for the URI of `h3-client`, and what it
+/// mixes in on every connection.
+#[derive(Clone, Debug, Default)]
+pub struct Mix {
+    pub priority: Option<Priority>,
+    pub urgent: Option<Urgent>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Urgent {
+    pub uri: Uri,
+    pub reqs: usize,
+    pub priority: Priority,
+    pub after: Duration,
+}
+
+/// When a request of a connection started and finished, in seconds since
+/// requests could be sent.
+#[derive(Clone, Copy, Debug)]
+pub struct Finish {
+    pub urgent: bool,
+    pub started: f64,
+    pub finished: f64,
+    /// Body bytes of the response.
+    pub bytes: u64,
+    /// The workload class of the request, none for the urgent ones.
+    pub class: Option<usize>,
+}
+
+/// The requests of every connection, to tell whether the urgent ones got
+/// ahead of the others.
+#[derive(Debug, Default)]
+pub struct Finishes(Vec<Vec<Finish>>);
+
+impl Finishes {
+    pub fn record(&mut self, connection: Vec<Finish>) {
+        self.0.push(connection);
+    }
+
+    /// Sets the percentiles of how long urgent and other requests took and
+    /// `urgent_ahead_ratio`, the share of urgent requests that finished
+    /// before any other one of their connection.
+    pub fn report(&self, report: &mut Report) {
+        let finishes = self.0.iter().flatten();
+        let took = |urgent: bool| {
+            finishes
+                .clone()
+                .filter(|finish| finish.urgent == urgent)
+                .map(|finish| (finish.finished - finish.started) * 1e3)
+                .collect::<Vec<_>>()
+        };
+        let urgent = took(true);
+        if urgent.is_empty() {
+            return;
+        }
+        report
+            .set_percentiles("urgent_time", "ms", &urgent)
+            .set_percentiles("background_time", "ms", &took(false));
+
+        let ahead = self
+            .0
+            .iter()
+            .map(|connection| {
+                let first_other = connection
+                    .iter()
+                    .filter(|finish| !finish.urgent)
+                    .map(|finish| finish.finished)
+                    .min_by(f64::total_cmp)
+                    .unwrap_or(f64::INFINITY);
+                connection
+                    .iter()
+                    .filter(|finish| finish.urgent && finish.finished < first_other)
+                    .count()
+            })
+            .sum::<usize>();
+        report.set("urgent_ahead_ratio", ahead as f64 / urgent.len() as f64);
+    }
+}
+
+/// Orders the response data of one connection by priority: only the most
+/// urgent responses send, one after another in the order they came unless
+/// the first of them is incremental, then the incremental ones take turns.
+#[derive(Clone, Debug, Default)]
+pub struct Scheduler(Arc<Shared>);
+
+#[derive(Debug, Default)]
+struct Shared {
+    /// Whether each response is incremental, by urgency and arrival.
+    responses: Mutex<BTreeMap<(u8, u64), bool>>,
+    arrivals: AtomicU64,
+    changed: Notify,
+}
+
+impl Scheduler {
+    /// Schedule a response, until the returned turn is dropped.
+    pub fn register(&self, priority: Priority) -> Turn {
+        let key = (
+            priority.urgency,
+            self.0.arrivals.fetch_add(1, Ordering::Relaxed),
+        );
+        let mut responses = self.0.responses.lock().unwrap();
+        responses.insert(key, priority.incremental);
+        // a more urgent response holds back the ones sending now
+        self.0.changed.notify_waiters();
+        Turn {
+            scheduler: self.clone(),
+            key,
+        }
+    }
+}
+
+/// A response of a [`Scheduler`].
+#[derive(Debug)]
+pub struct Turn {
+    scheduler: Scheduler,
+    key: (u8, u64),
+}
+
+impl Turn {
+    fn may_send(&self) -> bool {
+        let responses = self.scheduler.0.responses.lock().unwrap();
+        let Some((&first, &incremental)) = responses.iter().next() else {
+            return true;
+        };
+        if incremental {
+            first.0 == self.key.0 && responses[&self.key]
+        } else {
+            first == self.key
+        }
+    }
+
+    /// Wait until the response may send its next chunk.
+    pub async fn wait(&self) {
+        loop {
+            let changed = self.scheduler.0.changed.notified();
+            if self.may_send() {
+                return;
+            }
+            changed.await;
+        }
+    }
+}
+
+impl Drop for Turn {
+    fn drop(&mut self) {
+        let shared = &self.scheduler.0;
+        shared.responses.lock().unwrap().remove(&self.key);
+        shared.changed.notify_waiters();
+    }
+}
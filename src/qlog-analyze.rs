@@ -0,0 +1,458 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use clap::Parser;
+use serde_json::{Map, Value};
+
+#[derive(Parser)]
+struct Options {
+    /// qlog files (JSON-SEQ) or directories containing them
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+    /// Where to write the `<trace>.csv` time series, next to the qlog by default
+    #[arg(short = 'o', long)]
+    out_dir: Option<PathBuf>,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let options = Options::parse();
+
+    let mut files = vec![];
+    for path in &options.paths {
+        collect_qlogs(path, &mut files)?;
+    }
+    if files.is_empty() {
+        return Err("no qlog files found".into());
+    }
+
+    for file in files {
+        let events = read_json_seq(&file)?;
+        let analysis = Analysis::from_events(&events);
+
+        let out_dir = match &options.out_dir {
+            Some(dir) => dir.clone(),
+            None => file.parent().map(Path::to_path_buf).unwrap_or_default(),
+        };
+        fs::create_dir_all(&out_dir)?;
+        let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+        let csv = out_dir.join(format!("{stem}.csv"));
+        analysis.write_series(&csv)?;
+
+        println!("{}", file.display());
+        println!("  time series: {}", csv.display());
+        analysis.print_summary();
+    }
+
+    Ok(())
+}
+
+fn collect_qlogs(path: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+    let mut entries = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+    for entry in entries {
+        let is_qlog = entry
+            .extension()
+            .is_some_and(|ext| ext == "sqlog" || ext == "qlog");
+        if entry.is_dir() || is_qlog {
+            collect_qlogs(&entry, files)?;
+        }
+    }
+    Ok(())
+}
+
+/// One qlog event, with the time rebased to the first event of the trace.
+struct Event {
+    time: f64,
+    name: String,
+    data: Value,
+}
+
+/// Read a JSON-SEQ (RFC 7464) qlog file. The first record is the qlog header,
+/// every following record is an event.
+fn read_json_seq(path: &Path) -> io::Result<Vec<Event>> {
+    let content = fs::read_to_string(path)?;
+
+    let mut events = vec![];
+    let mut t0 = None;
+    for record in content.split(['\u{1e}', '\n']) {
+        let record = record.trim();
+        if record.is_empty() {
+            continue;
+        }
+        let value = serde_json::from_str::<Value>(record)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let (Some(time), Some(name)) = (
+            value.get("time").and_then(Value::as_f64),
+            value.get("name").and_then(Value::as_str),
+        ) else {
+            // qlog header or a record we don't understand
+            continue;
+        };
+        let t0 = *t0.get_or_insert(time);
+        events.push(Event {
+            time: time - t0,
+            name: name.to_owned(),
+            data: value.get("data").cloned().unwrap_or(Value::Null),
+        });
+    }
+    Ok(events)
+}
+
+/// Event names differ between qlog drafts (`recovery:metrics_updated` vs
+/// `quic:recovery_metrics_updated`), so match on the suffix.
+fn is_event(name: &str, suffix: &str) -> bool {
+    name.ends_with(suffix)
+}
+
+/// Packet number space of a qlog `header.packet_type`.
+fn packet_space(header: &Value) -> &'static str {
+    match header.get("packet_type").and_then(Value::as_str) {
+        Some("initial") => "initial",
+        Some("handshake") => "handshake",
+        _ => "data",
+    }
+}
+
+fn packet_number(header: &Value) -> Option<u64> {
+    header.get("packet_number").and_then(Value::as_u64)
+}
+
+fn frames(data: &Value) -> impl Iterator<Item = &Value> {
+    data.get("frames")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+}
+
+fn frame_type(frame: &Value) -> &str {
+    frame
+        .get("frame_type")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+}
+
+/// Accumulates the time spent in a boolean state.
+#[derive(Default)]
+struct Blocked {
+    since: Option<f64>,
+    total: f64,
+}
+
+impl Blocked {
+    fn set(&mut self, time: f64, blocked: bool) -> bool {
+        match (self.since, blocked) {
+            (None, true) => self.since = Some(time),
+            (Some(since), false) => {
+                self.total += time - since;
+                self.since = None;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn finish(&mut self, time: f64) {
+        self.set(time, false);
+    }
+}
+
+struct LossEpisode {
+    start: f64,
+    packets: u64,
+    bytes: u64,
+}
+
+#[derive(Default)]
+struct Analysis {
+    /// (time_ms, metric, value)
+    series: Vec<(f64, &'static str, f64)>,
+    duration: f64,
+
+    packets_sent: u64,
+    packets_lost: u64,
+    bytes_lost: u64,
+    loss_triggers: BTreeMap<String, u64>,
+    loss_episodes: Vec<LossEpisode>,
+    packets_retransmitted: u64,
+    bytes_retransmitted: u64,
+    spurious_retransmissions: u64,
+
+    congestion_blocked: Blocked,
+    flow_control_blocked: Blocked,
+
+    /// The latest `parameters_set` of the congestion controller.
+    parameters: Map<String, Value>,
+    /// Time spent in each congestion controller state, in ms.
+    congestion_states: BTreeMap<String, f64>,
+}
+
+impl Analysis {
+    fn from_events(events: &[Event]) -> Self {
+        let mut analysis = Analysis::default();
+
+        let mut cwnd = None;
+        let mut bytes_in_flight = None;
+        let mut smoothed_rtt = 0.0;
+        // packets declared lost that may still be acknowledged later, per space
+        let mut lost = HashSet::<(&str, u64)>::new();
+        // highest stream offset sent so far, to spot retransmitted stream data
+        let mut stream_sent = HashMap::<u64, u64>::new();
+        // streams (and the connection, as None) we told the peer we are blocked on
+        let mut blocked_on = HashSet::<Option<u64>>::new();
+        // the congestion controller state and since when it is in it
+        let mut congestion_state = None::<(&str, f64)>;
+
+        for event in events {
+            let time = event.time;
+            let data = &event.data;
+            analysis.duration = time;
+
+            if is_event(&event.name, "metrics_updated") {
+                let metrics = [
+                    ("congestion_window", "cwnd"),
+                    ("bytes_in_flight", "bytes_in_flight"),
+                    ("smoothed_rtt", "smoothed_rtt"),
+                    ("min_rtt", "min_rtt"),
+                    ("latest_rtt", "latest_rtt"),
+                    ("pacing_rate", "pacing_rate"),
+                ];
+                for (field, metric) in metrics {
+                    if let Some(value) = data.get(field).and_then(Value::as_f64) {
+                        analysis.series.push((time, metric, value));
+                        match field {
+                            "congestion_window" => cwnd = Some(value),
+                            "bytes_in_flight" => bytes_in_flight = Some(value),
+                            "smoothed_rtt" => smoothed_rtt = value,
+                            _ => {}
+                        }
+                    }
+                }
+                if let (Some(cwnd), Some(bytes_in_flight)) = (cwnd, bytes_in_flight) {
+                    let blocked = bytes_in_flight >= cwnd;
+                    if analysis.congestion_blocked.set(time, blocked) {
+                        analysis
+                            .series
+                            .push((time, "congestion_blocked", blocked as u8 as f64));
+                    }
+                }
+            } else if is_event(&event.name, "parameters_set") {
+                if let Some(parameters) = data.as_object() {
+                    analysis.parameters.extend(parameters.clone());
+                }
+            } else if is_event(&event.name, "congestion_state_updated") {
+                let Some(new) = data.get("new").and_then(Value::as_str) else {
+                    continue;
+                };
+                // before the first update the controller was in `old` since the start
+                let old = data
+                    .get("old")
+                    .and_then(Value::as_str)
+                    .map(|old| (old, 0.0));
+                if let Some((old, since)) = congestion_state.replace((new, time)).or(old) {
+                    *analysis
+                        .congestion_states
+                        .entry(old.to_owned())
+                        .or_default() += time - since;
+                }
+            } else if is_event(&event.name, "packet_lost") {
+                let header = data.get("header").unwrap_or(&Value::Null);
+                let bytes = data
+                    .get("raw")
+                    .and_then(|raw| raw.get("length"))
+                    .and_then(Value::as_u64)
+                    .unwrap_or_default();
+                if let Some(pn) = packet_number(header) {
+                    lost.insert((packet_space(header), pn));
+                }
+                let trigger = data
+                    .get("trigger")
+                    .and_then(Value::as_str)
+                    .unwrap_or("unknown");
+                *analysis
+                    .loss_triggers
+                    .entry(trigger.to_owned())
+                    .or_default() += 1;
+
+                analysis.packets_lost += 1;
+                analysis.bytes_lost += bytes;
+                analysis
+                    .series
+                    .push((time, "packets_lost", analysis.packets_lost as f64));
+
+                // losses detected within one RTT of each other are one congestion event
+                match analysis.loss_episodes.last_mut() {
+                    Some(episode) if time - episode.start <= smoothed_rtt => {
+                        episode.packets += 1;
+                        episode.bytes += bytes;
+                    }
+                    _ => analysis.loss_episodes.push(LossEpisode {
+                        start: time,
+                        packets: 1,
+                        bytes,
+                    }),
+                }
+            } else if is_event(&event.name, "packet_sent") {
+                analysis.packets_sent += 1;
+                let mut retransmitted = false;
+                for frame in frames(data) {
+                    match frame_type(frame) {
+                        "stream" => {
+                            let get = |field| frame.get(field).and_then(Value::as_u64);
+                            let (Some(stream_id), Some(offset)) = (get("stream_id"), get("offset"))
+                            else {
+                                continue;
+                            };
+                            let end = offset + get("length").unwrap_or_default();
+                            let sent = stream_sent.entry(stream_id).or_default();
+                            if offset < *sent {
+                                retransmitted = true;
+                                analysis.bytes_retransmitted += end.min(*sent) - offset;
+                            }
+                            *sent = (*sent).max(end);
+                        }
+                        "data_blocked" => _ = blocked_on.insert(None),
+                        "stream_data_blocked" => {
+                            let stream_id = frame.get("stream_id").and_then(Value::as_u64);
+                            blocked_on.insert(Some(stream_id.unwrap_or_default()));
+                        }
+                        _ => {}
+                    }
+                }
+                if retransmitted {
+                    analysis.packets_retransmitted += 1;
+                    analysis.series.push((
+                        time,
+                        "packets_retransmitted",
+                        analysis.packets_retransmitted as f64,
+                    ));
+                }
+                if analysis
+                    .flow_control_blocked
+                    .set(time, !blocked_on.is_empty())
+                {
+                    analysis.series.push((time, "flow_control_blocked", 1.0));
+                }
+            } else if is_event(&event.name, "packet_received") {
+                let header = data.get("header").unwrap_or(&Value::Null);
+                let space = packet_space(header);
+                for frame in frames(data) {
+                    match frame_type(frame) {
+                        "ack" => {
+                            let ranges = frame.get("acked_ranges").and_then(Value::as_array);
+                            for range in ranges.into_iter().flatten() {
+                                let Some(range) = range.as_array() else {
+                                    continue;
+                                };
+                                let lo = range.first().and_then(Value::as_u64);
+                                let hi = range.last().and_then(Value::as_u64);
+                                let (Some(lo), Some(hi)) = (lo, hi) else {
+                                    continue;
+                                };
+                                // a lost packet being acknowledged after all was lost spuriously
+                                let before = lost.len();
+                                lost.retain(|&(s, pn)| s != space || !(lo..=hi).contains(&pn));
+                                analysis.spurious_retransmissions += (before - lost.len()) as u64;
+                            }
+                        }
+                        "max_data" => _ = blocked_on.remove(&None),
+                        "max_stream_data" => {
+                            let stream_id = frame.get("stream_id").and_then(Value::as_u64);
+                            blocked_on.remove(&Some(stream_id.unwrap_or_default()));
+                        }
+                        _ => {}
+                    }
+                }
+                if analysis
+                    .flow_control_blocked
+                    .set(time, !blocked_on.is_empty())
+                {
+                    analysis.series.push((time, "flow_control_blocked", 0.0));
+                }
+            }
+        }
+
+        if let Some((state, since)) = congestion_state {
+            *analysis
+                .congestion_states
+                .entry(state.to_owned())
+                .or_default() += analysis.duration - since;
+        }
+        analysis.congestion_blocked.finish(analysis.duration);
+        analysis.flow_control_blocked.finish(analysis.duration);
+        analysis
+    }
+
+    fn write_series(&self, path: &Path) -> io::Result<()> {
+        let mut csv = BufWriter::new(fs::File::create(path)?);
+        writeln!(csv, "time_ms,metric,value")?;
+        for (time, metric, value) in &self.series {
+            writeln!(csv, "{time:.3},{metric},{value}")?;
+        }
+        csv.flush()
+    }
+
+    fn print_summary(&self) {
+        let percent = |part: f64| {
+            if self.duration > 0.0 {
+                part / self.duration * 100.0
+            } else {
+                0.0
+            }
+        };
+        println!("  duration: {:.3} ms", self.duration);
+        if !self.parameters.is_empty() {
+            println!("  congestion control parameters:");
+            for (name, value) in &self.parameters {
+                println!("    {name}: {value}");
+            }
+        }
+        for (state, time) in &self.congestion_states {
+            println!(
+                "  in congestion state {state}: {time:.3} ms ({:.1}%)",
+                percent(*time)
+            );
+        }
+        println!(
+            "  packets: {} sent, {} lost ({} bytes), {} retransmitted ({} bytes)",
+            self.packets_sent,
+            self.packets_lost,
+            self.bytes_lost,
+            self.packets_retransmitted,
+            self.bytes_retransmitted,
+        );
+        println!(
+            "  spurious retransmissions: {} of {} lost packets were acked later",
+            self.spurious_retransmissions, self.packets_lost
+        );
+        println!("  loss events: {}", self.loss_episodes.len());
+        for episode in &self.loss_episodes {
+            println!(
+                "    at {:.3} ms: {} packets, {} bytes",
+                episode.start, episode.packets, episode.bytes
+            );
+        }
+        for (trigger, count) in &self.loss_triggers {
+            println!("    trigger {trigger}: {count}");
+        }
+        println!(
+            "  blocked on congestion: {:.3} ms ({:.1}%)",
+            self.congestion_blocked.total,
+            percent(self.congestion_blocked.total)
+        );
+        println!(
+            "  blocked on flow control: {:.3} ms ({:.1}%)",
+            self.flow_control_blocked.total,
+            percent(self.flow_control_blocked.total)
+        );
+    }
+}
@@ -0,0 +1,134 @@
+//! The summary every binary prints when a run ends.
+//!
+//! A report is a flat list of named values, so results of different binaries
+//! can be put side by side. It is printed to stdout, logged as one record with
+//! target [`REPORT`] and optionally written to a JSON file.
+
+use std::{
+    fs, io,
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use clap::Args;
+use serde_json::{Map, Value};
+
+use crate::resources::Usage;
+
+pub const REPORT: &str = "report";
+
+#[derive(Args, Clone, Debug)]
+pub struct ReportOptions {
+    /// Also write the run report as JSON to this file
+    #[arg(long, value_name = "PATH")]
+    pub report: Option<PathBuf>,
+}
+
+pub struct Report {
+    bin: String,
+    start: Usage,
+    fields: Map<String, Value>,
+}
+
+impl Report {
+    /// Start accounting resources for a run of `bin`.
+    pub fn start(bin: &str) -> Self {
+        Self {
+            bin: bin.to_owned(),
+            start: Usage::now(),
+            fields: Map::new(),
+        }
+    }
+
+    pub fn set(&mut self, key: &str, value: impl Into<Value>) -> &mut Self {
+        self.fields.insert(key.to_owned(), value.into());
+        self
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.fields.get(key)
+    }
+
+    /// Set `{name}_p50_{unit}`, `_p90_`, `_p99_` and `_max_` of `samples`,
+    /// nothing if there are none.
+    pub fn set_percentiles(&mut self, name: &str, unit: &str, samples: &[f64]) -> &mut Self {
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        for (label, p) in [("p50", 0.5), ("p90", 0.9), ("p99", 0.99), ("max", 1.0)] {
+            if let Some(value) = percentile(&sorted, p) {
+                self.set(&format!("{name}_{label}_{unit}"), value);
+            }
+        }
+        self
+    }
+
+    /// Add the resource usage of the run, `bytes` being the payload it moved,
+    /// and publish the report.
+    pub fn finish(mut self, bytes: u64, options: &ReportOptions) -> io::Result<()> {
+        self.fields.extend(Usage::now().since(&self.start, bytes));
+
+        let report = Value::Object(self.fields);
+        tracing::info!(target: REPORT, bin = self.bin, %report);
+
+        println!("{} report:", self.bin);
+        let Value::Object(fields) = &report else {
+            unreachable!()
+        };
+        let width = fields.keys().map(String::len).max().unwrap_or_default();
+        for (key, value) in fields {
+            match value {
+                Value::Null => {}
+                Value::Number(n) if n.is_f64() => {
+                    println!("  {key:width$}  {:.4}", n.as_f64().unwrap())
+                }
+                value => println!("  {key:width$}  {value}"),
+            }
+        }
+
+        if let Some(path) = &options.report {
+            fs::write(path, serde_json::to_string_pretty(&report)? + "\n")?;
+        }
+        Ok(())
+    }
+}
+
+/// The nearest-rank percentile `p` (0 to 1) of ascending `sorted`.
+pub fn percentile(sorted: &[f64], p: f64) -> Option<f64> {
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted.get(rank.clamp(1, sorted.len().max(1)) - 1).copied()
+}
+
+/// What a server counts while serving, reported when it shuts down.
+#[derive(Debug, Default)]
+pub struct ServerStats {
+    pub connections: AtomicU64,
+    /// Payload bytes echoed or served.
+    pub bytes: AtomicU64,
+    /// QUIC datagrams received, see [`crate::datagram`].
+    pub datagrams: AtomicU64,
+    pub datagram_bytes: AtomicU64,
+    /// WebTransport sessions accepted, see [`crate::webtransport`].
+    pub sessions: AtomicU64,
+}
+
+impl ServerStats {
+    pub fn connections(&self) -> u64 {
+        self.connections.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn datagrams(&self) -> u64 {
+        self.datagrams.load(Ordering::Relaxed)
+    }
+
+    pub fn datagram_bytes(&self) -> u64 {
+        self.datagram_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn sessions(&self) -> u64 {
+        self.sessions.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,62 @@
+//! How `h3-client` shapes its requests and where it sends them, curl-style:
+//! the method and headers of every request, and an address and server name
+//! other than the ones of the URI, whose authority the requests keep.
+
+use clap::Args;
+use http::{HeaderName, HeaderValue, Method, Uri};
+
+use crate::{fetch::Error, report::Report};
+
+#[derive(Args, Clone, Debug, Default)]
+pub struct RequestOptions {
+    /// Add this header to every request, as `Name: value`
+    #[arg(short = 'H', long = "header", value_name = "HEADER", value_parser = parse_header)]
+    pub headers: Vec<(HeaderName, HeaderValue)>,
+    /// Send requests with this method rather than GET
+    #[arg(short = 'X', long = "request", value_name = "METHOD")]
+    pub method: Option<Method>,
+    /// Connect to this host rather than the one of the URI
+    #[arg(long, value_name = "HOST:PORT")]
+    pub connect_to: Option<String>,
+    /// Present this server name in the handshake rather than the host of the
+    /// URI
+    #[arg(long, value_name = "NAME")]
+    pub sni: Option<String>,
+}
+
+fn parse_header(header: &str) -> Result<(HeaderName, HeaderValue), Error> {
+    let (name, value) = header.split_once(':').ok_or("a header is `Name: value`")?;
+    Ok((name.trim().parse()?, value.trim().parse()?))
+}
+
+impl RequestOptions {
+    /// The host and port to connect to for `uri`.
+    pub fn destination<'a>(&'a self, uri: &'a Uri) -> Result<(&'a str, u16), Error> {
+        let Some(connect_to) = &self.connect_to else {
+            let auth = uri.authority().ok_or("missing host")?;
+            return Ok((auth.host(), auth.port_u16().unwrap_or(443)));
+        };
+        let (host, port) = connect_to
+            .rsplit_once(':')
+            .ok_or("--connect-to is HOST:PORT")?;
+        Ok((host, port.parse()?))
+    }
+
+    pub fn method(&self) -> Method {
+        self.method.clone().unwrap_or(Method::GET)
+    }
+
+    pub fn report(&self, report: &mut Report) {
+        report
+            .set("method", self.method().as_str())
+            .set(
+                "headers",
+                self.headers
+                    .iter()
+                    .map(|(name, _)| name.as_str())
+                    .collect::<Vec<_>>(),
+            )
+            .set("connect_to", self.connect_to.clone())
+            .set("sni", self.sni.clone());
+    }
+}
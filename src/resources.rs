@@ -0,0 +1,187 @@
+//! What a run costs: CPU time, memory, context switches and, on Linux, the
+//! syscall and UDP datagram counters the kernel exposes.
+//!
+//! gm-quic's UDP syscalls can't be counted from inside the process, so the
+//! closest observable numbers are reported instead: the `syscr`/`syscw`
+//! counters of `/proc/self/io`, which count read(2)/write(2)-family calls but
+//! not the sendmsg, sendmmsg and recvmmsg UDP goes through, and the host-wide
+//! UDP datagram counters from `/proc/net/snmp{,6}`, which include other
+//! traffic.
+
+use std::time::{Duration, Instant};
+
+use serde_json::{Map, Value, json};
+
+/// A snapshot of the counters, see [`Usage::since`].
+#[derive(Debug, Clone)]
+pub struct Usage {
+    at: Instant,
+    user: Duration,
+    system: Duration,
+    /// In bytes, the high-water mark of the whole process lifetime.
+    max_rss: u64,
+    voluntary_switches: u64,
+    involuntary_switches: u64,
+    syscr: Option<u64>,
+    syscw: Option<u64>,
+    udp_in_datagrams: Option<u64>,
+    udp_out_datagrams: Option<u64>,
+}
+
+impl Usage {
+    pub fn now() -> Self {
+        // SAFETY: getrusage only writes into the zeroed struct we hand it
+        let rusage = unsafe {
+            let mut rusage = std::mem::zeroed::<libc::rusage>();
+            libc::getrusage(libc::RUSAGE_SELF, &mut rusage);
+            rusage
+        };
+        let timeval = |tv: libc::timeval| {
+            Duration::from_secs(tv.tv_sec as u64) + Duration::from_micros(tv.tv_usec as u64)
+        };
+        // Linux reports kilobytes, macOS bytes
+        let rss_unit = if cfg!(target_os = "macos") { 1 } else { 1024 };
+
+        let (syscr, syscw) = proc_self_io();
+        let (udp_in_datagrams, udp_out_datagrams) = proc_net_udp();
+        Self {
+            at: Instant::now(),
+            user: timeval(rusage.ru_utime),
+            system: timeval(rusage.ru_stime),
+            max_rss: rusage.ru_maxrss as u64 * rss_unit,
+            voluntary_switches: rusage.ru_nvcsw as u64,
+            involuntary_switches: rusage.ru_nivcsw as u64,
+            syscr,
+            syscw,
+            udp_in_datagrams,
+            udp_out_datagrams,
+        }
+    }
+
+    /// The cost of the work done since `start`, `bytes` being the payload it
+    /// moved, as report fields.
+    pub fn since(&self, start: &Usage, bytes: u64) -> Map<String, Value> {
+        let delta = |now: Option<u64>, then: Option<u64>| Some(now?.saturating_sub(then?));
+        let wall = self.at - start.at;
+        let user = self.user.saturating_sub(start.user);
+        let system = self.system.saturating_sub(start.system);
+        let cpu = (user + system).as_secs_f64();
+
+        let mut fields = Map::new();
+        let mut set = |key: &str, value: Value| _ = fields.insert(key.to_owned(), value);
+        set("wall_time_s", json!(wall.as_secs_f64()));
+        set("user_time_s", json!(user.as_secs_f64()));
+        set("system_time_s", json!(system.as_secs_f64()));
+        set("cpu_utilization", json!(cpu / wall.as_secs_f64()));
+        set("max_rss_bytes", json!(self.max_rss));
+        set(
+            "voluntary_context_switches",
+            json!(self.voluntary_switches - start.voluntary_switches),
+        );
+        set(
+            "involuntary_context_switches",
+            json!(self.involuntary_switches - start.involuntary_switches),
+        );
+        set("proc_io_syscr", json!(delta(self.syscr, start.syscr)));
+        set("proc_io_syscw", json!(delta(self.syscw, start.syscw)));
+        set(
+            "host_udp_in_datagrams",
+            json!(delta(self.udp_in_datagrams, start.udp_in_datagrams)),
+        );
+        set(
+            "host_udp_out_datagrams",
+            json!(delta(self.udp_out_datagrams, start.udp_out_datagrams)),
+        );
+        set("bytes", json!(bytes));
+        if cpu > 0.0 {
+            set("bytes_per_cpu_second", json!(bytes as f64 / cpu));
+        }
+        if let (Some(hz), true) = (cpu_hz(), bytes > 0) {
+            // an estimate: frequency scaling and SMT are not accounted for
+            set("cpu_cycles_per_byte", json!(cpu * hz / bytes as f64));
+        }
+        fields
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn proc_self_io() -> (Option<u64>, Option<u64>) {
+    let Ok(io) = std::fs::read_to_string("/proc/self/io") else {
+        return (None, None);
+    };
+    let field = |name: &str| {
+        io.lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+            .and_then(|value| value.trim().parse().ok())
+    };
+    (field("syscr"), field("syscw"))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn proc_self_io() -> (Option<u64>, Option<u64>) {
+    (None, None)
+}
+
+/// Host wide UDP datagrams received and sent, over IPv4 and IPv6.
+#[cfg(target_os = "linux")]
+fn proc_net_udp() -> (Option<u64>, Option<u64>) {
+    let snmp = std::fs::read_to_string("/proc/net/snmp").unwrap_or_default();
+    let snmp6 = std::fs::read_to_string("/proc/net/snmp6").unwrap_or_default();
+
+    // `Udp: InDatagrams NoPorts ...` followed by `Udp: 24 0 ...`
+    let udp = snmp
+        .lines()
+        .filter(|line| line.starts_with("Udp:"))
+        .collect::<Vec<_>>();
+    let v4 = |name: &str| {
+        let (names, values) = (udp.first()?, udp.get(1)?);
+        let idx = names.split_whitespace().position(|n| n == name)?;
+        values.split_whitespace().nth(idx)?.parse::<u64>().ok()
+    };
+    let v6 = |name: &str| {
+        snmp6
+            .lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|value| value.trim().parse::<u64>().ok())
+    };
+    let sum = |a: Option<u64>, b: Option<u64>| match (a, b) {
+        (None, None) => None,
+        (a, b) => Some(a.unwrap_or_default() + b.unwrap_or_default()),
+    };
+    let received = sum(v4("InDatagrams"), v6("Udp6InDatagrams"));
+    let sent = sum(v4("OutDatagrams"), v6("Udp6OutDatagrams"));
+    (received, sent)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn proc_net_udp() -> (Option<u64>, Option<u64>) {
+    (None, None)
+}
+
+/// Maximum clock rate of the first CPU, or the rate it runs at right now
+/// where cpufreq isn't there, as in most VMs.
+#[cfg(target_os = "linux")]
+fn cpu_hz() -> Option<f64> {
+    let max = "/sys/devices/system/cpu/cpu0/cpufreq/cpuinfo_max_freq";
+    if let Some(khz) = std::fs::read_to_string(max)
+        .ok()
+        .and_then(|khz| khz.trim().parse::<f64>().ok())
+    {
+        return Some(khz * 1e3);
+    }
+    let cpuinfo = std::fs::read_to_string("/proc/cpuinfo").ok()?;
+    let mhz = cpuinfo
+        .lines()
+        .find(|line| line.starts_with("cpu MHz"))?
+        .split_once(':')?
+        .1
+        .trim()
+        .parse::<f64>()
+        .ok()?;
+    Some(mhz * 1e6)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn cpu_hz() -> Option<f64> {
+    None
+}
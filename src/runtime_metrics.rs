@@ -0,0 +1,83 @@
+//! Periodic samples of the tokio runtime, to tell when task scheduling rather
+//! than the network is the bottleneck.
+//!
+//! Poll counts, local queue depths and the blocking pool are only available
+//! with `--cfg tokio_unstable` (set in `.cargo/config.toml`) and read as zero
+//! otherwise.
+
+use std::time::{Duration, Instant};
+
+use tokio::runtime::{Handle, RuntimeMetrics as Metrics};
+
+use crate::trace_events::RuntimeMetrics;
+
+/// Emit a [`RuntimeMetrics`] record every `interval` on the current runtime.
+pub fn spawn(interval: Duration) {
+    let metrics = Handle::current().metrics();
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        ticker.tick().await;
+
+        let mut last = Counters::read(&metrics);
+        loop {
+            ticker.tick().await;
+            let now = Counters::read(&metrics);
+            sample(&metrics, &last, &now).emit();
+            last = now;
+        }
+    });
+}
+
+/// Cumulative counters, sampled to compute deltas.
+struct Counters {
+    at: Instant,
+    busy: Duration,
+    polls: u64,
+}
+
+impl Counters {
+    fn read(metrics: &Metrics) -> Self {
+        let workers = 0..metrics.num_workers();
+        #[cfg(tokio_unstable)]
+        let polls = workers
+            .clone()
+            .map(|worker| metrics.worker_poll_count(worker))
+            .sum();
+        #[cfg(not(tokio_unstable))]
+        let polls = 0;
+        Self {
+            at: Instant::now(),
+            busy: workers
+                .map(|worker| metrics.worker_total_busy_duration(worker))
+                .sum(),
+            polls,
+        }
+    }
+}
+
+fn sample(metrics: &Metrics, last: &Counters, now: &Counters) -> RuntimeMetrics {
+    let workers = metrics.num_workers();
+    let wall = (now.at - last.at).as_secs_f64() * workers as f64;
+    let busy = now.busy.saturating_sub(last.busy).as_secs_f64();
+
+    let sample = RuntimeMetrics {
+        workers: workers as u64,
+        alive_tasks: metrics.num_alive_tasks() as u64,
+        busy_ratio: if wall > 0.0 { busy / wall } else { 0.0 },
+        polls: now.polls.saturating_sub(last.polls),
+        global_queue_depth: metrics.global_queue_depth() as u64,
+        ..RuntimeMetrics::default()
+    };
+    #[cfg(tokio_unstable)]
+    let sample = RuntimeMetrics {
+        local_queue_depth: (0..workers)
+            .map(|worker| metrics.worker_local_queue_depth(worker) as u64)
+            .sum(),
+        blocking_threads: metrics.num_blocking_threads() as u64,
+        idle_blocking_threads: metrics.num_idle_blocking_threads() as u64,
+        blocking_queue_depth: metrics.blocking_queue_depth() as u64,
+        ..sample
+    };
+    sample
+}
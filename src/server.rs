@@ -1,13 +1,17 @@
 use std::{io, net::SocketAddr, path::PathBuf, sync::Arc};
 
+use big_file_test::{
+    congestion::CongestionOptions,
+    echo,
+    logging::{LogDefaults, LogOptions},
+    report::{Report, ReportOptions, ServerStats},
+    tls::{ClientAuthOptions, TicketOptions},
+};
 use clap::Parser;
-use gm_quic::{Connection, StreamReader, StreamWriter};
 use qlog::telemetry::{
     Log,
     handy::{DefaultSeqLogger, NullLogger},
 };
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tracing::Instrument;
 
 #[derive(Parser)]
 struct Options {
@@ -15,15 +19,32 @@ struct Options {
     bind: SocketAddr,
     #[arg(long)]
     qlog_dir: Option<PathBuf>,
+    /// Certificate chain (PEM), see `certgen`
+    #[arg(long, default_value = "server.crt")]
+    cert: PathBuf,
+    /// Private key for the certificate (PEM)
+    #[arg(long, default_value = "server.key")]
+    key: PathBuf,
+    #[command(flatten)]
+    client_auth: ClientAuthOptions,
+    #[command(flatten)]
+    tickets: TicketOptions,
+    #[command(flatten)]
+    congestion: CongestionOptions,
+    #[command(flatten)]
+    log: LogOptions,
+    #[command(flatten)]
+    report: ReportOptions,
 }
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::INFO)
-        .init();
-
     let options = Options::parse();
+    options
+        .log
+        .init(env!("CARGO_BIN_NAME"), LogDefaults::INFO, &[])?;
+
+    let mut report = Report::start(env!("CARGO_BIN_NAME"));
 
     let qlogger = options
         .qlog_dir
@@ -33,73 +54,34 @@ async fn main() -> io::Result<()> {
             |dir| Arc::new(DefaultSeqLogger::new(dir.clone())),
         );
 
-    let server = gm_quic::QuicServer::builder()
-        .without_client_cert_verifier()
-        .with_single_cert(
-            include_bytes!("../server.crt"),
-            include_bytes!("../server.key"),
-        )
-        .with_parameters(server_stream_unlimited_parameters())
-        .with_qlog(qlogger)
-        .listen(options.bind)?;
+    let server = echo::listen(
+        options.bind,
+        &std::fs::read(&options.cert)?,
+        &std::fs::read(&options.key)?,
+        options.client_auth.verifier()?,
+        &options.tickets,
+        &options.congestion,
+        qlogger,
+    )?;
 
     tracing::info!("listening on {:?}", server.addresses());
 
-    async fn for_each_stream(mut reader: StreamReader, mut writer: StreamWriter) -> io::Result<()> {
-        let mut buffer = [0; 4096];
-
-        let mut echoed = 0;
-        loop {
-            match reader.read(&mut buffer).await? {
-                0 => break,
-                n => {
-                    echoed += n;
-                    writer.write_all(&buffer[..n]).await?
-                }
-            }
-        }
-
-        // let mut buf = vec![];
-        // reader.read_to_end(&mut buf).await?;
-        // writer.write_all(&buf).await?;
-
-        tracing::info!(echoed, "transfer completed, waiting for ack");
-        writer.shutdown().await?;
-        tracing::info!("done");
-        Ok(())
-    }
-
-    async fn for_each_conn(conn: Arc<Connection>) -> io::Result<()> {
-        loop {
-            let (stream_id, (reader, writer)) = conn.accept_bi_stream().await?.unwrap();
-
-            tokio::spawn(
-                for_each_stream(reader, writer)
-                    .instrument(tracing::info_span!("stream", %stream_id)),
-            );
-        }
-    }
-
-    while let Ok((connection, pathway)) = server.accept().await {
-        tracing::info!(%pathway, "new connection");
-        tokio::spawn(
-            for_each_conn(connection)
-                .instrument(tracing::info_span!("conn", from = %pathway.remote())),
-        );
-    }
-
-    Ok(())
-}
-
-pub fn server_stream_unlimited_parameters() -> gm_quic::ServerParameters {
-    let mut params = gm_quic::ServerParameters::default();
-
-    params.set_initial_max_streams_bidi(100u32);
-    params.set_initial_max_streams_uni(100u32);
-    params.set_initial_max_data(1u32 << 20);
-    params.set_initial_max_stream_data_uni(1u32 << 20);
-    params.set_initial_max_stream_data_bidi_local(1u32 << 20);
-    params.set_initial_max_stream_data_bidi_remote(1u32 << 20);
-
-    params
+    let stats = Arc::new(ServerStats::default());
+    echo::serve(&server, stats.clone(), async {
+        _ = tokio::signal::ctrl_c().await;
+        tracing::info!("interrupted");
+    })
+    .await;
+
+    // everything echoed was received once and sent once
+    let echoed = stats.bytes();
+    report
+        .set("connections", stats.connections())
+        .set("echoed_bytes", echoed)
+        .set("datagrams", stats.datagrams())
+        .set("datagram_bytes", stats.datagram_bytes());
+    options.client_auth.report(&mut report);
+    options.tickets.report(&mut report);
+    options.congestion.report(&mut report);
+    report.finish(echoed * 2, &options.report)
 }
@@ -0,0 +1,146 @@
+//! The UDP sockets of `client`. gm-quic binds them itself without telling
+//! where, opens paths only from sockets it knows and counts nothing per path,
+//! so `client` binds them through [`Sockets`] to move its connection, give it
+//! more paths and see what each of them carried.
+
+use std::{
+    collections::BTreeMap,
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, ready},
+};
+
+use bytes::BytesMut;
+use gm_quic::{
+    Connection, EndpointAddr, Interfaces, Link, PacketHeader, Pathway, ProductQuicInterface,
+    QuicInterface, handy::UdpSocketController,
+};
+
+/// What went between a local and a remote address.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Traffic {
+    pub sent_bytes: u64,
+    pub sent_packets: u64,
+    pub received_bytes: u64,
+    pub received_packets: u64,
+}
+
+type Links = Arc<Mutex<BTreeMap<(SocketAddr, SocketAddr), Traffic>>>;
+
+/// Binds sockets as gm-quic does, noting their local addresses and counting
+/// what they send and receive. Clones share them.
+#[derive(Clone, Debug, Default)]
+pub struct Sockets {
+    local_addrs: Arc<Mutex<Vec<SocketAddr>>>,
+    links: Links,
+}
+
+impl ProductQuicInterface for Sockets {
+    fn bind(&self, addr: SocketAddr) -> io::Result<Arc<dyn QuicInterface>> {
+        let socket = UdpSocketController::bind(addr)?;
+        self.local_addrs.lock().unwrap().push(socket.local_addr()?);
+        Ok(Arc::new(Counted {
+            socket,
+            links: self.links.clone(),
+        }))
+    }
+}
+
+impl Sockets {
+    /// Where the sockets bound so far are.
+    pub fn local_addrs(&self) -> Vec<SocketAddr> {
+        self.local_addrs.lock().unwrap().clone()
+    }
+
+    /// What went between every local and remote address so far, named as
+    /// gm-quic names its links.
+    pub fn traffic(&self) -> Vec<(String, Traffic)> {
+        let links = self.links.lock().unwrap();
+        links
+            .iter()
+            .map(|(&(local, remote), traffic)| (Link::new(local, remote).to_string(), *traffic))
+            .collect()
+    }
+
+    /// Open a path of `connection` from a new socket at `local` to `remote`,
+    /// returning the address the socket got.
+    pub fn add_path(
+        &self,
+        connection: &Connection,
+        local: SocketAddr,
+        remote: SocketAddr,
+    ) -> io::Result<SocketAddr> {
+        let socket = ProductQuicInterface::bind(self, local)?;
+        let local = socket.local_addr()?;
+        // gm-quic receives on it from now on, and finds it for the path
+        drop(Interfaces::add(socket)?);
+        connection.add_path(Link::new(local, remote), pathway(local, remote))?;
+        Ok(local)
+    }
+}
+
+/// The path from `local` to `remote`, as gm-quic names it.
+pub fn pathway(local: SocketAddr, remote: SocketAddr) -> Pathway {
+    Pathway::new(EndpointAddr::direct(local), EndpointAddr::direct(remote))
+}
+
+struct Counted {
+    socket: UdpSocketController,
+    links: Links,
+}
+
+impl Counted {
+    fn count(&self, link: Link, count: impl FnOnce(&mut Traffic)) {
+        let mut links = self.links.lock().unwrap();
+        count(links.entry((link.src(), link.dst())).or_default());
+    }
+}
+
+impl QuicInterface for Counted {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    fn max_segment_size(&self) -> usize {
+        QuicInterface::max_segment_size(&self.socket)
+    }
+
+    fn max_segments(&self) -> usize {
+        QuicInterface::max_segments(&self.socket)
+    }
+
+    fn poll_send(
+        &self,
+        cx: &mut Context,
+        pkts: &[io::IoSlice],
+        hdr: PacketHeader,
+    ) -> Poll<io::Result<usize>> {
+        let sent = ready!(QuicInterface::poll_send(&self.socket, cx, pkts, hdr))?;
+        // every slice is a datagram, split into segments of `seg_size`
+        let seg_size = usize::from(hdr.seg_size()).max(1);
+        self.count(hdr.link(), |traffic| {
+            for pkt in &pkts[..sent] {
+                traffic.sent_bytes += pkt.len() as u64;
+                traffic.sent_packets += pkt.len().div_ceil(seg_size) as u64;
+            }
+        });
+        Poll::Ready(Ok(sent))
+    }
+
+    fn poll_recv(
+        &self,
+        cx: &mut Context,
+        pkts: &mut Vec<BytesMut>,
+        hdrs: &mut [PacketHeader],
+    ) -> Poll<io::Result<usize>> {
+        let received = ready!(QuicInterface::poll_recv(&self.socket, cx, pkts, hdrs))?;
+        for hdr in &hdrs[..received] {
+            self.count(hdr.link(), |traffic| {
+                traffic.received_bytes += u64::from(hdr.seg_size());
+                traffic.received_packets += 1;
+            });
+        }
+        Poll::Ready(Ok(received))
+    }
+}
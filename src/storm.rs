@@ -0,0 +1,173 @@
+//! The handshake storm of `h3-client`: connections are opened, optionally
+//! used for one request and closed again, so connection establishment is all
+//! the server does.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use clap::Args;
+use http::Uri;
+use indicatif::ProgressBar;
+use tokio::{
+    sync::Semaphore,
+    task::{JoinError, JoinSet},
+    time::{self, MissedTickBehavior},
+};
+use tracing::{Instrument, info_span};
+
+use crate::{
+    args,
+    eyeballs::{Candidates, Winners},
+    failure::{Failure, Failures},
+    fetch::{self, ByHandshake, Connector, Error, Probe},
+    report::Report,
+};
+
+#[derive(Args, Clone, Debug)]
+pub struct StormOptions {
+    /// Instead of keeping `--conns` connections for `--reqs` requests each,
+    /// open and close this many connections
+    #[arg(long, value_name = "HANDSHAKES")]
+    pub storm: Option<usize>,
+    /// Start handshakes at this rate, as fast as `--storm-concurrency` allows
+    /// if omitted
+    #[arg(
+        long,
+        value_name = "PER_SECOND",
+        requires = "storm",
+        value_parser = args::positive
+    )]
+    pub storm_rate: Option<f64>,
+    /// Handshakes in flight at most
+    #[arg(long, value_name = "HANDSHAKES", default_value = "64")]
+    pub storm_concurrency: usize,
+    /// GET the URI once on every connection before closing it
+    #[arg(long, requires = "storm")]
+    pub storm_request: bool,
+    /// Count a handshake (and its request) as failed after this long
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value = "10",
+        value_parser = args::positive
+    )]
+    pub storm_timeout: f64,
+}
+
+/// What a storm came to.
+#[derive(Debug, Default)]
+pub struct Storm {
+    /// Seconds from starting to connect until requests could be sent, of the
+    /// connections that succeeded.
+    pub handshakes: Vec<f64>,
+    /// Seconds until the connections that succeeded were of use, see
+    /// [`Probe::time`].
+    pub by_handshake: ByHandshake,
+    /// Where the connections that succeeded went.
+    pub winners: Winners,
+    /// How many connections failed for each reason, none tried again.
+    pub failures: Failures,
+    /// Body bytes of the requests.
+    pub bytes: u64,
+    /// Seconds from the first handshake starting to the last one ending.
+    pub elapsed: f64,
+}
+
+impl Storm {
+    /// `baseline` is what a connection took before there was a session to
+    /// resume.
+    pub fn report(&self, report: &mut Report, baseline: Option<f64>) {
+        let failed = self.failures.by_class.values().sum::<usize>();
+        report
+            .set("handshakes", self.handshakes.len() + failed)
+            .set("failed_handshakes", failed)
+            .set(
+                "handshakes_per_s",
+                self.handshakes.len() as f64 / self.elapsed,
+            );
+        self.failures.report(report);
+        let millis = self
+            .handshakes
+            .iter()
+            .map(|secs| secs * 1e3)
+            .collect::<Vec<_>>();
+        report.set_percentiles("handshake", "ms", &millis);
+        self.by_handshake.report(report, "time", baseline);
+        self.winners.report(report);
+    }
+
+    fn record(&mut self, done: Result<Result<Probe, Error>, JoinError>, pb: &ProgressBar) {
+        match done {
+            Ok(Ok(probe)) => {
+                self.handshakes.push(probe.connect_time);
+                self.by_handshake.record(probe.handshake, probe.time());
+                self.winners.record(probe.addr);
+                self.bytes += probe.bytes;
+                pb.inc(1);
+            }
+            Ok(Err(error)) => {
+                tracing::warn!(%error, "connection failed");
+                self.failures.record(Failure::of_request(&error), false);
+                pb.dec_length(1);
+            }
+            Err(error) if error.is_panic() => std::panic::resume_unwind(error.into_panic()),
+            Err(error) => panic!("{error}"),
+        }
+    }
+}
+
+/// Open `options.storm` connections to the first of `candidates` to answer
+/// for the host of `uri`, ticking `pb` for every one that ends.
+pub async fn storm(
+    connector: Connector,
+    candidates: Candidates,
+    uri: Uri,
+    options: &StormOptions,
+    pb: ProgressBar,
+) -> Storm {
+    let handshakes = options.storm.unwrap_or_default();
+    pb.inc_length(handshakes as u64);
+
+    let concurrency = Arc::new(Semaphore::new(options.storm_concurrency.max(1)));
+    let mut rate = options.storm_rate.map(|rate| {
+        let mut interval = time::interval(Duration::from_secs_f64(1.0 / rate));
+        // a storm held back by the concurrency doesn't catch up in a burst
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        interval
+    });
+    let timeout = Duration::from_secs_f64(options.storm_timeout);
+
+    let mut storm = Storm::default();
+    let mut connections = JoinSet::new();
+    let start = Instant::now();
+    for idx in 0..handshakes {
+        if let Some(rate) = &mut rate {
+            rate.tick().await;
+        }
+        let permit = concurrency.clone().acquire_owned().await.unwrap();
+        let connector = connector.clone();
+        let candidates = candidates.clone();
+        let uri = uri.clone();
+        let request = options.storm_request;
+        connections.spawn(
+            async move {
+                let probe = fetch::probe(&connector, &candidates, &uri, request);
+                let result = time::timeout(timeout, probe).await;
+                drop(permit);
+                result.unwrap_or_else(|elapsed| Err(elapsed.into()))
+            }
+            .instrument(info_span!("handshake", idx)),
+        );
+        while let Some(done) = connections.try_join_next() {
+            storm.record(done, &pb);
+        }
+    }
+    while let Some(done) = connections.join_next().await {
+        storm.record(done, &pb);
+    }
+    storm.elapsed = start.elapsed().as_secs_f64();
+    pb.finish();
+    storm
+}
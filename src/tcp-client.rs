@@ -1,70 +1,135 @@
-use std::{io, path::PathBuf, sync::Arc};
+use std::{path::PathBuf, sync::Arc, time::Instant};
 
+use big_file_test::{
+    logging::{LogDefaults, LogFormat, LogOptions},
+    report::{Report, ReportOptions},
+    tcp::{TcpInfo, TcpOptions},
+    tcp_echo::{self, Client},
+    tls::{self, ClientCertOptions, TlsOptions},
+    transfer::Progress,
+};
 use clap::Parser;
-use indicatif::{MultiProgress, ProgressBar};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use rustls::pki_types::ServerName;
+use tokio_rustls::TlsConnector;
 
+/// Takes the arguments of `client`, every stream being a TCP connection.
 #[derive(Parser)]
 struct Options {
-    #[arg(long, default_value = "0.0.0.0:0")]
+    #[arg(default_value = "localhost:35467")]
     server: String,
-    #[arg(long)]
+    /// Number of parallel connections, each echoing the whole file
+    #[arg(short = 's', long, visible_alias = "connections", default_value = "4")]
+    streams: usize,
+    #[arg(short = 'f', long, default_value = "rand-file-128M")]
     file: PathBuf,
+    #[arg(short = 'p', long)]
+    progress: bool,
+    #[command(flatten)]
+    tcp: TcpOptions,
+    #[command(flatten)]
+    tls: TlsOptions,
+    /// Root certificates to verify the server with
+    #[arg(long, default_value = "ca.crt")]
+    roots: Vec<PathBuf>,
+    /// Name to verify the server certificate against
+    #[arg(long, default_value = "localhost")]
+    server_name: String,
+    #[command(flatten)]
+    client_cert: ClientCertOptions,
+    /// `--log-file` may also use `{streams}` and `{size}` (file size in MB)
+    #[command(flatten)]
+    log: LogOptions,
+    #[command(flatten)]
+    report: ReportOptions,
 }
 
 #[tokio::main]
-async fn main() -> io::Result<()> {
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::INFO)
-        .init();
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let options = Options::parse();
+    let file_size = options.file.metadata()?.len() / (1024 * 1024);
+    let log_defaults = LogDefaults {
+        filter: "debug",
+        format: LogFormat::Json,
+        file: Some("{bin}-{streams}x{size}M.output"),
+    };
+    options.log.init(
+        env!("CARGO_BIN_NAME"),
+        log_defaults,
+        &[("streams", &options.streams), ("size", &file_size)],
+    )?;
+    run(options)
+        .await
+        .inspect_err(|error| tracing::error!(?error))
+}
+
+async fn run(options: Options) -> Result<(), Box<dyn std::error::Error>> {
+    let mut report = Report::start(env!("CARGO_BIN_NAME"));
     let file = Arc::new(tokio::fs::read(&options.file).await?);
 
-    let (mut reader, mut writer) = tokio::net::TcpStream::connect(options.server)
+    let progress = Arc::new(Progress::new(
+        options.streams,
+        file.len() as u64,
+        options.progress,
+    ));
+
+    let identity = options.client_cert.identity()?;
+    let tls = if options.tls.tls {
+        let config = options
+            .tls
+            .client_config(tls::load_roots(&options.roots)?, identity.as_ref())?;
+        let server_name = ServerName::try_from(options.server_name.clone())?;
+        Some((TlsConnector::from(Arc::new(config)), server_name))
+    } else {
+        None
+    };
+    let client = Arc::new(Client {
+        tcp: options.tcp.clone(),
+        tls,
+    });
+    let server_addr = tokio::net::lookup_host(&options.server)
         .await?
-        .into_split();
+        .next()
+        .ok_or("DNS lookup failed")?;
+    tracing::info!("connecting to {}[{server_addr}]", options.server);
 
-    let pbs = MultiProgress::new();
+    let start_time = Instant::now();
+    let connections =
+        tcp_echo::echo_connections(server_addr, client, file.clone(), progress).await?;
+    let transfer_time = start_time.elapsed().as_secs_f64();
 
-    let download = tokio::spawn({
-        let file = file.clone();
-        let download_pb = ProgressBar::new(file.len() as u64);
-        pbs.add(download_pb.clone());
-        async move {
-            let mut back = vec![];
-            while let Ok(n) = reader.read_buf(&mut back).await {
-                download_pb.inc(n as u64);
-                if n == 0 {
-                    break;
-                }
+    let mut tcp_infos = vec![];
+    let mut handshake_times = vec![];
+    for connection in connections {
+        match connection.tcp_info {
+            Ok(tcp_info) => {
+                tracing::info!(?tcp_info, "connection done");
+                tcp_infos.push(tcp_info);
             }
-            download_pb.finish_with_message("done!");
-
-            assert_eq!(back, *file);
+            Err(error) => tracing::warn!("failed to read TCP_INFO: {}", error),
         }
-    });
-
-    let upload_pb = ProgressBar::new(file.len() as u64);
-    pbs.add(upload_pb.clone());
-
-    let mut file = file.as_slice();
-    while !file.is_empty() {
-        let write = writer.write(file).await?;
-        upload_pb.inc(write as u64);
-        file = &file[write..];
+        if let Some((handshake_time, cipher)) = connection.tls {
+            handshake_times.push(handshake_time);
+            report.set("cipher", cipher);
+        }
+    }
+    if !handshake_times.is_empty() {
+        let mean = handshake_times.iter().sum::<f64>() / handshake_times.len() as f64;
+        report.set("handshake_time_s", mean);
     }
-    upload_pb.set_message("shutdown...");
-    writer.shutdown().await?;
-    upload_pb.finish_with_message("done");
-    download.await?;
 
-    // tracing::info!(
-    //     "done! ↑ {:.4}s({:.4}MB/S), ↓ {:.4}s({:.4}MB/S)",
-    //     upload_sec,
-    //     file.len() as f64 / upload_sec / 1024u32.pow(2) as f64,
-    //     download_sec,
-    //     file.len() as f64 / download_sec / 1024u32.pow(2) as f64
-    // );
+    // every connection uploads the file and downloads it back
+    let bytes = (file.len() * options.streams * 2) as u64;
+    report
+        .set("server", server_addr.to_string())
+        .set("streams", options.streams)
+        .set("file_bytes", file.len())
+        .set("transfer_time_s", transfer_time)
+        .set("throughput_bytes_per_s", bytes as f64 / transfer_time)
+        .set("tls", options.tls.tls)
+        .set("tls_record_size", options.tls.tls_record_size)
+        .set("client_cert", identity.is_some());
+    TcpInfo::report(&tcp_infos, &mut report);
+    report.finish(bytes, &options.report)?;
 
     Ok(())
 }
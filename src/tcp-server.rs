@@ -1,49 +1,83 @@
-use std::{io, net::SocketAddr};
+use std::{io, net::SocketAddr, num::NonZeroUsize, path::PathBuf, sync::Arc};
 
+use big_file_test::{
+    logging::{LogDefaults, LogOptions},
+    report::{Report, ReportOptions, ServerStats},
+    tcp::{TcpInfo, TcpOptions},
+    tcp_echo::{self, Server},
+    tls::{self, ClientAuthOptions, TlsOptions},
+};
 use clap::Parser;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_rustls::TlsAcceptor;
 
 #[derive(Parser)]
 struct Options {
     #[arg(long, default_value = "0.0.0.0:0")]
     bind: SocketAddr,
+    /// Size of the buffer each connection echoes through
+    #[arg(long, value_name = "BYTES", default_value = "4096")]
+    buffer_size: NonZeroUsize,
+    #[command(flatten)]
+    tcp: TcpOptions,
+    #[command(flatten)]
+    tls: TlsOptions,
+    /// Certificate for TLS
+    #[arg(long, default_value = "server.crt")]
+    cert: PathBuf,
+    /// Private key for the certificate
+    #[arg(long, default_value = "server.key")]
+    key: PathBuf,
+    #[command(flatten)]
+    client_auth: ClientAuthOptions,
+    #[command(flatten)]
+    log: LogOptions,
+    #[command(flatten)]
+    report: ReportOptions,
 }
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::INFO)
-        .init();
-
     let options = Options::parse();
+    options
+        .log
+        .init(env!("CARGO_BIN_NAME"), LogDefaults::INFO, &[])?;
+
+    let mut report = Report::start(env!("CARGO_BIN_NAME"));
 
-    let listener = tokio::net::TcpListener::bind(options.bind).await?;
+    let acceptor = if options.tls.tls {
+        let config = options.tls.server_config(
+            tls::load_certs(&options.cert)?,
+            tls::load_key(&options.key)?,
+            options.client_auth.verifier()?,
+        )?;
+        Some(TlsAcceptor::from(Arc::new(config)))
+    } else {
+        None
+    };
 
+    let listener = tcp_echo::listen(&options.tcp, options.bind)?;
     tracing::info!("listening on {:?}", listener.local_addr()?);
 
-    loop {
-        let (stream, peer) = listener.accept().await?;
-        tokio::spawn(async move {
-            let (mut reader, mut writer) = stream.into_split();
-            let mut buffer = [0; 4096];
-
-            let mut echoed = 0;
-            loop {
-                match reader.read(&mut buffer).await {
-                    Ok(0) => break,
-                    Ok(n) => {
-                        echoed += n;
-                        writer.write_all(&buffer[..n]).await?;
-                    }
-                    Err(e) => {
-                        tracing::error!("failed to read from socket; err = {:?}", e);
-                        break;
-                    }
-                }
-            }
-
-            tracing::info!("echoed {} bytes to {}", echoed, peer);
-            io::Result::Ok(())
-        });
-    }
+    let server = Arc::new(Server {
+        tcp: options.tcp.clone(),
+        tls: acceptor,
+        buffer_size: options.buffer_size.get(),
+    });
+    let stats = Arc::new(ServerStats::default());
+    let tcp_infos = tcp_echo::serve(listener, server, stats.clone(), async {
+        _ = tokio::signal::ctrl_c().await;
+        tracing::info!("interrupted");
+    })
+    .await?;
+
+    // everything echoed was received once and sent once
+    let echoed = stats.bytes();
+    report
+        .set("connections", stats.connections())
+        .set("echoed_bytes", echoed)
+        .set("buffer_size", options.buffer_size.get())
+        .set("tls", options.tls.tls);
+    options.client_auth.report(&mut report);
+    TcpInfo::report(&tcp_infos, &mut report);
+    report.finish(echoed * 2, &options.report)
 }
@@ -0,0 +1,206 @@
+//! Socket tuning of the TCP baseline and the `TCP_INFO` it reports, so QUIC
+//! is compared against a properly tuned TCP with transport level stats.
+//!
+//! `TCP_CONGESTION`, `TCP_NOTSENT_LOWAT` and `TCP_INFO` are Linux only.
+
+use std::{io, net::SocketAddr, time::Duration};
+
+use clap::Args;
+use tokio::net::{TcpSocket, TcpStream};
+
+use crate::report::Report;
+
+#[derive(Args, Clone, Debug, Default)]
+pub struct TcpOptions {
+    /// Disable Nagle's algorithm (TCP_NODELAY)
+    #[arg(long)]
+    pub nodelay: bool,
+    /// Socket send buffer (SO_SNDBUF), the kernel doubles it
+    #[arg(long, value_name = "BYTES")]
+    pub send_buffer: Option<u32>,
+    /// Socket receive buffer (SO_RCVBUF), the kernel doubles it
+    #[arg(long, value_name = "BYTES")]
+    pub recv_buffer: Option<u32>,
+    /// Congestion control algorithm (TCP_CONGESTION), e.g. cubic, bbr or reno
+    #[arg(long, value_name = "ALGORITHM")]
+    pub congestion: Option<String>,
+    /// Limit of unsent bytes in the send buffer (TCP_NOTSENT_LOWAT)
+    #[arg(long, value_name = "BYTES")]
+    pub notsent_lowat: Option<u32>,
+}
+
+impl TcpOptions {
+    /// A socket for `addr` with the options that have to be set before
+    /// connecting or listening, the buffers decide the window scale.
+    pub fn socket(&self, addr: SocketAddr) -> io::Result<TcpSocket> {
+        let socket = match addr {
+            SocketAddr::V4(_) => TcpSocket::new_v4()?,
+            SocketAddr::V6(_) => TcpSocket::new_v6()?,
+        };
+        if let Some(size) = self.send_buffer {
+            socket.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = self.recv_buffer {
+            socket.set_recv_buffer_size(size)?;
+        }
+        Ok(socket)
+    }
+
+    /// Set the per connection options of an established stream.
+    pub fn apply(&self, stream: &TcpStream) -> io::Result<()> {
+        if self.nodelay {
+            stream.set_nodelay(true)?;
+        }
+        if let Some(algorithm) = &self.congestion {
+            sys::set_congestion(stream, algorithm)?;
+        }
+        if let Some(lowat) = self.notsent_lowat {
+            sys::set_notsent_lowat(stream, lowat)?;
+        }
+        Ok(())
+    }
+}
+
+/// What the kernel knows about a connection, read before it is closed.
+#[derive(Debug, Clone)]
+pub struct TcpInfo {
+    /// Smoothed RTT.
+    pub rtt: Duration,
+    pub rtt_var: Duration,
+    /// Segments retransmitted over the connection lifetime.
+    pub retransmits: u32,
+    /// Congestion window in bytes.
+    pub cwnd: u64,
+    pub congestion: String,
+}
+
+impl TcpInfo {
+    pub fn of(stream: &TcpStream) -> io::Result<Self> {
+        sys::tcp_info(stream)
+    }
+
+    /// Add the mean RTT and cwnd and the total retransmits of `infos`.
+    pub fn report(infos: &[TcpInfo], report: &mut Report) {
+        if infos.is_empty() {
+            return;
+        }
+        let n = infos.len() as f64;
+        let mean = |value: fn(&TcpInfo) -> f64| infos.iter().map(value).sum::<f64>() / n;
+        report
+            .set("tcp_congestion", infos[0].congestion.as_str())
+            .set("tcp_rtt_ms", mean(|info| info.rtt.as_secs_f64() * 1e3))
+            .set(
+                "tcp_rtt_var_ms",
+                mean(|info| info.rtt_var.as_secs_f64() * 1e3),
+            )
+            .set(
+                "tcp_retransmits",
+                infos
+                    .iter()
+                    .map(|info| info.retransmits as u64)
+                    .sum::<u64>(),
+            )
+            .set("tcp_cwnd_bytes", mean(|info| info.cwnd as f64));
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::{io, mem, os::fd::AsRawFd, time::Duration};
+
+    use tokio::net::TcpStream;
+
+    use super::TcpInfo;
+
+    fn setsockopt(stream: &TcpStream, name: libc::c_int, value: &[u8]) -> io::Result<()> {
+        // SAFETY: the kernel reads at most `value.len()` bytes
+        let ret = unsafe {
+            libc::setsockopt(
+                stream.as_raw_fd(),
+                libc::IPPROTO_TCP,
+                name,
+                value.as_ptr().cast(),
+                value.len() as libc::socklen_t,
+            )
+        };
+        match ret {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+
+    fn getsockopt(stream: &TcpStream, name: libc::c_int, value: &mut [u8]) -> io::Result<usize> {
+        let mut len = value.len() as libc::socklen_t;
+        // SAFETY: the kernel writes at most `len` bytes and reports how many
+        let ret = unsafe {
+            libc::getsockopt(
+                stream.as_raw_fd(),
+                libc::IPPROTO_TCP,
+                name,
+                value.as_mut_ptr().cast(),
+                &mut len,
+            )
+        };
+        match ret {
+            0 => Ok(len as usize),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+
+    pub fn set_congestion(stream: &TcpStream, algorithm: &str) -> io::Result<()> {
+        setsockopt(stream, libc::TCP_CONGESTION, algorithm.as_bytes())
+            .map_err(|e| io::Error::new(e.kind(), format!("congestion control {algorithm}: {e}")))
+    }
+
+    pub fn set_notsent_lowat(stream: &TcpStream, lowat: u32) -> io::Result<()> {
+        setsockopt(stream, libc::TCP_NOTSENT_LOWAT, &lowat.to_ne_bytes())
+    }
+
+    pub fn tcp_info(stream: &TcpStream) -> io::Result<TcpInfo> {
+        let mut info = [0u8; mem::size_of::<libc::tcp_info>()];
+        getsockopt(stream, libc::TCP_INFO, &mut info)?;
+        // SAFETY: tcp_info is plain old data, fields the kernel didn't fill stay zeroed
+        let info = unsafe { std::ptr::read_unaligned(info.as_ptr().cast::<libc::tcp_info>()) };
+
+        let mut name = [0u8; 16];
+        let len = getsockopt(stream, libc::TCP_CONGESTION, &mut name)?;
+        let name = &name[..len];
+        let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(len)];
+
+        Ok(TcpInfo {
+            rtt: Duration::from_micros(info.tcpi_rtt as u64),
+            rtt_var: Duration::from_micros(info.tcpi_rttvar as u64),
+            retransmits: info.tcpi_total_retrans,
+            cwnd: info.tcpi_snd_cwnd as u64 * info.tcpi_snd_mss as u64,
+            congestion: String::from_utf8_lossy(name).into_owned(),
+        })
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use std::io;
+
+    use tokio::net::TcpStream;
+
+    use super::TcpInfo;
+
+    fn unsupported(what: &str) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{what} is only supported on Linux"),
+        )
+    }
+
+    pub fn set_congestion(_: &TcpStream, _: &str) -> io::Result<()> {
+        Err(unsupported("TCP_CONGESTION"))
+    }
+
+    pub fn set_notsent_lowat(_: &TcpStream, _: u32) -> io::Result<()> {
+        Err(unsupported("TCP_NOTSENT_LOWAT"))
+    }
+
+    pub fn tcp_info(_: &TcpStream) -> io::Result<TcpInfo> {
+        Err(unsupported("TCP_INFO"))
+    }
+}
@@ -0,0 +1,252 @@
+//! The TCP echo of `tcp-server` and `tcp-client`, the baseline of [`crate::echo`]:
+//! every connection, optionally over TLS, sends the file back as it arrives.
+
+use std::{
+    future::Future,
+    io,
+    net::SocketAddr,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Instant,
+};
+
+use indicatif::ProgressBar;
+use rustls::pki_types::ServerName;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    task::{JoinError, JoinSet},
+};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+use crate::{
+    report::ServerStats,
+    tcp::{TcpInfo, TcpOptions},
+    tls,
+    transfer::{self, Progress},
+};
+
+pub struct Server {
+    pub tcp: TcpOptions,
+    /// Accept TLS on every connection.
+    pub tls: Option<TlsAcceptor>,
+    /// Size of the buffer each connection echoes through, not 0.
+    pub buffer_size: usize,
+}
+
+/// Bind a listener with the socket options applied before `listen`.
+pub fn listen(tcp: &TcpOptions, bind: SocketAddr) -> io::Result<TcpListener> {
+    let socket = tcp.socket(bind)?;
+    socket.set_reuseaddr(true)?;
+    socket.bind(bind)?;
+    socket.listen(1024)
+}
+
+/// Echo every connection `listener` accepts until `shutdown` resolves,
+/// returning the TCP_INFO of the connections that completed. Connections still
+/// open then are aborted.
+pub async fn serve(
+    listener: TcpListener,
+    server: Arc<Server>,
+    stats: Arc<ServerStats>,
+    shutdown: impl Future<Output = ()>,
+) -> io::Result<Vec<TcpInfo>> {
+    let mut tcp_infos = vec![];
+    let mut connections = JoinSet::new();
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (stream, peer) = accepted?;
+                stats.connections.fetch_add(1, Ordering::Relaxed);
+                if let Err(error) = server.tcp.apply(&stream) {
+                    tracing::warn!("failed to set socket options for {}: {}", peer, error);
+                    continue;
+                }
+                connections.spawn(for_each_conn(stream, peer, server.clone(), stats.clone()));
+            }
+            Some(done) = connections.join_next() => tcp_infos.extend(completed(done)),
+            _ = &mut shutdown => break,
+        }
+    }
+    // connections done by now still count
+    while let Some(done) = connections.try_join_next() {
+        tcp_infos.extend(completed(done));
+    }
+    Ok(tcp_infos)
+}
+
+fn completed(done: Result<io::Result<Option<TcpInfo>>, JoinError>) -> Option<TcpInfo> {
+    match done {
+        Ok(Ok(tcp_info)) => tcp_info,
+        Ok(Err(error)) => {
+            tracing::warn!("connection failed: {}", error);
+            None
+        }
+        Err(error) => {
+            tracing::warn!("connection task failed: {}", error);
+            None
+        }
+    }
+}
+
+async fn for_each_conn(
+    stream: TcpStream,
+    peer: SocketAddr,
+    server: Arc<Server>,
+    stats: Arc<ServerStats>,
+) -> io::Result<Option<TcpInfo>> {
+    let (echoed, tcp_info) = match &server.tls {
+        Some(acceptor) => {
+            let stream = acceptor.accept(stream).await?;
+            let (_, session) = stream.get_ref();
+            tracing::info!(
+                cipher = ?session.negotiated_cipher_suite().map(|s| s.suite()),
+                client = ?session.peer_certificates().map(|certs| tls::peer_identity(&certs[0])),
+                "TLS handshake with {} done",
+                peer
+            );
+            let (mut reader, mut writer) = tokio::io::split(stream);
+            let echoed = echo(&mut reader, &mut writer, server.buffer_size, &stats.bytes).await?;
+            let stream = reader.unsplit(writer);
+            (echoed, TcpInfo::of(stream.get_ref().0))
+        }
+        None => {
+            let (mut reader, mut writer) = stream.into_split();
+            let echoed = echo(&mut reader, &mut writer, server.buffer_size, &stats.bytes).await?;
+            (echoed, TcpInfo::of(reader.as_ref()))
+        }
+    };
+
+    tracing::info!("echoed {} bytes to {}", echoed, peer);
+    match tcp_info {
+        Ok(tcp_info) => {
+            tracing::info!(?tcp_info, "connection to {} done", peer);
+            Ok(Some(tcp_info))
+        }
+        Err(error) => {
+            tracing::warn!("failed to read TCP_INFO: {}", error);
+            Ok(None)
+        }
+    }
+}
+
+async fn echo(
+    reader: &mut (impl AsyncRead + Unpin),
+    writer: &mut (impl AsyncWrite + Unpin),
+    buffer_size: usize,
+    total_echoed: &AtomicU64,
+) -> io::Result<usize> {
+    let mut buffer = vec![0; buffer_size];
+
+    let mut echoed = 0;
+    loop {
+        match reader.read(&mut buffer).await {
+            Ok(0) => break,
+            Ok(n) => {
+                echoed += n;
+                writer.write_all(&buffer[..n]).await?;
+                total_echoed.fetch_add(n as u64, Ordering::Relaxed);
+            }
+            Err(e) => {
+                tracing::error!("failed to read from socket; err = {:?}", e);
+                break;
+            }
+        }
+    }
+    // sends TLS close_notify before the FIN, a plain drop would skip it
+    writer.shutdown().await?;
+    Ok(echoed)
+}
+
+pub struct Client {
+    pub tcp: TcpOptions,
+    /// Connect over TLS, verifying the server against the name.
+    pub tls: Option<(TlsConnector, ServerName<'static>)>,
+}
+
+/// What a connection learned besides the echoed file.
+pub struct Echoed {
+    pub tcp_info: io::Result<TcpInfo>,
+    /// Handshake time in seconds and the negotiated cipher suite.
+    pub tls: Option<(f64, Option<String>)>,
+}
+
+/// Echo `file` over one connection per bar of `progress`.
+pub async fn echo_connections(
+    server_addr: SocketAddr,
+    client: Arc<Client>,
+    file: Arc<Vec<u8>>,
+    progress: Arc<Progress>,
+) -> io::Result<Vec<Echoed>> {
+    let mut connections = JoinSet::new();
+    for (stream_idx, (tx_pb, rx_pb)) in progress.streams.iter().cloned().enumerate() {
+        let client = client.clone();
+        let file = file.clone();
+        let progress = progress.clone();
+
+        connections.spawn(async move {
+            let stream = client.tcp.socket(server_addr)?.connect(server_addr).await?;
+            client.tcp.apply(&stream)?;
+            tracing::info!(stream_idx, "connected");
+
+            let Some((connector, server_name)) = &client.tls else {
+                let (reader, writer) = stream.into_split();
+                let (reader, _) =
+                    echo_halves(&file, reader, writer, &progress, tx_pb, rx_pb).await?;
+                let tcp_info = TcpInfo::of(reader.as_ref());
+                return io::Result::Ok(Echoed {
+                    tcp_info,
+                    tls: None,
+                });
+            };
+
+            let handshake_start = Instant::now();
+            let stream = connector.connect(server_name.clone(), stream).await?;
+            let handshake_time = handshake_start.elapsed().as_secs_f64();
+            let (_, session) = stream.get_ref();
+            let cipher = session
+                .negotiated_cipher_suite()
+                .map(|suite| format!("{:?}", suite.suite()));
+
+            let (reader, writer) = tokio::io::split(stream);
+            let (reader, writer) =
+                echo_halves(&file, reader, writer, &progress, tx_pb, rx_pb).await?;
+            let stream = reader.unsplit(writer);
+            Ok(Echoed {
+                tcp_info: TcpInfo::of(stream.get_ref().0),
+                tls: Some((handshake_time, cipher)),
+            })
+        });
+    }
+
+    let connections = tokio::select! {
+        all = connections.join_all() => all.into_iter().collect::<Result<Vec<_>, _>>()?,
+        _ = progress.tick() => unreachable!(),
+    };
+    progress.finish();
+    Ok(connections)
+}
+
+/// Run the transfer over the halves of a connection, handing them back so
+/// the connection can still be inspected.
+async fn echo_halves<R, W>(
+    file: &[u8],
+    mut reader: R,
+    mut writer: W,
+    progress: &Progress,
+    tx_pb: ProgressBar,
+    rx_pb: ProgressBar,
+) -> io::Result<(R, W)>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    tokio::try_join!(
+        transfer::upload(file, &mut writer, tx_pb, progress.total_tx.clone()),
+        transfer::receive(file, &mut reader, rx_pb, progress.total_rx.clone()),
+    )?;
+    Ok((reader, writer))
+}
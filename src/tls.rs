@@ -0,0 +1,487 @@
+//! The optional rustls layer of `tcp-server`/`tcp-client`, so the TCP baseline
+//! pays the same crypto cost as the QUIC transfer, the PEM loading the other
+//! binaries share, mutual TLS for all of them and session resumption for the
+//! file and echo ones.
+
+use std::{
+    fmt,
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use clap::{Args, ValueEnum};
+use rustls::{
+    ClientConfig, ConfigBuilder, DigitallySignedStruct, DistinguishedName, RootCertStore,
+    ServerConfig, SignatureScheme, SupportedCipherSuite, WantsVerifier,
+    client::{Resumption, WantsClientCert, danger::HandshakeSignatureValid},
+    crypto::{CryptoProvider, ring},
+    pki_types::{CertificateDer, PrivateKeyDer, UnixTime, pem::PemObject},
+    server::{
+        NoServerSessionStorage, ServerSessionMemoryCache, WantsServerCert, WebPkiClientVerifier,
+        danger::{ClientCertVerified, ClientCertVerifier},
+    },
+    version::TLS13,
+};
+use tracing::{Instrument, info_span};
+
+use crate::report::Report;
+
+/// TLS 1.3 cipher suites, the only version QUIC speaks.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CipherSuite {
+    Aes128Gcm,
+    Aes256Gcm,
+    Chacha20Poly1305,
+}
+
+impl CipherSuite {
+    fn suite(self) -> SupportedCipherSuite {
+        match self {
+            CipherSuite::Aes128Gcm => ring::cipher_suite::TLS13_AES_128_GCM_SHA256,
+            CipherSuite::Aes256Gcm => ring::cipher_suite::TLS13_AES_256_GCM_SHA384,
+            CipherSuite::Chacha20Poly1305 => ring::cipher_suite::TLS13_CHACHA20_POLY1305_SHA256,
+        }
+    }
+}
+
+#[derive(Args, Clone, Debug, Default)]
+pub struct TlsOptions {
+    /// Run the transfer over TLS 1.3
+    #[arg(long)]
+    pub tls: bool,
+    /// Only offer/accept this cipher suite, all of them if omitted
+    #[arg(long, value_enum, requires = "tls")]
+    pub tls_cipher: Option<CipherSuite>,
+    /// Largest TLS record to send. 16384 gives the full records kTLS offloads
+    /// best, ~1200 encrypts in chunks as small as QUIC packets
+    #[arg(long, value_name = "BYTES", requires = "tls")]
+    pub tls_record_size: Option<usize>,
+}
+
+impl TlsOptions {
+    fn provider(&self) -> Arc<CryptoProvider> {
+        let mut provider = ring::default_provider();
+        if let Some(cipher) = self.tls_cipher {
+            provider.cipher_suites = vec![cipher.suite()];
+        }
+        Arc::new(provider)
+    }
+
+    /// Clients are asked for a certificate if `client_verifier` is given.
+    pub fn server_config(
+        &self,
+        cert_chain: Vec<CertificateDer<'static>>,
+        key: PrivateKeyDer<'static>,
+        client_verifier: Option<Arc<dyn ClientCertVerifier>>,
+    ) -> io::Result<ServerConfig> {
+        let builder = ServerConfig::builder_with_provider(self.provider())
+            .with_protocol_versions(&[&TLS13])
+            .map_err(io::Error::other)?;
+        let mut config = with_client_verifier(builder, client_verifier)
+            .with_single_cert(cert_chain, key)
+            .map_err(io::Error::other)?;
+        config.max_fragment_size = self.tls_record_size;
+        Ok(config)
+    }
+
+    /// `identity` is presented if the server asks for a certificate.
+    pub fn client_config(
+        &self,
+        roots: RootCertStore,
+        identity: Option<&Identity>,
+    ) -> io::Result<ClientConfig> {
+        let builder = ClientConfig::builder_with_provider(self.provider())
+            .with_protocol_versions(&[&TLS13])
+            .map_err(io::Error::other)?
+            .with_root_certificates(roots);
+        let mut config = with_identity(builder, identity)?;
+        config.max_fragment_size = self.tls_record_size;
+        Ok(config)
+    }
+}
+
+/// Whether a server asks clients for a certificate.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ClientAuth {
+    #[default]
+    None,
+    /// Verify a certificate if the client has one, let it in without
+    Optional,
+    /// Close connections without a valid certificate
+    Required,
+}
+
+/// The server side of mutual TLS.
+#[derive(Args, Clone, Debug, Default)]
+pub struct ClientAuthOptions {
+    /// Ask clients for a certificate issued by `--client-ca`
+    #[arg(long, value_enum, default_value_t)]
+    pub client_auth: ClientAuth,
+    /// CA certificates (PEM) client certificates have to chain up to, see
+    /// `certgen --client-cert`
+    #[arg(long, value_name = "PEM")]
+    pub client_ca: Vec<PathBuf>,
+}
+
+impl ClientAuthOptions {
+    pub fn verifier(&self) -> io::Result<Option<Arc<dyn ClientCertVerifier>>> {
+        if self.client_auth == ClientAuth::None {
+            return Ok(None);
+        }
+        if self.client_ca.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--client-auth needs --client-ca",
+            ));
+        }
+        client_verifier(load_roots(&self.client_ca)?, self.client_auth)
+    }
+
+    pub fn report(&self, report: &mut Report) {
+        let client_auth = self.client_auth.to_possible_value().unwrap();
+        report.set("client_auth", client_auth.get_name());
+    }
+}
+
+/// Verifies client certificates against `roots`, logging who they belong to
+/// and why the ones turned away were. `None` for [`ClientAuth::None`].
+pub fn client_verifier(
+    roots: RootCertStore,
+    auth: ClientAuth,
+) -> io::Result<Option<Arc<dyn ClientCertVerifier>>> {
+    let builder = WebPkiClientVerifier::builder_with_provider(
+        Arc::new(roots),
+        Arc::new(ring::default_provider()),
+    );
+    let builder = match auth {
+        ClientAuth::None => return Ok(None),
+        ClientAuth::Optional => builder.allow_unauthenticated(),
+        ClientAuth::Required => builder,
+    };
+    let inner = builder.build().map_err(io::Error::other)?;
+    Ok(Some(Arc::new(LoggingVerifier(inner))))
+}
+
+pub(crate) fn with_client_verifier(
+    builder: ConfigBuilder<ServerConfig, WantsVerifier>,
+    client_verifier: Option<Arc<dyn ClientCertVerifier>>,
+) -> ConfigBuilder<ServerConfig, WantsServerCert> {
+    match client_verifier {
+        Some(verifier) => builder.with_client_cert_verifier(verifier),
+        None => builder.with_no_client_auth(),
+    }
+}
+
+/// Who a client certificate belongs to: its DNS names, which `certgen` puts
+/// the client name in.
+pub fn peer_identity(cert: &CertificateDer<'_>) -> String {
+    let Ok(cert) = webpki::EndEntityCert::try_from(cert) else {
+        return "<unparsable>".to_owned();
+    };
+    let names = cert.valid_dns_names().collect::<Vec<_>>();
+    if names.is_empty() {
+        "<unnamed>".to_owned()
+    } else {
+        names.join(",")
+    }
+}
+
+#[derive(Debug)]
+struct LoggingVerifier(Arc<dyn ClientCertVerifier>);
+
+impl ClientCertVerifier for LoggingVerifier {
+    fn offer_client_auth(&self) -> bool {
+        self.0.offer_client_auth()
+    }
+
+    fn client_auth_mandatory(&self) -> bool {
+        self.0.client_auth_mandatory()
+    }
+
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        self.0.root_hint_subjects()
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        now: UnixTime,
+    ) -> Result<ClientCertVerified, rustls::Error> {
+        let identity = peer_identity(end_entity);
+        self.0
+            .verify_client_cert(end_entity, intermediates, now)
+            .inspect(|_| tracing::info!(client = identity, "client certificate verified"))
+            .inspect_err(|error| {
+                tracing::warn!(client = identity, "client certificate rejected: {}", error)
+            })
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.0.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.0.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.supported_verify_schemes()
+    }
+}
+
+/// A certificate chain and its key, what a client proves itself with.
+#[derive(Debug)]
+pub struct Identity {
+    pub cert_chain: Vec<CertificateDer<'static>>,
+    pub key: PrivateKeyDer<'static>,
+}
+
+impl Clone for Identity {
+    fn clone(&self) -> Self {
+        Self {
+            cert_chain: self.cert_chain.clone(),
+            key: self.key.clone_key(),
+        }
+    }
+}
+
+/// The client side of mutual TLS.
+#[derive(Args, Clone, Debug, Default)]
+pub struct ClientCertOptions {
+    /// Certificate chain (PEM) to present when the server asks for one
+    #[arg(long, value_name = "PEM", requires = "client_key")]
+    pub client_cert: Option<PathBuf>,
+    /// Private key (PEM) of `--client-cert`
+    #[arg(long, value_name = "PEM", requires = "client_cert")]
+    pub client_key: Option<PathBuf>,
+}
+
+impl ClientCertOptions {
+    pub fn identity(&self) -> io::Result<Option<Identity>> {
+        let (Some(cert), Some(key)) = (&self.client_cert, &self.client_key) else {
+            return Ok(None);
+        };
+        Ok(Some(Identity {
+            cert_chain: load_certs(cert)?,
+            key: load_key(key)?,
+        }))
+    }
+}
+
+pub(crate) fn with_identity(
+    builder: ConfigBuilder<ClientConfig, WantsClientCert>,
+    identity: Option<&Identity>,
+) -> io::Result<ClientConfig> {
+    match identity {
+        Some(identity) => builder
+            .with_client_auth_cert(identity.cert_chain.clone(), identity.key.clone_key())
+            .map_err(io::Error::other),
+        None => Ok(builder.with_no_client_auth()),
+    }
+}
+
+/// The client side of session resumption. Without `--resume` every
+/// connection does a full handshake.
+#[derive(Args, Clone, Debug)]
+pub struct ResumptionOptions {
+    /// Keep session tickets between connections to resume them
+    #[arg(long)]
+    pub resume: bool,
+    /// Send the first request of resumed connections as 0-RTT early data,
+    /// implies `--resume`
+    #[arg(long)]
+    pub early_data: bool,
+    /// Session tickets to keep at most, one per server name is enough
+    #[arg(long, value_name = "TICKETS", default_value = "256")]
+    pub session_cache: usize,
+}
+
+impl Default for ResumptionOptions {
+    fn default() -> Self {
+        Self {
+            resume: false,
+            early_data: false,
+            session_cache: 256,
+        }
+    }
+}
+
+impl ResumptionOptions {
+    pub fn enabled(&self) -> bool {
+        self.resume || self.early_data
+    }
+
+    pub fn apply(&self, config: &mut ClientConfig) {
+        config.resumption = if self.enabled() {
+            Resumption::in_memory_sessions(self.session_cache)
+        } else {
+            Resumption::disabled()
+        };
+        config.enable_early_data = self.early_data;
+    }
+
+    /// gm-quic 0.2.2 turns resumption off in every client it builds and has
+    /// no 0-RTT, so QUIC connections always do a full handshake.
+    pub fn check_quic(&self) -> io::Result<()> {
+        if self.enabled() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "gm-quic 0.2.2 clients can't resume sessions",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Runs `connect` as a full handshake to compare the resumed ones with,
+    /// which leaves the session ticket they resume behind. Nothing is run
+    /// without resumption.
+    pub async fn warmup<T: fmt::Debug, E>(
+        &self,
+        connect: impl Future<Output = Result<T, E>>,
+    ) -> Result<Option<T>, E> {
+        if !self.enabled() {
+            return Ok(None);
+        }
+        let warmup = connect.instrument(info_span!("warmup")).await?;
+        tracing::info!(?warmup, "warmed up");
+        Ok(Some(warmup))
+    }
+
+    pub fn report(&self, report: &mut Report) {
+        report
+            .set("resume", self.enabled())
+            .set("early_data", self.early_data);
+    }
+}
+
+/// The server side of session resumption.
+#[derive(Args, Clone, Debug)]
+pub struct TicketOptions {
+    /// Session tickets to send after every full handshake, 0 disables
+    /// resumption
+    #[arg(long, value_name = "TICKETS", default_value = "2")]
+    pub session_tickets: usize,
+    /// Sessions to remember for stateful tickets. Every ticket resumes once
+    /// only, which is what keeps early data from being replayed
+    #[arg(long, value_name = "SESSIONS", default_value = "256")]
+    pub ticket_cache: usize,
+    /// Encrypt the session into the ticket instead of remembering it. Such
+    /// tickets can be replayed, so early data is refused with them
+    #[arg(long)]
+    pub stateless_tickets: bool,
+    /// Early data to accept on resumed connections, 0 refuses 0-RTT
+    #[arg(long, value_name = "BYTES", default_value = "0")]
+    pub max_early_data: u32,
+}
+
+impl Default for TicketOptions {
+    fn default() -> Self {
+        Self {
+            session_tickets: 2,
+            ticket_cache: 256,
+            stateless_tickets: false,
+            max_early_data: 0,
+        }
+    }
+}
+
+impl TicketOptions {
+    pub fn apply(&self, config: &mut ServerConfig) -> io::Result<()> {
+        config.send_tls13_tickets = self.session_tickets;
+        if self.stateless_tickets {
+            config.ticketer = ring::Ticketer::new().map_err(io::Error::other)?;
+            config.session_storage = Arc::new(NoServerSessionStorage {});
+        } else {
+            config.session_storage = ServerSessionMemoryCache::new(self.ticket_cache);
+            config.max_early_data_size = self.max_early_data;
+        }
+        Ok(())
+    }
+
+    /// The tickets of a QUIC server. gm-quic 0.2.2 sends them, but has no
+    /// 0-RTT to accept.
+    pub fn check_quic(&self) -> io::Result<()> {
+        if self.max_early_data != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "gm-quic 0.2.2 has no 0-RTT to accept",
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn report(&self, report: &mut Report) {
+        report
+            .set("session_tickets", self.session_tickets)
+            .set("stateless_tickets", self.stateless_tickets)
+            .set("max_early_data", self.max_early_data);
+    }
+}
+
+/// TLS 1.3 for a QUIC server from the PEM `cert` and `key`, asking clients for
+/// a certificate if `client_verifier` is given and handing out session tickets
+/// as `tickets` says.
+pub fn quic_server_config(
+    cert: &[u8],
+    key: &[u8],
+    client_verifier: Option<Arc<dyn ClientCertVerifier>>,
+    tickets: &TicketOptions,
+) -> io::Result<ServerConfig> {
+    tickets.check_quic()?;
+    let cert_chain = CertificateDer::pem_slice_iter(cert)
+        .collect::<Result<_, _>>()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let key = PrivateKeyDer::from_pem_slice(key)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let builder = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_protocol_versions(&[&TLS13])
+        .map_err(io::Error::other)?;
+    let mut config = with_client_verifier(builder, client_verifier)
+        .with_single_cert(cert_chain, key)
+        .map_err(io::Error::other)?;
+    tickets.apply(&mut config)?;
+    Ok(config)
+}
+
+/// All certificates of a PEM file.
+pub fn load_certs(path: &Path) -> io::Result<Vec<CertificateDer<'static>>> {
+    CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect())
+        .map_err(|e| invalid_data(path, e))
+}
+
+/// The first private key of a PEM file.
+pub fn load_key(path: &Path) -> io::Result<PrivateKeyDer<'static>> {
+    PrivateKeyDer::from_pem_file(path).map_err(|e| invalid_data(path, e))
+}
+
+/// A root store trusting every certificate in the PEM files.
+pub fn load_roots(paths: &[impl AsRef<Path>]) -> io::Result<RootCertStore> {
+    let mut store = RootCertStore::empty();
+    for path in paths {
+        for cert in load_certs(path.as_ref())? {
+            store.add(cert).map_err(io::Error::other)?;
+        }
+    }
+    Ok(store)
+}
+
+fn invalid_data(path: &Path, error: impl fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {error}", path.display()),
+    )
+}
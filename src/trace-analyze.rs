@@ -0,0 +1,491 @@
+use std::{
+    fmt::Write as _,
+    fs,
+    io::{self, BufRead, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use big_file_test::trace_events::{Event, Record, RuntimeMetrics};
+use clap::Parser;
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+
+#[derive(Parser)]
+struct Options {
+    /// JSON tracing output of `client`
+    log: PathBuf,
+    /// Where to write the SVG charts
+    #[arg(short = 'o', long, default_value = ".")]
+    out_dir: PathBuf,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let options = Options::parse();
+
+    let (events, runtime_metrics) = read_events(&options.log)?;
+    let bursts = Burst::reconstruct(&events);
+    let waits = Wait::reconstruct(&events);
+    let end = events.last().map_or(0.0, |(time, _)| *time);
+
+    fs::create_dir_all(&options.out_dir)?;
+    let out = |name: &str| options.out_dir.join(name);
+    fs::write(out("timeline.svg"), timeline_svg(&bursts, &waits, end))?;
+
+    let histograms: [(&str, &str, Vec<f64>); 5] = [
+        (
+            "hist_burst_duration.svg",
+            "burst duration (ms)",
+            bursts.iter().filter_map(Burst::total_duration).collect(),
+        ),
+        (
+            "hist_burst_load.svg",
+            "burst load time (ms)",
+            bursts.iter().filter_map(Burst::load_time).collect(),
+        ),
+        (
+            "hist_burst_socket.svg",
+            "burst socket time (ms)",
+            bursts.iter().filter_map(Burst::socket_time).collect(),
+        ),
+        (
+            "hist_burst_void.svg",
+            "void time between bursts (ms)",
+            bursts.iter().filter_map(Burst::void_time).collect(),
+        ),
+        (
+            "hist_waker_wait.svg",
+            "send_waker wait (ms)",
+            waits
+                .iter()
+                .filter_map(|wait| Some(wait.end? - wait.start))
+                .collect(),
+        ),
+    ];
+    for (file, title, values) in histograms {
+        fs::write(out(file), histogram_svg(title, &values))?;
+    }
+
+    if !runtime_metrics.is_empty() {
+        write_runtime_metrics(&out("runtime_metrics.csv"), &runtime_metrics)?;
+    }
+
+    print_analysis(&bursts, &waits);
+    print_runtime_metrics(&runtime_metrics);
+    println!("\n图表已写入 {}", options.out_dir.display());
+    Ok(())
+}
+
+type Timed<T> = Vec<(f64, T)>;
+
+/// Read the recognized events and runtime samples, with times in ms relative
+/// to the first record.
+fn read_events(path: &Path) -> io::Result<(Timed<Event>, Timed<RuntimeMetrics>)> {
+    let reader = io::BufReader::new(fs::File::open(path)?);
+
+    let mut t0 = None;
+    let mut events = vec![];
+    let mut runtime_metrics = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        // skip anything that isn't a JSON record, e.g. a panic message
+        let Ok(record) = serde_json::from_str::<Record>(&line) else {
+            continue;
+        };
+        let Ok(timestamp) = OffsetDateTime::parse(&record.timestamp, &Rfc3339) else {
+            continue;
+        };
+        let t0 = *t0.get_or_insert(timestamp);
+        let time = (timestamp - t0).as_seconds_f64() * 1000.0;
+        if let Some(event) = Event::from_record(&record) {
+            events.push((time, event));
+        } else if let Some(sample) = RuntimeMetrics::from_record(&record) {
+            runtime_metrics.push((time, sample));
+        }
+    }
+    Ok((events, runtime_metrics))
+}
+
+/// Same long format as `qlog-analyze`, so both can be plotted on one time axis.
+fn write_runtime_metrics(path: &Path, samples: &[(f64, RuntimeMetrics)]) -> io::Result<()> {
+    let mut csv = BufWriter::new(fs::File::create(path)?);
+    writeln!(csv, "time_ms,metric,value")?;
+    for (time, sample) in samples {
+        for (metric, value) in RuntimeMetrics::FIELDS.iter().zip(sample.values()) {
+            writeln!(csv, "{time:.3},{metric},{value}")?;
+        }
+    }
+    csv.flush()
+}
+
+enum BurstResult {
+    Success,
+    Failed { wait_for: String },
+    Unfinished,
+}
+
+struct Burst {
+    start: f64,
+    quota: u64,
+    last_end: Option<f64>,
+    result: BurstResult,
+    load_end: Option<f64>,
+    finish: Option<f64>,
+    bytes: u64,
+}
+
+impl Burst {
+    fn reconstruct(events: &[(f64, Event)]) -> Vec<Burst> {
+        let mut bursts = vec![];
+        let mut current: Option<Burst> = None;
+        let mut last_end = None;
+
+        for (time, event) in events {
+            match event {
+                Event::BurstQuota { quota } => {
+                    if let Some(unfinished) = current.take() {
+                        eprintln!("Warning: 未处理的burst, 开始于 {:.3} ms", unfinished.start);
+                        bursts.push(unfinished);
+                    }
+                    current = Some(Burst {
+                        start: *time,
+                        quota: *quota,
+                        last_end,
+                        result: BurstResult::Unfinished,
+                        load_end: None,
+                        finish: None,
+                        bytes: 0,
+                    });
+                }
+                _ if current.is_none() => {}
+                Event::BurstLoadResult { bytes } => {
+                    let burst = current.as_mut().unwrap();
+                    burst.bytes += bytes.unwrap_or_default();
+                }
+                Event::BurstLoaded => current.as_mut().unwrap().load_end = Some(*time),
+                Event::BurstSentAll | Event::WaitFor { .. } => {
+                    let mut burst = current.take().unwrap();
+                    burst.result = match event {
+                        Event::WaitFor { signals } => BurstResult::Failed {
+                            wait_for: signals.clone(),
+                        },
+                        _ => BurstResult::Success,
+                    };
+                    burst.finish = Some(*time);
+                    last_end = Some(*time);
+                    bursts.push(burst);
+                }
+                Event::WokenBy { .. } => {}
+            }
+        }
+
+        bursts.extend(current);
+        bursts
+    }
+
+    fn total_duration(&self) -> Option<f64> {
+        Some(self.finish? - self.start)
+    }
+
+    fn load_time(&self) -> Option<f64> {
+        Some(self.load_end? - self.start)
+    }
+
+    fn socket_time(&self) -> Option<f64> {
+        Some(self.finish? - self.load_end?)
+    }
+
+    fn void_time(&self) -> Option<f64> {
+        Some(self.start - self.last_end?)
+    }
+}
+
+/// One interval the send task spent parked in the send_waker.
+struct Wait {
+    start: f64,
+    end: Option<f64>,
+    wait_for: String,
+    woken_by: Option<String>,
+}
+
+impl Wait {
+    fn reconstruct(events: &[(f64, Event)]) -> Vec<Wait> {
+        let mut waits = vec![];
+        let mut waiting: Option<Wait> = None;
+
+        for (time, event) in events {
+            match event {
+                Event::WaitFor { signals } => {
+                    waits.extend(waiting.take());
+                    waiting = Some(Wait {
+                        start: *time,
+                        end: None,
+                        wait_for: signals.clone(),
+                        woken_by: None,
+                    });
+                }
+                Event::WokenBy { signals } => {
+                    if let Some(mut wait) = waiting.take() {
+                        wait.end = Some(*time);
+                        wait.woken_by = Some(signals.clone());
+                        waits.push(wait);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        waits.extend(waiting);
+        waits
+    }
+}
+
+fn print_analysis(bursts: &[Burst], waits: &[Wait]) {
+    let ms = |time: Option<f64>| time.map_or_else(|| "-".to_owned(), |ms| format!("{ms:.3}"));
+
+    println!("\nBurst 分析报告:");
+    println!("{}", "-".repeat(50));
+    for (idx, burst) in bursts.iter().enumerate() {
+        println!("\nBurst #{}:", idx + 1);
+        println!("初始配额: {}", burst.quota);
+        println!("持续时间: {} ms", ms(burst.total_duration()));
+        println!("空隙时间: {} ms", ms(burst.void_time()));
+        match &burst.result {
+            BurstResult::Success => {
+                println!("结果: Success");
+                println!("传输数据量: {} bytes", burst.bytes);
+                println!("加载时间: {} ms", ms(burst.load_time()));
+                println!("Socket时间: {} ms", ms(burst.socket_time()));
+            }
+            BurstResult::Failed { wait_for } => {
+                println!("结果: Failed");
+                println!("等待原因: {wait_for}");
+            }
+            BurstResult::Unfinished => println!("结果: Unfinished"),
+        }
+    }
+
+    let successful = bursts
+        .iter()
+        .filter(|burst| matches!(burst.result, BurstResult::Success));
+    let failed = bursts
+        .iter()
+        .filter(|burst| matches!(burst.result, BurstResult::Failed { .. }));
+    println!("\n总结:");
+    println!("总burst数: {}", bursts.len());
+    println!("成功的burst数: {}", successful.clone().count());
+    println!("失败的burst数: {}", failed.count());
+    println!(
+        "总传输数据量: {} bytes",
+        successful.map(|burst| burst.bytes).sum::<u64>()
+    );
+
+    println!("\n状态转换统计:");
+    println!("进入等待状态: {}", waits.len());
+    println!(
+        "退出等待状态: {}",
+        waits.iter().filter(|wait| wait.end.is_some()).count()
+    );
+}
+
+fn print_runtime_metrics(samples: &[(f64, RuntimeMetrics)]) {
+    if samples.is_empty() {
+        return;
+    }
+    let max = |value: fn(&RuntimeMetrics) -> f64| {
+        samples
+            .iter()
+            .map(|(_, sample)| value(sample))
+            .fold(0.0, f64::max)
+    };
+    let mean_busy = samples.iter().map(|(_, s)| s.busy_ratio).sum::<f64>() / samples.len() as f64;
+
+    println!("\n运行时指标 ({} 个采样):", samples.len());
+    println!(
+        "worker繁忙率: 平均 {:.1}%, 最高 {:.1}%",
+        mean_busy * 100.0,
+        max(|s| s.busy_ratio) * 100.0
+    );
+    println!("最大全局队列深度: {}", max(|s| s.global_queue_depth as f64));
+    println!("最大本地队列深度: {}", max(|s| s.local_queue_depth as f64));
+    println!(
+        "最大blocking队列深度: {}",
+        max(|s| s.blocking_queue_depth as f64)
+    );
+}
+
+const WIDTH: f64 = 1200.0;
+const MARGIN: f64 = 60.0;
+
+fn svg(height: f64, title: &str, body: &str) -> String {
+    format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{height}" font-family="sans-serif" font-size="12">
+<rect width="100%" height="100%" fill="white"/>
+<text x="{}" y="24" text-anchor="middle" font-size="16">{title}</text>
+{body}</svg>
+"#,
+        WIDTH / 2.0
+    )
+}
+
+/// A horizontal axis from `min` to `max` at `y`, with ten labelled ticks.
+fn x_axis(body: &mut String, y: f64, min: f64, max: f64, label: &str) {
+    let span = (max - min).max(f64::EPSILON);
+    let right = WIDTH - MARGIN;
+    _ = writeln!(
+        body,
+        r#"<line x1="{MARGIN}" y1="{y}" x2="{right}" y2="{y}" stroke="black"/>"#
+    );
+    for tick in 0..=10 {
+        let value = min + span * tick as f64 / 10.0;
+        let x = MARGIN + (right - MARGIN) * tick as f64 / 10.0;
+        _ = writeln!(
+            body,
+            r#"<line x1="{x}" y1="{y}" x2="{x}" y2="{}" stroke="black"/><text x="{x}" y="{}" text-anchor="middle">{value:.2}</text>"#,
+            y + 5.0,
+            y + 18.0,
+        );
+    }
+    _ = writeln!(
+        body,
+        r#"<text x="{}" y="{}" text-anchor="middle">{label}</text>"#,
+        WIDTH / 2.0,
+        y + 36.0
+    );
+}
+
+fn timeline_svg(bursts: &[Burst], waits: &[Wait], end: f64) -> String {
+    let end = end.max(f64::EPSILON);
+    let x = |time: f64| MARGIN + (WIDTH - 2.0 * MARGIN) * time / end;
+    let rect = |body: &mut String, from: f64, to: f64, y: f64, fill: &str, tip: String| {
+        let width = (x(to) - x(from)).max(0.5);
+        _ = writeln!(
+            body,
+            r#"<rect x="{:.3}" y="{y}" width="{width:.3}" height="30" fill="{fill}"><title>{tip}</title></rect>"#,
+            x(from)
+        );
+    };
+
+    let mut body = String::new();
+    let rows = [(50.0, "send_waker"), (100.0, "bursts")];
+    for (y, name) in rows {
+        _ = writeln!(
+            body,
+            r#"<text x="{}" y="{}" text-anchor="end">{name}</text>"#,
+            MARGIN - 6.0,
+            y + 20.0
+        );
+    }
+
+    for wait in waits {
+        let tip = format!(
+            "wait for {} / wake by {}",
+            wait.wait_for,
+            wait.woken_by.as_deref().unwrap_or("-")
+        );
+        rect(
+            &mut body,
+            wait.start,
+            wait.end.unwrap_or(end),
+            50.0,
+            "#d62728",
+            tip,
+        );
+    }
+
+    for burst in bursts {
+        let finish = burst.finish.unwrap_or(end);
+        match &burst.result {
+            BurstResult::Success => {
+                let load_end = burst.load_end.unwrap_or(burst.start);
+                let tip = format!("quota {} / {} bytes", burst.quota, burst.bytes);
+                rect(
+                    &mut body,
+                    burst.start,
+                    load_end,
+                    100.0,
+                    "#98df8a",
+                    tip.clone(),
+                );
+                rect(&mut body, load_end, finish, 100.0, "#2ca02c", tip);
+            }
+            BurstResult::Failed { wait_for } => {
+                let tip = format!("quota {} / wait for {wait_for}", burst.quota);
+                rect(&mut body, burst.start, finish, 100.0, "#ff9896", tip);
+            }
+            BurstResult::Unfinished => {
+                let tip = format!("quota {} / unfinished", burst.quota);
+                rect(&mut body, burst.start, finish, 100.0, "#c7c7c7", tip);
+            }
+        }
+    }
+
+    x_axis(&mut body, 145.0, 0.0, end, "time (ms)");
+    let legend = [
+        ("#d62728", "waiting"),
+        ("#98df8a", "burst loading"),
+        ("#2ca02c", "burst on socket"),
+        ("#ff9896", "burst failed"),
+    ];
+    for (idx, (fill, name)) in legend.iter().enumerate() {
+        let lx = MARGIN + idx as f64 * 160.0;
+        _ = writeln!(
+            body,
+            r#"<rect x="{lx}" y="200" width="14" height="14" fill="{fill}"/><text x="{}" y="212">{name}</text>"#,
+            lx + 20.0
+        );
+    }
+
+    svg(230.0, "Send Waker State Timeline and Bursts", &body)
+}
+
+fn histogram_svg(title: &str, values: &[f64]) -> String {
+    const BINS: usize = 30;
+    const TOP: f64 = 40.0;
+    const BOTTOM: f64 = 340.0;
+
+    let mut body = String::new();
+    if values.is_empty() {
+        _ = writeln!(
+            body,
+            r#"<text x="{}" y="{}" text-anchor="middle">no samples</text>"#,
+            WIDTH / 2.0,
+            (TOP + BOTTOM) / 2.0
+        );
+        return svg(BOTTOM + 50.0, title, &body);
+    }
+
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let span = (max - min).max(f64::EPSILON);
+    let mut counts = [0usize; BINS];
+    for value in values {
+        let bin = ((value - min) / span * BINS as f64) as usize;
+        counts[bin.min(BINS - 1)] += 1;
+    }
+    let highest = counts.iter().copied().max().unwrap_or(1) as f64;
+
+    let bar_width = (WIDTH - 2.0 * MARGIN) / BINS as f64;
+    for (bin, count) in counts.iter().enumerate() {
+        let height = (BOTTOM - TOP) * *count as f64 / highest;
+        _ = writeln!(
+            body,
+            r##"<rect x="{:.3}" y="{:.3}" width="{:.3}" height="{height:.3}" fill="#1f77b4" stroke="white"><title>{count}</title></rect>"##,
+            MARGIN + bar_width * bin as f64,
+            BOTTOM - height,
+            bar_width,
+        );
+    }
+    _ = writeln!(
+        body,
+        r#"<text x="{}" y="{}" text-anchor="end">{highest}</text>"#,
+        MARGIN - 6.0,
+        TOP + 10.0
+    );
+    x_axis(
+        &mut body,
+        BOTTOM,
+        min,
+        max,
+        &format!("{} samples", values.len()),
+    );
+    svg(BOTTOM + 50.0, title, &body)
+}
@@ -0,0 +1,172 @@
+//! The tracing events `trace-analyze` reconstructs bursts, send_waker
+//! intervals and runtime metrics from.
+//!
+//! Both kinds are emitted through [`Event::emit`] and [`RuntimeMetrics::emit`]
+//! and read back from the JSON layer output with the `from_record` of the same
+//! type, which only looks at the target and the structured fields. A gm-quic
+//! build instrumented for bursts and send wakers logs through [`Event::emit`]
+//! too, gm-quic 0.2.2 logs neither.
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+/// Target of the burst events.
+pub const BURST: &str = "quic::burst";
+/// Target of the send_waker events.
+pub const SEND_WAKER: &str = "quic::send_waker";
+
+/// The `event` field of each kind.
+const GET_QUOTA: &str = "get_quota";
+const LOAD_RESULT: &str = "load_result";
+const LOADED: &str = "loaded";
+const SENT_ALL: &str = "sent_all";
+const WAIT_FOR: &str = "wait_for";
+const WOKEN_BY: &str = "woken_by";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// A burst started with `quota` bytes of congestion/flow control credit.
+    BurstQuota { quota: u64 },
+    /// Segments were loaded into the burst, `None` if loading failed.
+    BurstLoadResult { bytes: Option<u64> },
+    /// All segments of the burst are loaded, the socket send starts.
+    BurstLoaded,
+    /// The burst was handed to the socket completely.
+    BurstSentAll,
+    /// The send task parks until one of `signals` fires.
+    WaitFor { signals: String },
+    /// The send task was woken by `signals`.
+    WokenBy { signals: String },
+}
+
+impl Event {
+    pub fn emit(&self) {
+        match self {
+            Event::BurstQuota { quota } => {
+                tracing::debug!(target: BURST, event = GET_QUOTA, quota, "burst")
+            }
+            Event::BurstLoadResult { bytes } => {
+                tracing::debug!(target: BURST, event = LOAD_RESULT, bytes, "burst")
+            }
+            Event::BurstLoaded => tracing::debug!(target: BURST, event = LOADED, "burst"),
+            Event::BurstSentAll => tracing::debug!(target: BURST, event = SENT_ALL, "burst"),
+            Event::WaitFor { signals } => {
+                tracing::debug!(target: SEND_WAKER, event = WAIT_FOR, signals, "send_waker")
+            }
+            Event::WokenBy { signals } => {
+                tracing::debug!(target: SEND_WAKER, event = WOKEN_BY, signals, "send_waker")
+            }
+        }
+    }
+
+    pub fn from_record(record: &Record) -> Option<Event> {
+        let field = |name: &str| record.fields.get(name);
+        let signals = || field("signals")?.as_str().map(str::to_owned);
+        let event = field("event")?.as_str()?;
+        match record.target.as_str() {
+            BURST => match event {
+                GET_QUOTA => Some(Event::BurstQuota {
+                    quota: field("quota")?.as_u64()?,
+                }),
+                // a failed load leaves `bytes` out
+                LOAD_RESULT => Some(Event::BurstLoadResult {
+                    bytes: field("bytes").and_then(Value::as_u64),
+                }),
+                LOADED => Some(Event::BurstLoaded),
+                SENT_ALL => Some(Event::BurstSentAll),
+                _ => None,
+            },
+            SEND_WAKER => match event {
+                WAIT_FOR => Some(Event::WaitFor {
+                    signals: signals()?,
+                }),
+                WOKEN_BY => Some(Event::WokenBy {
+                    signals: signals()?,
+                }),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// One line written by `tracing_subscriber::fmt().json()`.
+#[derive(Debug, Deserialize)]
+pub struct Record {
+    pub timestamp: String,
+    #[serde(default)]
+    pub target: String,
+    #[serde(default)]
+    pub fields: Map<String, Value>,
+}
+
+/// Target of the periodic tokio runtime samples.
+pub const RUNTIME_METRICS: &str = "metrics::runtime";
+
+/// One sample of the tokio runtime, see [`crate::runtime_metrics`]. Counters
+/// are deltas since the previous sample.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct RuntimeMetrics {
+    pub workers: u64,
+    pub alive_tasks: u64,
+    /// Busy time of all workers over wall time times worker count.
+    pub busy_ratio: f64,
+    pub polls: u64,
+    pub global_queue_depth: u64,
+    pub local_queue_depth: u64,
+    pub blocking_threads: u64,
+    pub idle_blocking_threads: u64,
+    pub blocking_queue_depth: u64,
+}
+
+impl RuntimeMetrics {
+    pub const FIELDS: [&str; 9] = [
+        "workers",
+        "alive_tasks",
+        "busy_ratio",
+        "polls",
+        "global_queue_depth",
+        "local_queue_depth",
+        "blocking_threads",
+        "idle_blocking_threads",
+        "blocking_queue_depth",
+    ];
+
+    pub fn emit(&self) {
+        tracing::info!(
+            target: RUNTIME_METRICS,
+            workers = self.workers,
+            alive_tasks = self.alive_tasks,
+            busy_ratio = self.busy_ratio,
+            polls = self.polls,
+            global_queue_depth = self.global_queue_depth,
+            local_queue_depth = self.local_queue_depth,
+            blocking_threads = self.blocking_threads,
+            idle_blocking_threads = self.idle_blocking_threads,
+            blocking_queue_depth = self.blocking_queue_depth,
+            "runtime metrics"
+        );
+    }
+
+    pub fn from_record(record: &Record) -> Option<RuntimeMetrics> {
+        if record.target != RUNTIME_METRICS {
+            return None;
+        }
+        serde_json::from_value(Value::Object(record.fields.clone())).ok()
+    }
+
+    /// Values in the order of [`Self::FIELDS`].
+    pub fn values(&self) -> [f64; 9] {
+        [
+            self.workers as f64,
+            self.alive_tasks as f64,
+            self.busy_ratio,
+            self.polls as f64,
+            self.global_queue_depth as f64,
+            self.local_queue_depth as f64,
+            self.blocking_threads as f64,
+            self.idle_blocking_threads as f64,
+            self.blocking_queue_depth as f64,
+        ]
+    }
+}
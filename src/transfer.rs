@@ -0,0 +1,129 @@
+//! The echo transfer `client` and `tcp-client` run on every stream or
+//! connection: upload the file, receive it back and verify it on the fly.
+
+use std::{io, time::Duration};
+
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Upload and download bars of every stream plus the totals.
+pub struct Progress {
+    bars: MultiProgress,
+    /// `(upload, download)` of each stream.
+    pub streams: Vec<(ProgressBar, ProgressBar)>,
+    pub total_tx: ProgressBar,
+    pub total_rx: ProgressBar,
+}
+
+impl Progress {
+    pub fn new(streams: usize, file_len: u64, visible: bool) -> Self {
+        let bars = MultiProgress::new();
+        if !visible {
+            bars.set_draw_target(ProgressDrawTarget::hidden());
+        }
+
+        let style = ProgressStyle::default_bar()
+            .template(
+                "{prefix} {wide_bar} {percent_precise}% {decimal_bytes_per_sec} ETA: {eta} {msg}",
+            )
+            .unwrap();
+        let bar = |len: u64, prefix: String| {
+            bars.add(
+                ProgressBar::new(len)
+                    .with_style(style.clone())
+                    .with_prefix(prefix),
+            )
+        };
+
+        let tx = (0..streams)
+            .map(|idx| bar(file_len, format!("流{idx}↑")))
+            .collect::<Vec<_>>();
+        let total_tx = bar(file_len * streams as u64, "总↑".to_owned());
+        let rx = (0..streams)
+            .map(|idx| bar(file_len, format!("流{idx}↓")))
+            .collect::<Vec<_>>();
+        let total_rx = bar(file_len * streams as u64, "总↓".to_owned());
+
+        Self {
+            bars,
+            streams: tx.into_iter().zip(rx).collect(),
+            total_tx,
+            total_rx,
+        }
+    }
+
+    /// Redraw the bars at 30 fps, never returns.
+    pub async fn tick(&self) -> ! {
+        let mut interval = tokio::time::interval(Duration::from_millis(33));
+        loop {
+            self.bars.suspend(|| ());
+            interval.tick().await;
+        }
+    }
+
+    pub fn finish(&self) {
+        self.total_tx.finish_with_message("done");
+        self.total_rx.finish_with_message("done");
+    }
+}
+
+pub async fn upload(
+    file: &[u8],
+    mut writer: impl AsyncWrite + Unpin,
+    tx_pb: ProgressBar,
+    total_tx_pb: ProgressBar,
+) -> io::Result<()> {
+    let mut file = file;
+    while !file.is_empty() {
+        let write = writer.write(file).await?;
+        tx_pb.inc(write as u64);
+        total_tx_pb.inc(write as u64);
+        file = &file[write..];
+    }
+    tx_pb.set_message("shutdown...");
+    writer.shutdown().await?;
+    tx_pb.finish_with_message("done");
+    Ok(())
+}
+
+/// Receive the echoed `file`, comparing every chunk as it arrives instead of
+/// buffering the whole file.
+pub async fn receive(
+    file: &[u8],
+    mut reader: impl AsyncRead + Unpin,
+    rx_pb: ProgressBar,
+    total_rx_pb: ProgressBar,
+) -> io::Result<()> {
+    let mut buf = vec![0; 64 * 1024];
+    let mut received = 0;
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        let Some(expected) = file.get(received..received + n) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("received more than the {} bytes sent", file.len()),
+            ));
+        };
+        if let Some(offset) = buf[..n].iter().zip(expected).position(|(a, b)| a != b) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("received data differs at offset {}", received + offset),
+            ));
+        }
+        received += n;
+        rx_pb.inc(n as u64);
+        total_rx_pb.inc(n as u64);
+    }
+
+    if received != file.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("received {received} of {} bytes", file.len()),
+        ));
+    }
+    rx_pb.finish_with_message("done");
+    Ok(())
+}
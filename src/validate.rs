@@ -0,0 +1,219 @@
+//! What `h3-client` expects of its responses, so that a wrong status, a body
+//! of the wrong length or other bytes than were asked for fail the request
+//! rather than count as a win.
+
+use std::{
+    io,
+    path::PathBuf,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use clap::Args;
+use http::{Response, StatusCode, header};
+use rand::{RngCore, SeedableRng, rngs::StdRng};
+use tokio::io::{AsyncRead, ReadBuf};
+
+use crate::{failure::Failure, fetch::Error, report::Report};
+
+/// `len` bytes that are the same for the same seed, what `--expect-seed`
+/// compares with.
+pub fn seeded(seed: u64, len: usize) -> Vec<u8> {
+    let mut bytes = vec![0; len];
+    StdRng::seed_from_u64(seed).fill_bytes(&mut bytes);
+    bytes
+}
+
+/// What [`seeded`] makes, read a block at a time rather than all at once.
+pub struct Generated {
+    rng: StdRng,
+    left: u64,
+    block: Vec<u8>,
+    read: usize,
+}
+
+impl Generated {
+    /// Generating whole words, the blocks add up to what [`seeded`] makes.
+    const BLOCK: u64 = 64 * 1024;
+
+    pub fn new(seed: u64, len: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            left: len,
+            block: vec![],
+            read: 0,
+        }
+    }
+}
+
+impl AsyncRead for Generated {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.read == this.block.len() && this.left > 0 {
+            let len = this.left.min(Self::BLOCK);
+            this.block.resize(len as usize, 0);
+            this.rng.fill_bytes(&mut this.block);
+            this.left -= len;
+            this.read = 0;
+        }
+        let len = buf.remaining().min(this.block.len() - this.read);
+        buf.put_slice(&this.block[this.read..this.read + len]);
+        this.read += len;
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[derive(Args, Clone, Debug, Default)]
+pub struct ValidateOptions {
+    /// Expect this status rather than any 2xx
+    #[arg(long, value_name = "STATUS", value_parser = clap::value_parser!(u16).range(100..1000))]
+    pub expect_status: Option<u16>,
+    /// Expect bodies of this many bytes. A Content-Length the response
+    /// carries has to hold either way
+    #[arg(long, value_name = "BYTES")]
+    pub expect_size: Option<u64>,
+    /// Expect bodies to be the content of this file
+    #[arg(long, value_name = "PATH", conflicts_with = "expect_seed")]
+    pub expect_file: Option<PathBuf>,
+    /// Expect bodies to be the `--expect-size` bytes generated from this seed
+    #[arg(long, requires = "expect_size")]
+    pub expect_seed: Option<u64>,
+}
+
+impl ValidateOptions {
+    /// What the responses have to be, with the content to compare with read
+    /// or generated.
+    pub fn expect(&self) -> Result<Expect, Error> {
+        let content = match (&self.expect_file, self.expect_seed) {
+            (Some(path), _) => Some(std::fs::read(path)?),
+            (None, Some(seed)) => Some(seeded(seed, self.expect_size.unwrap_or(0) as usize)),
+            (None, None) => None,
+        };
+        let size = match (&content, self.expect_size) {
+            (Some(content), Some(size)) if content.len() as u64 != size => {
+                return Err(format!("the file is {} bytes, not {size}", content.len()).into());
+            }
+            (Some(content), _) => Some(content.len() as u64),
+            (None, size) => size,
+        };
+        Ok(Expect {
+            status: self.expect_status.map(StatusCode::from_u16).transpose()?,
+            size,
+            content: content.map(Arc::from),
+            head: false,
+        })
+    }
+
+    pub fn report(&self, report: &mut Report) {
+        report
+            .set("expect_status", self.expect_status)
+            .set("expect_size", self.expect_size)
+            .set(
+                "expect_content",
+                match (&self.expect_file, self.expect_seed) {
+                    (Some(path), _) => Some(path.display().to_string()),
+                    (None, Some(seed)) => Some(format!("seed {seed}")),
+                    (None, None) => None,
+                },
+            );
+    }
+}
+
+/// What a response has to be. Clones share the content.
+#[derive(Clone, Debug, Default)]
+pub struct Expect {
+    /// Any 2xx if none.
+    status: Option<StatusCode>,
+    size: Option<u64>,
+    content: Option<Arc<[u8]>>,
+    /// Responses to HEAD, whose Content-Length isn't the one of a body.
+    head: bool,
+}
+
+impl Expect {
+    /// Only the status, for the responses of another URI.
+    pub fn status_only(&self) -> Self {
+        Self {
+            status: self.status,
+            ..Self::default()
+        }
+    }
+
+    /// Only the status, for the responses to HEAD.
+    pub fn head(&self) -> Self {
+        Self {
+            head: true,
+            ..self.status_only()
+        }
+    }
+
+    /// Bodies of `size` bytes as well.
+    pub fn with_size(self, size: u64) -> Self {
+        Self {
+            size: Some(size),
+            ..self
+        }
+    }
+
+    /// Where the chunks of a response body go to be counted and compared.
+    pub fn body(&self) -> Body {
+        Body {
+            content: self.content.clone(),
+            bytes: 0,
+            differs: false,
+        }
+    }
+
+    /// The body bytes of `response` if it and its `body` are as expected.
+    pub fn check(&self, response: &Response<()>, body: Body) -> Result<u64, Error> {
+        let status = response.status();
+        let expected = match self.status {
+            Some(expected) => status == expected,
+            None => status.is_success(),
+        };
+        if !expected {
+            return Err(Failure::Status(status.as_u16()).error(format!("status {status}")));
+        }
+        let content_length = response
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok()?.parse::<u64>().ok())
+            .filter(|_| !self.head);
+        if let Some(size) = self
+            .size
+            .into_iter()
+            .chain(content_length)
+            .find(|size| *size != body.bytes)
+        {
+            let message = format!("{} body bytes rather than {size}", body.bytes);
+            return Err(Failure::Length.error(message));
+        }
+        if body.differs {
+            return Err(Failure::Content.error("the body isn't the expected content"));
+        }
+        Ok(body.bytes)
+    }
+}
+
+/// The body of one response so far.
+#[derive(Debug)]
+pub struct Body {
+    content: Option<Arc<[u8]>>,
+    bytes: u64,
+    differs: bool,
+}
+
+impl Body {
+    pub fn chunk(&mut self, chunk: &[u8]) {
+        if let Some(content) = &self.content {
+            let start = self.bytes as usize;
+            self.differs |= content.get(start..start + chunk.len()) != Some(chunk);
+        }
+        self.bytes += chunk.len() as u64;
+    }
+}
@@ -0,0 +1,594 @@
+//! WebTransport over HTTP/3 for `h3-server` and `h3-client`: an extended
+//! CONNECT to `/echo` or `/sink` opens a session. On an echo session every
+//! bidirectional stream sends back what it brings, every unidirectional one
+//! is answered on one of the server's; a sink counts what it gets and answers
+//! a bidirectional stream with the count once it ends. Datagrams are those of
+//! [`crate::datagram`], reflected if they ask for it and counted either way.
+//!
+//! The server side is h3-webtransport's. It has no client side, and h3's
+//! client can't announce WebTransport in its SETTINGS, which h3-webtransport
+//! insists on. So `h3-client` speaks what little HTTP/3 a session needs
+//! itself, on the QUIC connection underneath: a control stream with those
+//! SETTINGS, the CONNECT, and the streams and datagrams of the session. It
+//! doesn't read the echoes of its unidirectional streams.
+
+use std::{
+    future::poll_fn,
+    io,
+    path::PathBuf,
+    sync::{Arc, atomic::Ordering},
+    time::Instant,
+};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use clap::Args;
+use gm_quic::{Connection, QuicClient, StreamReader, StreamWriter};
+use h3::{
+    ext::Protocol,
+    quic::{self, BidiStream, SendStreamUnframed},
+};
+use h3_webtransport::server::{AcceptedBi, WebTransportSession};
+use http::{Method, Request, Uri};
+use indicatif::ProgressBar;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    task::JoinSet,
+};
+use tracing::Instrument;
+
+use crate::{
+    args,
+    datagram::{self, DatagramMode, DatagramOptions, Datagrams},
+    eyeballs::Candidates,
+    fetch::Error,
+    report::{Report, ServerStats},
+    transfer::{self, Progress},
+};
+
+/// What a client-initiated bidirectional stream of a session starts with.
+const BIDI_SIGNAL: u64 = 0x41;
+/// The stream type of a unidirectional stream of a session.
+const UNI_TYPE: u64 = 0x54;
+/// The CONNECT is the first bidirectional stream of a new connection, stream
+/// 0, and the session is named after it.
+const SESSION_ID: u64 = 0;
+/// The HTTP/3 frames the client sends and looks for.
+const SETTINGS_FRAME: u64 = 0x04;
+const HEADERS_FRAME: u64 = 0x01;
+/// Extended CONNECT, HTTP/3 datagrams and WebTransport.
+const SETTINGS: [(u64, u64); 3] = [(0x08, 1), (0x33, 1), (0x2b60_3742, 1)];
+/// QPACK's static table entries of `:status: 200`, `:method: CONNECT` and
+/// `:scheme: https`, and its names `:authority` and `:path`.
+const STATUS_200: u8 = 25;
+const METHOD_CONNECT: u8 = 15;
+const SCHEME_HTTPS: u8 = 23;
+const AUTHORITY: u8 = 0;
+const PATH: u8 = 1;
+
+type RequestStream = h3::server::RequestStream<
+    <h3_shim::QuicConnection as h3::quic::OpenStreams<Bytes>>::BidiStream,
+    Bytes,
+>;
+
+/// What a session does with what it gets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Echo,
+    Sink,
+}
+
+impl Mode {
+    /// The session `path` asks for.
+    pub fn of(path: &str) -> Option<Self> {
+        match path {
+            "/echo" => Some(Self::Echo),
+            "/sink" => Some(Self::Sink),
+            _ => None,
+        }
+    }
+
+    /// The session a request opens, if it is the extended CONNECT of one.
+    pub fn of_request(request: &Request<()>) -> Option<Self> {
+        let protocol = request.extensions().get::<Protocol>();
+        if request.method() != Method::CONNECT || protocol != Some(&Protocol::WEB_TRANSPORT) {
+            return None;
+        }
+        Self::of(request.uri().path())
+    }
+
+    fn datagram_mode(self) -> DatagramMode {
+        match self {
+            Mode::Echo => DatagramMode::Reflect,
+            Mode::Sink => DatagramMode::Count,
+        }
+    }
+}
+
+fn put_varint(buf: &mut BytesMut, value: u64) {
+    match value {
+        ..0x40 => buf.put_u8(value as u8),
+        0x40..0x4000 => buf.put_u16(0x4000 | value as u16),
+        0x4000..0x4000_0000 => buf.put_u32(0x8000_0000 | value as u32),
+        _ => buf.put_u64(0xc000_0000_0000_0000 | value),
+    }
+}
+
+fn get_varint(buf: &mut impl Buf) -> Option<u64> {
+    let len = 1 << (buf.chunk().first()? >> 6);
+    if buf.remaining() < len {
+        return None;
+    }
+    let mut value = (buf.get_u8() & 0x3f) as u64;
+    for _ in 1..len {
+        value = value << 8 | buf.get_u8() as u64;
+    }
+    Some(value)
+}
+
+/// `payload` as a datagram of the session, behind its quarter stream ID.
+fn session_datagram(payload: &[u8]) -> Bytes {
+    let mut datagram = BytesMut::with_capacity(1 + payload.len());
+    put_varint(&mut datagram, SESSION_ID / 4);
+    datagram.extend_from_slice(payload);
+    datagram.freeze()
+}
+
+/// Serve the session `request` opens on `connection`, which it takes over,
+/// until the client ends it. `quic` is the connection underneath, for the
+/// datagrams.
+pub async fn serve_session(
+    mode: Mode,
+    request: Request<()>,
+    stream: RequestStream,
+    connection: h3::server::Connection<h3_shim::QuicConnection, Bytes>,
+    quic: Arc<Connection>,
+    stats: Arc<ServerStats>,
+) -> Result<(), Error> {
+    let session = WebTransportSession::accept(request, stream, connection).await?;
+    tracing::info!(?mode, "session accepted");
+    stats.sessions.fetch_add(1, Ordering::Relaxed);
+
+    // dropped with the session, which ends with its connection
+    let mut datagrams = JoinSet::new();
+    datagrams
+        .spawn(reflect_datagrams(quic, stats.clone()).instrument(tracing::info_span!("datagrams")));
+    // h3-webtransport loses a stream whose first frame it was reading when
+    // accepting it is dropped, so both kinds are accepted side by side
+    let bidi = async {
+        let mut streams = JoinSet::new();
+        loop {
+            match session.accept_bi().await {
+                Ok(Some(AcceptedBi::BidiStream(_, stream))) => {
+                    let (send, recv) = stream.split();
+                    let stats = stats.clone();
+                    streams.spawn(async move {
+                        match mode {
+                            Mode::Echo => echo(recv, send, stats).await,
+                            Mode::Sink => sink(recv, Some(send), stats).await,
+                        }
+                    });
+                }
+                // only the session is served on its connection
+                Ok(Some(AcceptedBi::Request(request, _))) => {
+                    tracing::info!(uri = %request.uri(), "request in a session refused");
+                }
+                Ok(None) | Err(_) => return,
+            }
+        }
+    };
+    let uni = async {
+        let mut streams = JoinSet::new();
+        loop {
+            let Ok(Some((session_id, recv))) = session.accept_uni().await else {
+                return Ok::<_, Error>(());
+            };
+            let stats = stats.clone();
+            match mode {
+                Mode::Echo => {
+                    let send = session.open_uni(session_id).await?;
+                    streams.spawn(echo(recv, send, stats));
+                }
+                Mode::Sink => {
+                    streams.spawn(sink(recv, None::<h3_shim::SendStream<Bytes>>, stats));
+                }
+            }
+        }
+    };
+    tokio::select! {
+        () = bidi => {},
+        ended = uni => ended?,
+    }
+    tracing::info!("session ended");
+    Ok(())
+}
+
+/// Send back what `recv` brings over `send`.
+async fn echo<R, S>(mut recv: R, mut send: S, stats: Arc<ServerStats>) -> io::Result<()>
+where
+    R: quic::RecvStream<Buf = Bytes>,
+    R::Error: std::error::Error + Send + Sync + 'static,
+    S: SendStreamUnframed<Bytes>,
+    S::Error: std::error::Error + Send + Sync + 'static,
+{
+    while let Some(data) = poll_fn(|cx| recv.poll_data(cx))
+        .await
+        .map_err(io::Error::other)?
+    {
+        stats.bytes.fetch_add(data.len() as u64, Ordering::Relaxed);
+        send_all(&mut send, data).await?;
+    }
+    poll_fn(|cx| send.poll_finish(cx))
+        .await
+        .map_err(io::Error::other)
+}
+
+/// Count what `recv` brings, answering the count over `send` once it ended.
+async fn sink<R, S>(mut recv: R, send: Option<S>, stats: Arc<ServerStats>) -> io::Result<()>
+where
+    R: quic::RecvStream<Buf = Bytes>,
+    R::Error: std::error::Error + Send + Sync + 'static,
+    S: SendStreamUnframed<Bytes>,
+    S::Error: std::error::Error + Send + Sync + 'static,
+{
+    let mut received = 0;
+    while let Some(data) = poll_fn(|cx| recv.poll_data(cx))
+        .await
+        .map_err(io::Error::other)?
+    {
+        received += data.len() as u64;
+    }
+    stats.bytes.fetch_add(received, Ordering::Relaxed);
+    if let Some(mut send) = send {
+        send_all(&mut send, Bytes::copy_from_slice(&received.to_be_bytes())).await?;
+        poll_fn(|cx| send.poll_finish(cx))
+            .await
+            .map_err(io::Error::other)?;
+    }
+    Ok(())
+}
+
+/// Write all of `data` to `send`. The streams of h3-webtransport are only
+/// `AsyncWrite` if their errors are `io::Error`s, which h3-shim's aren't.
+async fn send_all<S>(send: &mut S, mut data: Bytes) -> io::Result<()>
+where
+    S: SendStreamUnframed<Bytes>,
+    S::Error: std::error::Error + Send + Sync + 'static,
+{
+    while data.has_remaining() {
+        // what was sent is left in `data`
+        let sent = poll_fn(|cx| send.poll_send(cx, &mut data))
+            .await
+            .map_err(io::Error::other)?;
+        data.advance(sent);
+    }
+    Ok(())
+}
+
+/// [`crate::echo`]'s datagram reflection, behind the quarter stream ID.
+async fn reflect_datagrams(quic: Arc<Connection>, stats: Arc<ServerStats>) -> io::Result<()> {
+    let mut reader = quic.unreliable_reader()?;
+    let writer = quic.unreliable_writer().await?;
+    let start = Instant::now();
+    loop {
+        let mut received = reader.recv().await?;
+        if get_varint(&mut received).is_none() {
+            continue;
+        }
+        stats.datagrams.fetch_add(1, Ordering::Relaxed);
+        stats
+            .datagram_bytes
+            .fetch_add(received.len() as u64, Ordering::Relaxed);
+        if let Some(reflected) = datagram::reflect(&received, start.elapsed()) {
+            _ = writer.send_bytes(session_datagram(&reflected));
+        }
+    }
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct WebTransportOptions {
+    /// Open a WebTransport session to the URI, `/echo` or `/sink` of
+    /// `h3-server --webtransport`, instead of GETting it
+    #[arg(long, conflicts_with = "storm")]
+    pub webtransport: bool,
+    /// What every stream of the session sends
+    #[arg(long, value_name = "PATH", default_value = "rand-file-32M")]
+    pub wt_file: PathBuf,
+    /// Bidirectional streams of the session
+    #[arg(long, value_name = "COUNT", default_value = "4")]
+    pub wt_bidi: usize,
+    /// Unidirectional streams of the session
+    #[arg(long, value_name = "COUNT", default_value = "0")]
+    pub wt_uni: usize,
+    /// Also send this many datagrams, see `client --datagrams`
+    #[arg(long, value_name = "COUNT")]
+    pub wt_datagrams: Option<u64>,
+    /// Bytes of every datagram, without the quarter stream ID in front
+    #[arg(
+        long,
+        value_name = "BYTES",
+        default_value = "1024",
+        value_parser = clap::value_parser!(u16).range(datagram::HEADER as i64..)
+    )]
+    pub wt_datagram_size: u16,
+    #[arg(
+        long,
+        value_name = "PER_SECOND",
+        requires = "wt_datagrams",
+        value_parser = args::positive
+    )]
+    pub wt_datagram_rate: Option<f64>,
+}
+
+impl WebTransportOptions {
+    fn datagram(&self, mode: Mode) -> DatagramOptions {
+        DatagramOptions {
+            datagrams: self.wt_datagrams,
+            datagram_size: self.wt_datagram_size,
+            datagram_rate: self.wt_datagram_rate,
+            datagram_mode: mode.datagram_mode(),
+            with_streams: true,
+        }
+    }
+
+    pub fn report(&self, report: &mut Report) {
+        report
+            .set("bidi_streams", self.wt_bidi)
+            .set("uni_streams", self.wt_uni);
+        if self.wt_datagrams.is_some() {
+            report
+                .set("datagram_size", self.wt_datagram_size)
+                .set("datagram_rate_per_s", self.wt_datagram_rate);
+        }
+    }
+}
+
+/// A WebTransport session on a connection of its own.
+pub struct Session {
+    mode: Mode,
+    connection: Arc<Connection>,
+    /// The CONNECT stream, the session ends with it.
+    _connect: (StreamReader, StreamWriter),
+    /// The control stream, closing it would close the connection.
+    _control: StreamWriter,
+}
+
+impl Session {
+    /// Open the session `uri` names over a new connection to the first of
+    /// `candidates` to accept it.
+    pub async fn open(
+        client: &QuicClient,
+        candidates: &Candidates,
+        uri: &Uri,
+    ) -> Result<Self, Error> {
+        let mode = Mode::of(uri.path()).ok_or("a WebTransport URI is /echo or /sink")?;
+        let host = candidates.server_name(uri.host().ok_or("missing host")?);
+        let (addr, (connection, (control, connect))) = candidates
+            .race(|addr| async move {
+                let connection = client.connect(host, addr)?;
+                let streams = Self::connect(&connection, uri).await?;
+                Ok::<_, Error>((connection, streams))
+            })
+            .await?;
+        tracing::info!(%addr, ?mode, "session opened");
+        Ok(Self {
+            mode,
+            connection,
+            _connect: connect,
+            _control: control,
+        })
+    }
+
+    /// Announce the session's SETTINGS and send the CONNECT of `uri`,
+    /// returning the control stream and the CONNECT stream once the server
+    /// accepted it.
+    async fn connect(
+        connection: &Connection,
+        uri: &Uri,
+    ) -> Result<(StreamWriter, (StreamReader, StreamWriter)), Error> {
+        let closed = || io::Error::new(io::ErrorKind::BrokenPipe, "connection closed");
+        let (_stream_id, mut control) = connection.open_uni_stream().await?.ok_or_else(closed)?;
+        let mut settings = BytesMut::new();
+        for (id, value) in SETTINGS {
+            put_varint(&mut settings, id);
+            put_varint(&mut settings, value);
+        }
+        let mut stream = BytesMut::new();
+        // the control stream type
+        put_varint(&mut stream, 0x00);
+        put_frame(&mut stream, SETTINGS_FRAME, &settings);
+        control.write_all(&stream).await?;
+
+        let (_stream_id, (mut reader, mut writer)) =
+            connection.open_bi_stream().await?.ok_or_else(closed)?;
+        let mut headers = BytesMut::new();
+        put_frame(&mut headers, HEADERS_FRAME, &connect_fields(uri)?);
+        writer.write_all(&headers).await?;
+
+        let fields = loop {
+            let kind = read_varint(&mut reader).await?;
+            let len = read_varint(&mut reader).await?;
+            let mut payload = vec![0; len as usize];
+            reader.read_exact(&mut payload).await?;
+            // frames of reserved types come and go
+            if kind == HEADERS_FRAME {
+                break payload;
+            }
+        };
+        // neither side has a dynamic table, so the section starts with two
+        // zeros, and h3 sends the status first and a 200 as the static entry
+        if !fields.starts_with(&[0, 0, 0xc0 | STATUS_200]) {
+            return Err("session refused".into());
+        }
+        Ok((control, (reader, writer)))
+    }
+
+    /// Send `file` over one bidirectional stream per bar of `progress` and
+    /// `options.wt_uni` unidirectional ones, with the datagrams `options`
+    /// ask for meanwhile.
+    pub async fn run(
+        &self,
+        file: Arc<Vec<u8>>,
+        progress: Arc<Progress>,
+        options: &WebTransportOptions,
+    ) -> Result<Option<Datagrams>, Error> {
+        let datagrams = async {
+            if options.wt_datagrams.is_none() {
+                return Ok(None);
+            }
+            let reader = &self.connection.unreliable_reader()?;
+            let writer = self.connection.unreliable_writer().await?;
+            let datagrams = Datagrams::run(
+                &options.datagram(self.mode),
+                |payload| writer.send_bytes(session_datagram(&payload)),
+                move || async move {
+                    loop {
+                        let mut datagram = poll_fn(|cx| reader.poll_recv(cx)).await?;
+                        if get_varint(&mut datagram) == Some(SESSION_ID / 4) {
+                            return Ok(datagram);
+                        }
+                    }
+                },
+            )
+            .await?;
+            Ok::<_, io::Error>(Some(datagrams))
+        };
+        let (datagrams, ()) =
+            tokio::try_join!(datagrams, self.streams(file, progress, options.wt_uni))?;
+        Ok(datagrams)
+    }
+
+    async fn streams(
+        &self,
+        file: Arc<Vec<u8>>,
+        progress: Arc<Progress>,
+        uni: usize,
+    ) -> io::Result<()> {
+        let closed = || io::Error::new(io::ErrorKind::BrokenPipe, "connection closed");
+        progress.total_tx.inc_length((file.len() * uni) as u64);
+        let mut streams = JoinSet::new();
+        for (tx_pb, rx_pb) in progress.streams.iter().cloned() {
+            let (_stream_id, (reader, mut writer)) =
+                self.connection.open_bi_stream().await?.ok_or_else(closed)?;
+            writer.write_all(&header(BIDI_SIGNAL)).await?;
+            let (file, progress, mode) = (file.clone(), progress.clone(), self.mode);
+            streams.spawn(async move {
+                let upload = transfer::upload(&file, writer, tx_pb, progress.total_tx.clone());
+                match mode {
+                    Mode::Echo => {
+                        let receive =
+                            transfer::receive(&file, reader, rx_pb, progress.total_rx.clone());
+                        tokio::try_join!(upload, receive)?;
+                    }
+                    Mode::Sink => {
+                        tokio::try_join!(upload, receive_count(reader, file.len()))?;
+                    }
+                }
+                Ok(())
+            });
+        }
+        for _ in 0..uni {
+            let (_stream_id, mut writer) = self
+                .connection
+                .open_uni_stream()
+                .await?
+                .ok_or_else(closed)?;
+            writer.write_all(&header(UNI_TYPE)).await?;
+            let (file, progress) = (file.clone(), progress.clone());
+            streams.spawn(async move {
+                let tx_pb = ProgressBar::hidden();
+                transfer::upload(&file, writer, tx_pb, progress.total_tx.clone()).await
+            });
+        }
+
+        tokio::select! {
+            all = streams.join_all() => { _ = all.into_iter().collect::<Result<Vec<_>, _>>()? },
+            _ = progress.tick() => unreachable!(),
+        }
+        progress.finish();
+        Ok(())
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn close(&self) {
+        self.connection.close("no error".into(), 0);
+    }
+}
+
+/// A frame of `kind` carrying `payload`.
+fn put_frame(buf: &mut BytesMut, kind: u64, payload: &[u8]) {
+    put_varint(buf, kind);
+    put_varint(buf, payload.len() as u64);
+    buf.extend_from_slice(payload);
+}
+
+/// The QPACK field section of the extended CONNECT to `uri`, all literal but
+/// for static entries and names.
+fn connect_fields(uri: &Uri) -> Result<BytesMut, Error> {
+    let authority = uri.authority().ok_or("missing host")?.as_str();
+    let path = uri.path_and_query().map_or("/", |path| path.as_str());
+    let mut fields = BytesMut::new();
+    // no dynamic table to refer to
+    fields.put_slice(&[0, 0]);
+    put_prefixed(&mut fields, 6, 0xc0, METHOD_CONNECT.into());
+    put_prefixed(&mut fields, 6, 0xc0, SCHEME_HTTPS.into());
+    for (name, value) in [(AUTHORITY, authority), (PATH, path)] {
+        put_prefixed(&mut fields, 4, 0x50, name.into());
+        put_prefixed(&mut fields, 7, 0x00, value.len() as u64);
+        fields.put_slice(value.as_bytes());
+    }
+    // `:protocol` isn't in the static table
+    let (name, value) = (":protocol", "webtransport");
+    put_prefixed(&mut fields, 3, 0x20, name.len() as u64);
+    fields.put_slice(name.as_bytes());
+    put_prefixed(&mut fields, 7, 0x00, value.len() as u64);
+    fields.put_slice(value.as_bytes());
+    Ok(fields)
+}
+
+/// A QPACK integer in the low `bits` of a byte with `flags` above them.
+fn put_prefixed(buf: &mut BytesMut, bits: u32, flags: u8, value: u64) {
+    let max = (1u64 << bits) - 1;
+    if value < max {
+        buf.put_u8(flags | value as u8);
+        return;
+    }
+    buf.put_u8(flags | max as u8);
+    let mut rest = value - max;
+    while rest >= 0x80 {
+        buf.put_u8(0x80 | (rest & 0x7f) as u8);
+        rest >>= 7;
+    }
+    buf.put_u8(rest as u8);
+}
+
+async fn read_varint(reader: &mut StreamReader) -> io::Result<u64> {
+    let first = reader.read_u8().await?;
+    let mut value = (first & 0x3f) as u64;
+    for _ in 1..1 << (first >> 6) {
+        value = value << 8 | reader.read_u8().await? as u64;
+    }
+    Ok(value)
+}
+
+/// What a stream of the session starts with.
+fn header(kind: u64) -> Bytes {
+    let mut header = BytesMut::new();
+    put_varint(&mut header, kind);
+    put_varint(&mut header, SESSION_ID);
+    header.freeze()
+}
+
+/// Wait for a sink to say it got all `len` bytes.
+async fn receive_count(mut reader: StreamReader, len: usize) -> io::Result<()> {
+    let received = reader.read_u64().await?;
+    if received != len as u64 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("the sink got {received} of {len} bytes"),
+        ));
+    }
+    Ok(())
+}
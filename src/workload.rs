@@ -0,0 +1,320 @@
+//! What `h3-client` requests beyond repeating one URI: a JSON file of
+//! weighted entries, each a URI or sizes to GET from the server's `/gen/` or
+//! file paths, picked in a seeded random or round-robin order and reported by
+//! class.
+
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
+
+use clap::{Args, ValueEnum};
+use http::{HeaderMap, Method, Request, Uri};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::{
+    fetch::Error,
+    priority::Finish,
+    report::{self, Report},
+    request::RequestOptions,
+    validate::Expect,
+};
+
+/// How every request picks its entry of the workload.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Order {
+    /// By weight, from the seed and the index of the request
+    Random,
+    /// Every entry as many times in a row as its weight, one after another
+    RoundRobin,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct WorkloadOptions {
+    /// GET the weighted entries of this JSON file rather than only the URI,
+    /// relative ones on its host
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["expect_size", "expect_file", "expect_seed"]
+    )]
+    pub workload: Option<PathBuf>,
+    #[arg(long, value_enum, default_value = "random")]
+    pub workload_order: Order,
+    /// The same seed picks the same requests
+    #[arg(long, default_value = "0")]
+    pub workload_seed: u64,
+}
+
+impl WorkloadOptions {
+    /// The requests to make of `uri`, whose responses have to be as
+    /// `expect` says. Entries of the workload file are only held to its
+    /// status, and sizes to their size.
+    pub fn load(&self, uri: &Uri, expect: Expect) -> Result<Workload, Error> {
+        let Some(path) = &self.workload else {
+            return Ok(Workload::single(uri.clone(), expect));
+        };
+
+        let specs = serde_json::from_slice::<Vec<Spec>>(&std::fs::read(path)?)?;
+        let mut classes = vec![];
+        let mut entries = vec![];
+        for spec in specs {
+            let (name, targets) = match (spec.uri, spec.sizes, spec.path) {
+                (Some(target), None, None) => {
+                    let target = resolve(uri, &target)?;
+                    (target.path().to_owned(), vec![(target, None)])
+                }
+                (None, Some(sizes), path) if !sizes.is_empty() => {
+                    let path = path.unwrap_or_else(|| "/gen/{size}".to_owned());
+                    let targets = sizes
+                        .into_iter()
+                        .map(|size| {
+                            let target = resolve(uri, &path.replace("{size}", &size.to_string()))?;
+                            Ok((target, Some(size)))
+                        })
+                        .collect::<Result<Vec<_>, Error>>()?;
+                    (path, targets)
+                }
+                _ => {
+                    return Err(
+                        "a workload entry has a uri or sizes, and a path only with sizes".into(),
+                    );
+                }
+            };
+            let name = spec.class.unwrap_or(name);
+            let class = match classes.iter().position(|class| *class == name) {
+                Some(class) => class,
+                None => {
+                    classes.push(name);
+                    classes.len() - 1
+                }
+            };
+            let targets = targets
+                .into_iter()
+                .map(|(uri, size)| Target {
+                    class,
+                    uri,
+                    expect: match size {
+                        Some(size) => expect.status_only().with_size(size),
+                        None => expect.status_only(),
+                    },
+                })
+                .collect();
+            entries.push(Entry {
+                weight: spec.weight,
+                targets,
+            });
+        }
+        let total = entries.iter().map(|entry| entry.weight).sum();
+        if total == 0 {
+            return Err("the workload has no weight".into());
+        }
+        Ok(Workload {
+            uri: uri.clone(),
+            entries: entries.into(),
+            classes: classes.into(),
+            total,
+            order: self.workload_order,
+            seed: self.workload_seed,
+            method: Method::GET,
+            headers: HeaderMap::new(),
+        })
+    }
+
+    pub fn report(&self, report: &mut Report) {
+        if let Some(path) = &self.workload {
+            report
+                .set("workload", path.display().to_string())
+                .set(
+                    "workload_order",
+                    format!("{:?}", self.workload_order).to_lowercase(),
+                )
+                .set("workload_seed", self.workload_seed);
+        }
+    }
+}
+
+/// An entry of the workload file.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Spec {
+    /// What the entry is reported as, the path if none. Entries of the same
+    /// class add up.
+    class: Option<String>,
+    #[serde(default = "one")]
+    weight: u64,
+    uri: Option<String>,
+    /// Bytes, every request of the entry GETs one of them.
+    sizes: Option<Vec<u64>>,
+    /// The path to GET a size from, `{size}` replaced by it. `/gen/{size}`
+    /// if none.
+    path: Option<String>,
+}
+
+fn one() -> u64 {
+    1
+}
+
+/// `target` on the host of `uri` if it has none of its own.
+fn resolve(uri: &Uri, target: &str) -> Result<Uri, Error> {
+    let target = target.parse::<Uri>()?;
+    if target.authority().is_some() {
+        return Ok(target);
+    }
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = target.into_parts().path_and_query;
+    Ok(Uri::from_parts(parts)?)
+}
+
+/// What a request GETs and what its response has to be.
+#[derive(Clone, Debug)]
+pub struct Target {
+    /// Index into [`Workload::classes`].
+    pub class: usize,
+    pub uri: Uri,
+    pub expect: Expect,
+}
+
+#[derive(Debug)]
+struct Entry {
+    weight: u64,
+    targets: Vec<Target>,
+}
+
+/// What the requests of a run GET. Clones share the entries.
+#[derive(Clone, Debug)]
+pub struct Workload {
+    uri: Uri,
+    entries: Arc<[Entry]>,
+    classes: Arc<[String]>,
+    /// Weight of all entries.
+    total: u64,
+    order: Order,
+    seed: u64,
+    method: Method,
+    headers: HeaderMap,
+}
+
+impl Workload {
+    /// Only `uri`, its responses held to `expect`.
+    pub fn single(uri: Uri, expect: Expect) -> Self {
+        Self {
+            uri: uri.clone(),
+            entries: Arc::new([Entry {
+                weight: 1,
+                targets: vec![Target {
+                    class: 0,
+                    uri: uri.clone(),
+                    expect,
+                }],
+            }]),
+            classes: Arc::new([uri.path().to_owned()]),
+            total: 1,
+            order: Order::RoundRobin,
+            seed: 0,
+            method: Method::GET,
+            headers: HeaderMap::new(),
+        }
+    }
+
+    /// Requests with the method and headers of `options`.
+    pub fn with_request(self, options: &RequestOptions) -> Self {
+        Self {
+            method: options.method(),
+            headers: options.headers.iter().cloned().collect(),
+            ..self
+        }
+    }
+
+    /// A request for `uri` and what its response has to be if the target
+    /// expects `expect`.
+    pub fn request(&self, uri: Uri, expect: &Expect) -> Result<(Request<()>, Expect), Error> {
+        let mut request = Request::builder()
+            .method(self.method.clone())
+            .uri(uri)
+            .body(())?;
+        request.headers_mut().extend(self.headers.clone());
+        let expect = if self.method == Method::HEAD {
+            expect.head()
+        } else {
+            expect.clone()
+        };
+        Ok((request, expect))
+    }
+
+    /// The URI the client was given, whose host connections go to.
+    pub fn uri(&self) -> &Uri {
+        &self.uri
+    }
+
+    pub fn classes(&self) -> &[String] {
+        &self.classes
+    }
+
+    /// The target of the `n`th request of the run, the same every time.
+    pub fn target(&self, n: usize) -> &Target {
+        let n = n as u64;
+        let (mut pick, round) = match self.order {
+            Order::Random => {
+                let mut rng = StdRng::seed_from_u64(self.seed.wrapping_add(n));
+                (rng.random_range(0..self.total), rng.random::<u32>() as u64)
+            }
+            Order::RoundRobin => (n % self.total, n / self.total),
+        };
+        for entry in self.entries.iter() {
+            if pick < entry.weight {
+                // round-robin goes through the targets of the entry too
+                let target = (round * entry.weight + pick) % entry.targets.len() as u64;
+                return &entry.targets[target as usize];
+            }
+            pick -= entry.weight;
+        }
+        unreachable!("picked within the total weight")
+    }
+}
+
+/// The requests that succeeded, by class.
+#[derive(Debug, Default)]
+pub struct ByClass(BTreeMap<usize, Vec<Finish>>);
+
+impl ByClass {
+    pub fn record(&mut self, finishes: &[Finish]) {
+        for finish in finishes {
+            if let Some(class) = finish.class {
+                self.0.entry(class).or_default().push(*finish);
+            }
+        }
+    }
+
+    /// Sets `classes`, the requests, bytes, goodput over `total_time` and
+    /// time percentiles of every class of `workload`.
+    pub fn report(&self, report: &mut Report, workload: &Workload, total_time: f64) {
+        let classes = workload
+            .classes()
+            .iter()
+            .enumerate()
+            .map(|(class, name)| {
+                let finishes = self.0.get(&class).map_or(&[][..], Vec::as_slice);
+                let bytes = finishes.iter().map(|finish| finish.bytes).sum::<u64>();
+                let mut times = finishes
+                    .iter()
+                    .map(|finish| (finish.finished - finish.started) * 1e3)
+                    .collect::<Vec<_>>();
+                times.sort_by(f64::total_cmp);
+                let mut fields = Map::new();
+                fields.insert("requests".to_owned(), finishes.len().into());
+                fields.insert("bytes".to_owned(), bytes.into());
+                fields.insert(
+                    "goodput_bytes_per_s".to_owned(),
+                    (bytes as f64 / total_time).into(),
+                );
+                for (label, p) in [("p50", 0.5), ("p90", 0.9), ("p99", 0.99), ("max", 1.0)] {
+                    if let Some(time) = report::percentile(&times, p) {
+                        fields.insert(format!("time_{label}_ms"), time.into());
+                    }
+                }
+                (name.clone(), Value::Object(fields))
+            })
+            .collect::<Map<_, _>>();
+        report.set("classes", classes);
+    }
+}
@@ -0,0 +1,71 @@
+//! What the loopback tests share. Every test binary uses a part of it.
+#![allow(dead_code)]
+
+use std::{
+    fs,
+    future::Future,
+    net::{SocketAddr, UdpSocket},
+    path::PathBuf,
+    sync::{Arc, LazyLock},
+};
+
+use big_file_test::{
+    certs::{Ca, CertSpec, Issued, KeyType},
+    transfer::Progress,
+    validate,
+};
+use rustls::RootCertStore;
+use time::Duration;
+use tokio::sync::oneshot;
+
+/// `len` bytes that are the same for the same seed, what `--expect-seed`
+/// checks for.
+pub fn payload(seed: u64, len: usize) -> Vec<u8> {
+    validate::seeded(seed, len)
+}
+
+/// A CA and a `localhost` certificate it signed, both of `key_type`.
+pub fn certs(key_type: KeyType) -> (Ca, Issued) {
+    let ca = Ca::new(&CertSpec::new(key_type, &[], Duration::days(1))).unwrap();
+    let spec = CertSpec::new(key_type, &["localhost", "::1"], Duration::days(1));
+    let server = ca.issue(&spec).unwrap();
+    (ca, server)
+}
+
+/// What most tests run with, made once per test binary.
+pub static CERTS: LazyLock<(Ca, Issued)> = LazyLock::new(|| certs(KeyType::P256));
+
+pub fn roots() -> RootCertStore {
+    CERTS.0.roots().unwrap()
+}
+
+/// A loopback address with a UDP port nobody is bound to right now.
+pub fn udp_addr() -> SocketAddr {
+    UdpSocket::bind("[::1]:0").unwrap().local_addr().unwrap()
+}
+
+/// An empty directory of this test run.
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("big-file-test-{}-{name}", std::process::id()));
+    _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Progress of `streams` transfers of `len` bytes, never drawn.
+pub fn progress(streams: usize, len: usize) -> Arc<Progress> {
+    Arc::new(Progress::new(streams, len as u64, false))
+}
+
+/// The shutdown future to hand a server and what resolves it.
+pub fn shutdown() -> (oneshot::Sender<()>, impl Future<Output = ()>) {
+    let (stop, stopped) = oneshot::channel();
+    (stop, async {
+        _ = stopped.await;
+    })
+}
+
+/// gm-quic and h2 servers look the provider up from the process.
+pub fn install_crypto_provider() {
+    _ = rustls::crypto::ring::default_provider().install_default();
+}
@@ -0,0 +1,104 @@
+//! `client` sending datagrams to `server`, reflected alone and counted next to
+//! stream transfers. gm-quic runs one server per process, so this binary has
+//! one test.
+
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use big_file_test::{
+    congestion::CongestionOptions,
+    datagram::{DatagramMode, DatagramOptions},
+    echo,
+    report::ServerStats,
+    sockets::Sockets,
+    tls::{ResumptionOptions, TicketOptions},
+};
+use qlog::telemetry::handy::NullLogger;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reflects_and_counts_datagrams() {
+    common::install_crypto_provider();
+    let addr = common::udp_addr();
+    let congestion = CongestionOptions::default();
+    let server = echo::listen(
+        addr,
+        common::CERTS.1.cert_pem().as_bytes(),
+        common::CERTS.1.key_pem().as_bytes(),
+        None,
+        &TicketOptions::default(),
+        &congestion,
+        Arc::new(NullLogger),
+    )
+    .unwrap();
+
+    let stats = Arc::new(ServerStats::default());
+    let (stop, shutdown) = common::shutdown();
+    let serving = tokio::spawn({
+        let server = server.clone();
+        let stats = stats.clone();
+        async move { echo::serve(&server, stats, shutdown).await }
+    });
+
+    let sockets = Sockets::default();
+    let client = echo::client(
+        common::roots(),
+        None,
+        &sockets,
+        &ResumptionOptions::default(),
+        &congestion,
+        Arc::new(NullLogger),
+    )
+    .unwrap();
+    let file = Arc::new(common::payload(1, 300_000));
+    let streams = 2;
+    // slow enough for loopback to lose none
+    let mut options = DatagramOptions {
+        datagrams: Some(200),
+        datagram_size: 512,
+        datagram_rate: Some(2000.0),
+        datagram_mode: DatagramMode::Reflect,
+        with_streams: false,
+    };
+
+    let connection = client.connect("localhost", addr).unwrap();
+    let reflected = echo::send_datagrams(
+        &connection,
+        file.clone(),
+        common::progress(streams, file.len()),
+        &options,
+    )
+    .await
+    .unwrap();
+    connection.close("no error".into(), 0);
+    assert_eq!(reflected.bytes(), 200 * 512);
+    assert_eq!(reflected.received(), 200);
+
+    // counted ones don't come back, the streams still echo in between
+    options.datagram_mode = DatagramMode::Count;
+    options.with_streams = true;
+    let connection = client.connect("localhost", addr).unwrap();
+    let counted = echo::send_datagrams(
+        &connection,
+        file.clone(),
+        common::progress(streams, file.len()),
+        &options,
+    )
+    .await
+    .unwrap();
+    connection.close("no error".into(), 0);
+    assert_eq!(counted.received(), 0);
+
+    stop.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), serving)
+        .await
+        .expect("server didn't shut down")
+        .unwrap();
+    assert_eq!(stats.connections(), 2);
+    assert_eq!(stats.bytes(), (streams * file.len()) as u64);
+    // all reflected ones got there, but gm-quic 0.2.2 only packs the counted
+    // ones into what room the stream data leaves, so some may still be on
+    // their way when the client closes
+    assert!((200..=400).contains(&stats.datagrams()), "{stats:?}");
+    assert_eq!(stats.datagram_bytes(), stats.datagrams() * 512);
+}
@@ -0,0 +1,73 @@
+//! `server` and `client` over loopback. gm-quic runs one server per process,
+//! so this binary has one test.
+
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use big_file_test::{
+    congestion::CongestionOptions,
+    echo,
+    report::ServerStats,
+    sockets::Sockets,
+    tls::{ResumptionOptions, TicketOptions},
+};
+use qlog::telemetry::handy::NullLogger;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn echoes_every_stream() {
+    common::install_crypto_provider();
+    let addr = common::udp_addr();
+    let congestion = CongestionOptions::default();
+    let server = echo::listen(
+        addr,
+        common::CERTS.1.cert_pem().as_bytes(),
+        common::CERTS.1.key_pem().as_bytes(),
+        None,
+        &TicketOptions::default(),
+        &congestion,
+        Arc::new(NullLogger),
+    )
+    .unwrap();
+
+    let stats = Arc::new(ServerStats::default());
+    let (stop, shutdown) = common::shutdown();
+    let serving = tokio::spawn({
+        let server = server.clone();
+        let stats = stats.clone();
+        async move { echo::serve(&server, stats, shutdown).await }
+    });
+
+    let sockets = Sockets::default();
+    let client = echo::client(
+        common::roots(),
+        None,
+        &sockets,
+        &ResumptionOptions::default(),
+        &congestion,
+        Arc::new(NullLogger),
+    )
+    .unwrap();
+    let file = Arc::new(common::payload(1, 300_000));
+    let streams = 4;
+    for _ in 0..2 {
+        let connection = client.connect("localhost", addr).unwrap();
+        // fails on the first byte that differs or a stream cut short
+        echo::echo_streams(
+            &connection,
+            file.clone(),
+            common::progress(streams, file.len()),
+        )
+        .await
+        .unwrap();
+        connection.close("no error".into(), 0);
+    }
+
+    stop.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), serving)
+        .await
+        .expect("server didn't shut down")
+        .unwrap();
+    assert_eq!(stats.connections(), 2);
+    assert_eq!(stats.bytes(), (2 * streams * file.len()) as u64);
+}
@@ -0,0 +1,763 @@
+//! `h3-server`, `h2-server` and `h3-client` over loopback, including the
+//! handshake storm, session resumption and happy eyeballs. gm-quic runs one server per
+//! process, so HTTP/3 has one test here and the others in the `h3_*` binaries.
+
+mod common;
+
+use std::{
+    fs, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use big_file_test::{
+    congestion::CongestionOptions,
+    eyeballs::Candidates,
+    failure::{Failed, Failure, Failures, RetryOptions},
+    fetch::{self, Connector, HandshakeKind, Protocol, Requester},
+    files,
+    priority::{Mix, Priority, Urgent},
+    report::{Report, ServerStats},
+    request::RequestOptions,
+    storm::{self, StormOptions},
+    tls::{ResumptionOptions, TicketOptions},
+    validate::{Expect, ValidateOptions},
+    workload::{ByClass, Order, Workload, WorkloadOptions},
+};
+use http::{StatusCode, Uri};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget};
+use tokio::{net::TcpListener, sync::oneshot, task::JoinHandle};
+
+/// The most `/gen/` bytes the servers answer with.
+const GEN_MAX: u64 = 1 << 20;
+
+/// A root with a small and a nested larger file, and a secret next to it.
+fn root(name: &str) -> (PathBuf, Vec<u8>, Vec<u8>) {
+    let dir = common::scratch_dir(name);
+    let root = dir.join("root");
+    fs::create_dir_all(root.join("nested")).unwrap();
+    let small = common::payload(3, 15 * 1024);
+    let large = common::payload(4, 1024 * 1024 + 7);
+    fs::write(root.join("small"), &small).unwrap();
+    fs::write(root.join("nested/large"), &large).unwrap();
+    fs::write(dir.join("secret"), b"not served").unwrap();
+    (root, small, large)
+}
+
+async fn get(requester: &mut Requester, addr: SocketAddr, path: &str) -> (StatusCode, Vec<u8>) {
+    let uri = format!("https://localhost:{}{path}", addr.port())
+        .parse::<Uri>()
+        .unwrap();
+    let mut body = vec![];
+    let status = requester
+        .get(&uri, |chunk| body.extend_from_slice(chunk))
+        .await
+        .unwrap();
+    (status, body)
+}
+
+/// Requests every case over one connection, returning the bytes served.
+async fn check(requester: &mut Requester, addr: SocketAddr, small: &[u8], large: &[u8]) -> u64 {
+    let (status, body) = get(requester, addr, "/small").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body == small, "small file differs");
+
+    let (status, body) = get(requester, addr, "/nested/large").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body == large, "large file differs");
+
+    for path in ["/missing", "/../secret", "/nested/../small"] {
+        let (status, body) = get(requester, addr, path).await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{path}");
+        assert!(body.is_empty(), "{path}");
+    }
+
+    let (status, body) = get(requester, addr, &format!("/gen/{}", GEN_MAX + 1)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.is_empty());
+
+    (small.len() + large.len()) as u64
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn h3_serves_files() {
+    common::install_crypto_provider();
+    let (root, small, large) = root("h3");
+    let addr = common::udp_addr();
+    let congestion = CongestionOptions::default();
+    let server = files::listen_h3(
+        &[addr],
+        common::CERTS.1.cert_pem().as_bytes(),
+        common::CERTS.1.key_pem().as_bytes(),
+        None,
+        &TicketOptions::default(),
+        &congestion,
+    )
+    .unwrap();
+
+    let stats = Arc::new(ServerStats::default());
+    let (stop, shutdown) = common::shutdown();
+    let serving = tokio::spawn({
+        let server = server.clone();
+        let stats = stats.clone();
+        async move {
+            files::serve_h3(
+                &server,
+                Arc::new(root),
+                GEN_MAX,
+                true,
+                false,
+                stats,
+                shutdown,
+            )
+            .await
+        }
+    });
+
+    let connector = Connector::new(
+        Protocol::H3,
+        common::roots(),
+        None,
+        &ResumptionOptions::default(),
+        &congestion,
+    )
+    .unwrap();
+    let (mut requester, _) = connector.connect(addr, "localhost").await.unwrap();
+    let served = check(&mut requester, addr, &small, &large).await;
+
+    stop.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), serving)
+        .await
+        .expect("server didn't shut down")
+        .unwrap();
+    assert_eq!(stats.connections(), 1);
+    assert_eq!(stats.bytes(), served);
+}
+
+/// An h2/http1 server of `root` and what stops it.
+struct TcpServer {
+    addr: SocketAddr,
+    stats: Arc<ServerStats>,
+    stop: oneshot::Sender<()>,
+    serving: JoinHandle<io::Result<()>>,
+}
+
+impl TcpServer {
+    async fn start(root: &Path, tickets: &TicketOptions) -> Self {
+        common::install_crypto_provider();
+        let listener = TcpListener::bind("[::1]:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (_, server) = &*common::CERTS;
+        let acceptor =
+            files::h2_acceptor(vec![server.cert_der()], server.key_der(), None, tickets).unwrap();
+
+        let stats = Arc::new(ServerStats::default());
+        let (stop, shutdown) = common::shutdown();
+        let serving = tokio::spawn(files::serve_h2(
+            vec![listener],
+            acceptor,
+            Arc::new(root.to_owned()),
+            GEN_MAX,
+            true,
+            stats.clone(),
+            shutdown,
+        ));
+        Self {
+            addr,
+            stats,
+            stop,
+            serving,
+        }
+    }
+
+    async fn stop(self) -> Arc<ServerStats> {
+        self.stop.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), self.serving)
+            .await
+            .expect("server didn't shut down")
+            .unwrap()
+            .unwrap();
+        self.stats
+    }
+}
+
+async fn serves_files_over_tcp(protocol: Protocol, root: &Path, small: &[u8], large: &[u8]) {
+    let server = TcpServer::start(root, &TicketOptions::default()).await;
+    let addr = server.addr;
+
+    let connector = Connector::new(
+        protocol,
+        common::roots(),
+        None,
+        &ResumptionOptions::default(),
+        &CongestionOptions::default(),
+    )
+    .unwrap();
+    let (mut requester, _) = connector.connect(addr, "localhost").await.unwrap();
+    let served = check(&mut requester, addr, small, large).await;
+
+    let stats = server.stop().await;
+    assert_eq!(stats.connections(), 1);
+    assert_eq!(stats.bytes(), served);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn h2_serves_files() {
+    let (root, small, large) = root("h2");
+    serves_files_over_tcp(Protocol::H2, &root, &small, &large).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn http1_serves_files() {
+    let (root, small, large) = root("http1");
+    serves_files_over_tcp(Protocol::Http1, &root, &small, &large).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn storm_opens_and_closes_connections() {
+    let (root, small, _) = root("storm");
+    let server = TcpServer::start(&root, &TicketOptions::default()).await;
+    let addr = server.addr;
+    let connector = Connector::new(
+        Protocol::H2,
+        common::roots(),
+        None,
+        &ResumptionOptions::default(),
+        &CongestionOptions::default(),
+    )
+    .unwrap();
+    let uri = |path: &str| {
+        format!("https://localhost:{}{path}", addr.port())
+            .parse::<Uri>()
+            .unwrap()
+    };
+    let mut options = StormOptions {
+        storm: Some(20),
+        storm_rate: None,
+        storm_concurrency: 4,
+        storm_request: true,
+        storm_timeout: 10.0,
+    };
+
+    let storm = storm::storm(
+        connector.clone(),
+        addr.into(),
+        uri("/small"),
+        &options,
+        ProgressBar::hidden(),
+    )
+    .await;
+    assert_eq!(storm.handshakes.len(), 20);
+    assert!(storm.failures.by_class.is_empty(), "{:?}", storm.failures);
+    assert_eq!(storm.bytes, 20 * small.len() as u64);
+
+    // failures are counted by what went wrong, a rate spaces the handshakes
+    options.storm = Some(5);
+    options.storm_rate = Some(100.0);
+    let storm = storm::storm(
+        connector,
+        addr.into(),
+        uri("/missing"),
+        &options,
+        ProgressBar::hidden(),
+    )
+    .await;
+    assert!(storm.handshakes.is_empty());
+    assert_eq!(
+        storm.failures.by_class.into_iter().collect::<Vec<_>>(),
+        [(Failure::Status(404), 5)]
+    );
+    // four intervals, less what passed before the first tick
+    assert!(storm.elapsed >= 0.03, "{}", storm.elapsed);
+
+    let stats = server.stop().await;
+    assert_eq!(stats.connections(), 25);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn races_past_blackholed_and_refusing_addresses() {
+    let (root, small, _) = root("eyeballs");
+    let server = TcpServer::start(&root, &TicketOptions::default()).await;
+    // takes connections but never answers the TLS handshake
+    let unanswered = std::net::TcpListener::bind("[::1]:0").unwrap();
+    let blackhole = unanswered.local_addr().unwrap();
+    // nothing listens here any more
+    let refusing = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let connector = Connector::new(
+        Protocol::H2,
+        common::roots(),
+        None,
+        &ResumptionOptions::default(),
+        &CongestionOptions::default(),
+    )
+    .unwrap();
+    let uri = format!("https://localhost:{}/small", server.addr.port())
+        .parse::<Uri>()
+        .unwrap();
+
+    // the families alternate, so the refused IPv4 address goes second
+    let candidates = Candidates::new(
+        vec![blackhole, server.addr, refusing],
+        Duration::from_secs(1),
+    );
+    assert_eq!(candidates.addrs(), [blackhole, refusing, server.addr]);
+    let probe = fetch::probe(&connector, &candidates, &uri, true)
+        .await
+        .unwrap();
+    assert_eq!(probe.addr, server.addr);
+    assert_eq!(probe.bytes, small.len() as u64);
+    // the second attempt failing starts the third without another delay,
+    // which would be at 2s
+    assert!(probe.connect_time >= 1.0, "{}", probe.connect_time);
+    assert!(probe.connect_time < 2.0, "{}", probe.connect_time);
+
+    // none connecting is the last error
+    let candidates = Candidates::new(vec![refusing], Duration::from_millis(200));
+    let error = fetch::probe(&connector, &candidates, &uri, true)
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("refused"), "{error}");
+
+    let stats = server.stop().await;
+    assert_eq!(stats.connections(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn schedules_responses_by_priority() {
+    let (root, small, _) = root("priority");
+    // big enough that what hyper buffers ahead of a response hardly counts
+    let large = common::payload(5, 8 * 1024 * 1024);
+    fs::write(root.join("big"), &large).unwrap();
+    let server = TcpServer::start(&root, &TicketOptions::default()).await;
+    let connector = Connector::new(
+        Protocol::H2,
+        common::roots(),
+        None,
+        &ResumptionOptions::default(),
+        &CongestionOptions::default(),
+    )
+    .unwrap();
+    let uri = |path: &str| {
+        format!("https://localhost:{}{path}", server.addr.port())
+            .parse::<Uri>()
+            .unwrap()
+    };
+    // three big responses that wait for each other, two small ones after
+    // them that go first
+    let mix = Mix {
+        priority: Some(Priority {
+            urgency: 5,
+            incremental: false,
+        }),
+        urgent: Some(Urgent {
+            uri: uri("/small"),
+            reqs: 2,
+            priority: Priority {
+                urgency: 0,
+                incremental: false,
+            },
+            after: Duration::ZERO,
+        }),
+    };
+    let pbs = MultiProgress::with_draw_target(ProgressDrawTarget::hidden());
+    let fetched = fetch::for_each_connection(
+        connector,
+        server.addr.into(),
+        Workload::single(uri("/big"), Expect::default()),
+        0,
+        3,
+        mix,
+        RetryOptions::default(),
+        pbs.add(ProgressBar::hidden()),
+        pbs,
+    )
+    .await
+    .unwrap();
+    assert_eq!(fetched.requests, 5);
+    assert_eq!(fetched.bytes, (3 * large.len() + 2 * small.len()) as u64);
+
+    let (urgent, mut big) = fetched
+        .finishes
+        .into_iter()
+        .partition::<Vec<_>, _>(|finish| finish.urgent);
+    big.sort_by(|a, b| a.finished.total_cmp(&b.finished));
+    assert_eq!((urgent.len(), big.len()), (2, 3));
+    assert!(
+        urgent
+            .iter()
+            .all(|finish| finish.finished < big[1].finished),
+        "{urgent:?} {big:?}"
+    );
+    // side by side they would all finish at about the same time, one after
+    // another the first at a third
+    assert!(big[0].finished < 0.9 * big[2].finished, "{big:?}");
+
+    let stats = server.stop().await;
+    assert_eq!(stats.connections(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn classifies_retries_and_times_out_failures() {
+    let (root, _, _) = root("failures");
+    let server = TcpServer::start(&root, &TicketOptions::default()).await;
+    let unanswered = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let blackhole = unanswered.local_addr().unwrap();
+    let refusing = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let connector = Connector::new(
+        Protocol::H2,
+        common::roots(),
+        None,
+        &ResumptionOptions::default(),
+        &CongestionOptions::default(),
+    )
+    .unwrap();
+    let retry = RetryOptions {
+        connection_timeout: Some(0.3),
+        retries: 1,
+        retry_backoff: 10.0,
+        ..Default::default()
+    };
+    let fetch = |addr: SocketAddr, path: &str| {
+        let uri = format!("https://localhost:{}{path}", addr.port())
+            .parse::<Uri>()
+            .unwrap();
+        let pbs = MultiProgress::with_draw_target(ProgressDrawTarget::hidden());
+        fetch::for_each_connection(
+            connector.clone(),
+            addr.into(),
+            Workload::single(uri, Expect::default()),
+            0,
+            3,
+            Mix::default(),
+            retry.clone(),
+            pbs.add(ProgressBar::hidden()),
+            pbs,
+        )
+    };
+    let failures = |failed: Failed| {
+        let Failures { by_class, retries } = failed.failures;
+        (by_class.into_iter().collect::<Vec<_>>(), retries)
+    };
+
+    // a missing file would come again, so it isn't tried again
+    let failed = fetch(server.addr, "/missing").await.err().unwrap();
+    assert_eq!(failures(failed), (vec![(Failure::Status(404), 3)], 0));
+
+    // nothing listening is tried again
+    let failed = fetch(refusing, "/small").await.err().unwrap();
+    assert_eq!(failures(failed), (vec![(Failure::Connect, 2)], 1));
+
+    // the stalled handshake runs into the connection timeout, which the
+    // retry doesn't get past, rather than waiting forever
+    let start = std::time::Instant::now();
+    let failed = fetch(blackhole, "/small").await.err().unwrap();
+    let elapsed = start.elapsed();
+    assert!(elapsed < Duration::from_secs(5), "{elapsed:?}");
+    assert_eq!(failures(failed), (vec![(Failure::Timeout, 2)], 1));
+
+    let stats = server.stop().await;
+    assert_eq!(stats.connections(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn validates_responses() {
+    let (root, small, _) = root("validate");
+    fs::write(root.join("other"), common::payload(5, small.len())).unwrap();
+    let server = TcpServer::start(&root, &TicketOptions::default()).await;
+    let connector = Connector::new(
+        Protocol::H2,
+        common::roots(),
+        None,
+        &ResumptionOptions::default(),
+        &CongestionOptions::default(),
+    )
+    .unwrap();
+    let fetch = |path: &str, validate: ValidateOptions| {
+        let uri = format!("https://localhost:{}{path}", server.addr.port())
+            .parse::<Uri>()
+            .unwrap();
+        let pbs = MultiProgress::with_draw_target(ProgressDrawTarget::hidden());
+        fetch::for_each_connection(
+            connector.clone(),
+            server.addr.into(),
+            Workload::single(uri, validate.expect().unwrap()),
+            0,
+            2,
+            Mix::default(),
+            RetryOptions::default(),
+            pbs.add(ProgressBar::hidden()),
+            pbs,
+        )
+    };
+    let seeded = ValidateOptions {
+        expect_size: Some(small.len() as u64),
+        expect_seed: Some(3),
+        ..Default::default()
+    };
+
+    let fetched = fetch("/small", seeded.clone()).await.ok().unwrap();
+    assert_eq!(fetched.requests, 2);
+    assert_eq!(fetched.bytes, 2 * small.len() as u64);
+    assert!(
+        fetched
+            .finishes
+            .iter()
+            .all(|finish| finish.bytes == small.len() as u64)
+    );
+
+    // generated a block at a time, the same bytes
+    let generated = ValidateOptions {
+        expect_size: Some(200_003),
+        expect_seed: Some(7),
+        ..Default::default()
+    };
+    let fetched = fetch("/gen/200003/7", generated).await.ok().unwrap();
+    assert_eq!(fetched.bytes, 2 * 200_003);
+
+    let mismatch = |failed: Failed| failed.failures.by_class.into_iter().collect::<Vec<_>>();
+    // as long, other bytes
+    let failed = fetch("/other", seeded).await.err().unwrap();
+    assert_eq!(mismatch(failed), [(Failure::Content, 2)]);
+    let longer = ValidateOptions {
+        expect_size: Some(small.len() as u64 + 1),
+        ..Default::default()
+    };
+    let failed = fetch("/small", longer).await.err().unwrap();
+    assert_eq!(mismatch(failed), [(Failure::Length, 2)]);
+    // the file to compare with has to be as long as expected
+    let file = ValidateOptions {
+        expect_size: Some(small.len() as u64 + 1),
+        expect_file: Some(root.join("small")),
+        ..Default::default()
+    };
+    assert!(file.expect().is_err());
+
+    let missing = ValidateOptions {
+        expect_status: Some(404),
+        ..Default::default()
+    };
+    let fetched = fetch("/missing", missing).await.ok().unwrap();
+    assert_eq!(fetched.requests, 2);
+
+    let stats = server.stop().await;
+    assert_eq!(stats.connections(), 5);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn requests_a_weighted_workload() {
+    let (root, small, _) = root("workload");
+    let server = TcpServer::start(&root, &TicketOptions::default()).await;
+    let connector = Connector::new(
+        Protocol::H2,
+        common::roots(),
+        None,
+        &ResumptionOptions::default(),
+        &CongestionOptions::default(),
+    )
+    .unwrap();
+    let uri = format!("https://localhost:{}/small", server.addr.port())
+        .parse::<Uri>()
+        .unwrap();
+    let file = root.parent().unwrap().join("workload.json");
+    fs::write(
+        &file,
+        r#"[
+            {"class": "small", "weight": 3, "uri": "/small"},
+            {"class": "generated", "sizes": [1000, 4096]}
+        ]"#,
+    )
+    .unwrap();
+    let options = |order| WorkloadOptions {
+        workload: Some(file.clone()),
+        workload_order: order,
+        workload_seed: 1,
+    };
+
+    // the same seed picks the same targets
+    let random = options(Order::Random)
+        .load(&uri, Expect::default())
+        .unwrap();
+    let again = options(Order::Random)
+        .load(&uri, Expect::default())
+        .unwrap();
+    let picks = |workload: &Workload| {
+        (0..100)
+            .map(|n| workload.target(n).uri.clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(picks(&random), picks(&again));
+
+    let workload = options(Order::RoundRobin)
+        .load(&uri, Expect::default())
+        .unwrap();
+    assert_eq!(workload.classes(), ["small", "generated"]);
+    let pbs = MultiProgress::with_draw_target(ProgressDrawTarget::hidden());
+    let fetched = fetch::for_each_connection(
+        connector,
+        server.addr.into(),
+        workload.clone(),
+        0,
+        8,
+        Mix::default(),
+        RetryOptions::default(),
+        pbs.add(ProgressBar::hidden()),
+        pbs,
+    )
+    .await
+    .ok()
+    .unwrap();
+    // three of the small file, then one of each size in turn
+    assert_eq!(fetched.requests, 8);
+    assert_eq!(fetched.bytes, (6 * small.len() + 1000 + 4096) as u64);
+
+    let mut by_class = ByClass::default();
+    by_class.record(&fetched.finishes);
+    let mut report = Report::start("workload");
+    by_class.report(&mut report, &workload, 1.0);
+    let classes = report.get("classes").unwrap();
+    assert_eq!(classes["small"]["requests"], 6);
+    assert_eq!(classes["generated"]["requests"], 2);
+    assert_eq!(classes["generated"]["bytes"], 5096);
+
+    let stats = server.stop().await;
+    assert_eq!(stats.connections(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn overrides_the_method_headers_address_and_server_name() {
+    let (root, _, _) = root("request");
+    let server = TcpServer::start(&root, &TicketOptions::default()).await;
+    let connector = Connector::new(
+        Protocol::Http1,
+        common::roots(),
+        None,
+        &ResumptionOptions::default(),
+        &CongestionOptions::default(),
+    )
+    .unwrap();
+    // a host that neither resolves nor is in the certificate
+    let uri = "https://example.invalid/small".parse::<Uri>().unwrap();
+    let options = RequestOptions {
+        headers: vec![("user-agent".parse().unwrap(), "test".parse().unwrap())],
+        method: Some(http::Method::HEAD),
+        connect_to: Some(server.addr.to_string()),
+        sni: Some("localhost".to_owned()),
+    };
+    let (host, port) = options.destination(&uri).unwrap();
+    assert_eq!(format!("{host}:{port}"), server.addr.to_string());
+
+    let fetch = |sni: Option<&str>| {
+        let candidates = Candidates::from(server.addr).with_server_name(sni);
+        let pbs = MultiProgress::with_draw_target(ProgressDrawTarget::hidden());
+        fetch::for_each_connection(
+            connector.clone(),
+            candidates,
+            Workload::single(uri.clone(), Expect::default()).with_request(&options),
+            0,
+            2,
+            Mix::default(),
+            RetryOptions::default(),
+            pbs.add(ProgressBar::hidden()),
+            pbs,
+        )
+    };
+    // HEAD gets the Content-Length of the file but no body
+    let fetched = fetch(options.sni.as_deref()).await.ok().unwrap();
+    assert_eq!((fetched.requests, fetched.bytes), (2, 0));
+
+    let failed = fetch(None).await.err().unwrap();
+    let failures = failed.failures.by_class.into_iter().collect::<Vec<_>>();
+    assert_eq!(failures, [(Failure::Handshake, 1)]);
+
+    // the rejected handshake was a connection too
+    let stats = server.stop().await;
+    assert_eq!(stats.connections(), 2);
+}
+
+/// The handshakes of three connections in a row, each GETting `/small`.
+async fn handshakes(
+    protocol: Protocol,
+    tickets: &TicketOptions,
+    resumption: &ResumptionOptions,
+) -> Vec<Option<HandshakeKind>> {
+    let (root, small, _) = root(&format!("resume-{protocol:?}"));
+    let server = TcpServer::start(&root, tickets).await;
+    let addr = server.addr;
+    let connector = Connector::new(
+        protocol,
+        common::roots(),
+        None,
+        resumption,
+        &CongestionOptions::default(),
+    )
+    .unwrap();
+    let uri = format!("https://localhost:{}/small", addr.port())
+        .parse::<Uri>()
+        .unwrap();
+
+    let mut kinds = vec![];
+    for _ in 0..3 {
+        let probe = fetch::probe(&connector, &addr.into(), &uri, true)
+            .await
+            .unwrap();
+        assert_eq!(probe.bytes, small.len() as u64);
+        kinds.push(probe.handshake);
+    }
+    let stats = server.stop().await;
+    assert_eq!(stats.connections(), 3);
+    kinds
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn resumes_sessions_and_sends_early_data() {
+    use HandshakeKind::*;
+    let resume = ResumptionOptions {
+        resume: true,
+        ..Default::default()
+    };
+    let early_data = ResumptionOptions {
+        early_data: true,
+        ..Default::default()
+    };
+    let accept_early_data = TicketOptions {
+        max_early_data: 16384,
+        ..Default::default()
+    };
+    let stateless = TicketOptions {
+        stateless_tickets: true,
+        max_early_data: 16384,
+        ..Default::default()
+    };
+
+    // tickets are only kept if asked for
+    let tickets = TicketOptions::default();
+    let kinds = handshakes(Protocol::H2, &tickets, &ResumptionOptions::default()).await;
+    assert_eq!(kinds, [Some(Full); 3]);
+    let kinds = handshakes(Protocol::H2, &tickets, &resume).await;
+    assert_eq!(kinds, [Some(Full), Some(Resumed), Some(Resumed)]);
+    // the first request goes as early data, and again if the server refuses it
+    let kinds = handshakes(Protocol::H2, &accept_early_data, &early_data).await;
+    assert_eq!(kinds, [Some(Full), Some(EarlyData), Some(EarlyData)]);
+    let kinds = handshakes(Protocol::Http1, &accept_early_data, &early_data).await;
+    assert_eq!(kinds, [Some(Full), Some(EarlyData), Some(EarlyData)]);
+    let kinds = handshakes(Protocol::H2, &tickets, &early_data).await;
+    assert_eq!(kinds, [Some(Full), Some(Resumed), Some(Resumed)]);
+    // stateless tickets could be replayed
+    let kinds = handshakes(Protocol::H2, &stateless, &early_data).await;
+    assert_eq!(kinds, [Some(Full), Some(Resumed), Some(Resumed)]);
+    // no tickets, nothing to resume
+    let no_tickets = TicketOptions {
+        session_tickets: 0,
+        ..Default::default()
+    };
+    let kinds = handshakes(Protocol::H2, &no_tickets, &resume).await;
+    assert_eq!(kinds, [Some(Full); 3]);
+}
@@ -0,0 +1,155 @@
+//! `h3-client` against an HTTP/3 server that stalls, cuts short, resets and
+//! closes under its requests, and an address that never answers, counted by
+//! class. gm-quic runs one server per process, so this binary has one test.
+
+mod common;
+
+use std::{net::SocketAddr, sync::Arc};
+
+use big_file_test::{
+    congestion::CongestionOptions,
+    failure::{Failed, Failure, Failures, RetryOptions},
+    fetch::{self, Connector, Protocol},
+    files,
+    priority::Mix,
+    tls::{ResumptionOptions, TicketOptions},
+    validate::Expect,
+    workload::Workload,
+};
+use bytes::Bytes;
+use gm_quic::QuicServer;
+use h3::error::Code;
+use http::{Response, Uri, header};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget};
+use tokio::task::JoinSet;
+
+/// Resets `/reset` before answering, closes the connection under `/close`
+/// and ends `/short` before the Content-Length it gave. Anything else gets
+/// the head of a response and never the body.
+async fn misbehave(server: Arc<QuicServer>) {
+    let mut connections = JoinSet::new();
+    while let Ok((quic, _pathway)) = server.accept().await {
+        connections.spawn(async move {
+            let mut connection: h3::server::Connection<_, Bytes> = h3::server::builder()
+                .build(h3_shim::QuicConnection::new(quic.clone()).await)
+                .await
+                .unwrap();
+            let mut stalled = vec![];
+            while let Ok(Some((request, mut stream))) = connection.accept().await {
+                match request.uri().path() {
+                    "/reset" => stream.stop_stream(Code::H3_REQUEST_REJECTED),
+                    "/close" => quic.close("closing".into(), Code::H3_EXCESSIVE_LOAD.value()),
+                    "/short" => {
+                        let response = Response::builder()
+                            .header(header::CONTENT_LENGTH, 100)
+                            .body(())
+                            .unwrap();
+                        stream.send_response(response).await.unwrap();
+                        stream
+                            .send_data(Bytes::from_static(&[0; 10]))
+                            .await
+                            .unwrap();
+                        stream.finish().await.unwrap();
+                    }
+                    _ => {
+                        stream.send_response(Response::new(())).await.unwrap();
+                        stalled.push(stream);
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn classifies_h3_failures() {
+    common::install_crypto_provider();
+    let addr = common::udp_addr();
+    let congestion = CongestionOptions::default();
+    let server = files::listen_h3(
+        &[addr],
+        common::CERTS.1.cert_pem().as_bytes(),
+        common::CERTS.1.key_pem().as_bytes(),
+        None,
+        &TicketOptions::default(),
+        &congestion,
+    )
+    .unwrap();
+    let serving = tokio::spawn(misbehave(server.clone()));
+    // takes the handshake's packets but never answers
+    let unanswered = std::net::UdpSocket::bind("[::1]:0").unwrap();
+    let blackhole = unanswered.local_addr().unwrap();
+
+    let connector = Connector::new(
+        Protocol::H3,
+        common::roots(),
+        None,
+        &ResumptionOptions::default(),
+        &congestion,
+    )
+    .unwrap();
+    let retry = RetryOptions {
+        request_timeout: Some(0.2),
+        connection_timeout: Some(2.0),
+        retries: 1,
+        retry_backoff: 10.0,
+    };
+    let fetch = |addr: SocketAddr, path: &str| {
+        let uri = format!("https://localhost:{}{path}", addr.port())
+            .parse::<Uri>()
+            .unwrap();
+        let pbs = MultiProgress::with_draw_target(ProgressDrawTarget::hidden());
+        fetch::for_each_connection(
+            connector.clone(),
+            addr.into(),
+            Workload::single(uri, Expect::default()),
+            0,
+            2,
+            Mix::default(),
+            retry.clone(),
+            pbs.add(ProgressBar::hidden()),
+            pbs,
+        )
+    };
+    let failures = |failed: Failed| {
+        let Failures { by_class, retries } = failed.failures;
+        (by_class.into_iter().collect::<Vec<_>>(), retries)
+    };
+
+    // a body that doesn't come runs into the request timeout, every time
+    let failed = fetch(addr, "/stall").await.err().unwrap();
+    assert_eq!(failures(failed), (vec![(Failure::Timeout, 4)], 2));
+
+    // a body shorter than its Content-Length would be again
+    let failed = fetch(addr, "/short").await.err().unwrap();
+    assert_eq!(failures(failed), (vec![(Failure::Length, 2)], 0));
+
+    // a reset stream is tried again on the same connection, h3-shim 0.2.2
+    // doesn't tell with which code
+    let failed = fetch(addr, "/reset").await.err().unwrap();
+    assert_eq!(failures(failed), (vec![(Failure::StreamReset(None), 4)], 2));
+
+    // a closed connection takes no more requests, the one that saw the
+    // close and the one after it may not get the same code
+    let failed = fetch(addr, "/close").await.err().unwrap();
+    let (by_class, retries) = failures(failed);
+    assert_eq!(retries, 0);
+    assert_eq!(by_class.iter().map(|(_, count)| count).sum::<usize>(), 2);
+    assert!(
+        by_class
+            .iter()
+            .all(|(failure, _)| matches!(failure, Failure::ConnectionClose(_))),
+        "{by_class:?}"
+    );
+
+    // the handshake, or the requests waiting for it, run into the timeouts
+    let failed = fetch(blackhole, "/").await.err().unwrap();
+    let (by_class, retries) = failures(failed);
+    assert!(retries >= 1);
+    assert!(
+        matches!(by_class[..], [(Failure::Timeout, 2..)]),
+        "{by_class:?}"
+    );
+
+    serving.abort();
+}
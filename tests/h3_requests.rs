@@ -0,0 +1,203 @@
+//! `h3-client` validating responses, having them scheduled by priority and
+//! doing full handshakes only against `h3-server`. gm-quic runs one server per
+//! process, so this binary has one test.
+
+mod common;
+
+use std::{fs, sync::Arc, time::Duration};
+
+use big_file_test::{
+    congestion::CongestionOptions,
+    failure::{Failed, Failure, RetryOptions},
+    fetch::{self, Connector, Fetched, HandshakeKind, Protocol},
+    files,
+    priority::{Mix, Priority, Urgent},
+    report::ServerStats,
+    tls::{ResumptionOptions, TicketOptions},
+    validate::{Expect, ValidateOptions},
+    workload::Workload,
+};
+use http::Uri;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn validates_schedules_and_never_resumes() {
+    common::install_crypto_provider();
+    let root = common::scratch_dir("h3-requests");
+    let small = common::payload(3, 15 * 1024);
+    let large = common::payload(4, 4 * 1024 * 1024);
+    fs::write(root.join("small"), &small).unwrap();
+    fs::write(root.join("big"), &large).unwrap();
+    let addr = common::udp_addr();
+    let congestion = CongestionOptions::default();
+    let listen = |tickets: &TicketOptions| {
+        files::listen_h3(
+            &[addr],
+            common::CERTS.1.cert_pem().as_bytes(),
+            common::CERTS.1.key_pem().as_bytes(),
+            None,
+            tickets,
+            &congestion,
+        )
+    };
+    // gm-quic has no 0-RTT to accept
+    let early_data = TicketOptions {
+        max_early_data: 16384,
+        ..Default::default()
+    };
+    let refused = listen(&early_data).err().unwrap();
+    assert_eq!(refused.kind(), std::io::ErrorKind::Unsupported);
+    let server = listen(&TicketOptions::default()).unwrap();
+
+    let stats = Arc::new(ServerStats::default());
+    let (stop, shutdown) = common::shutdown();
+    let serving = tokio::spawn({
+        let server = server.clone();
+        let stats = stats.clone();
+        async move {
+            files::serve_h3(
+                &server,
+                Arc::new(root),
+                1 << 20,
+                true,
+                false,
+                stats,
+                shutdown,
+            )
+            .await
+        }
+    });
+
+    let connector = Connector::new(
+        Protocol::H3,
+        common::roots(),
+        None,
+        &ResumptionOptions::default(),
+        &congestion,
+    )
+    .unwrap();
+    let uri = |path: &str| {
+        format!("https://localhost:{}{path}", addr.port())
+            .parse::<Uri>()
+            .unwrap()
+    };
+    let fetch = |workload: Workload, reqs: usize, mix: Mix| {
+        let pbs = MultiProgress::with_draw_target(ProgressDrawTarget::hidden());
+        fetch::for_each_connection(
+            connector.clone(),
+            addr.into(),
+            workload,
+            0,
+            reqs,
+            mix,
+            RetryOptions::default(),
+            pbs.add(ProgressBar::hidden()),
+            pbs,
+        )
+    };
+    let validated = |path: &str, validate: ValidateOptions| {
+        fetch(
+            Workload::single(uri(path), validate.expect().unwrap()),
+            2,
+            Mix::default(),
+        )
+    };
+    let mismatch = |failed: Failed| failed.failures.by_class.into_iter().collect::<Vec<_>>();
+
+    // generated a block at a time, the same bytes
+    let generated = ValidateOptions {
+        expect_size: Some(200_003),
+        expect_seed: Some(7),
+        ..Default::default()
+    };
+    let fetched = validated("/gen/200003/7", generated).await.ok().unwrap();
+    assert_eq!((fetched.requests, fetched.bytes), (2, 2 * 200_003));
+    // as long, other bytes
+    let other = ValidateOptions {
+        expect_size: Some(small.len() as u64),
+        expect_seed: Some(5),
+        ..Default::default()
+    };
+    let failed = validated("/small", other).await.err().unwrap();
+    assert_eq!(mismatch(failed), [(Failure::Content, 2)]);
+    let longer = ValidateOptions {
+        expect_size: Some(small.len() as u64 + 1),
+        ..Default::default()
+    };
+    let failed = validated("/small", longer).await.err().unwrap();
+    assert_eq!(mismatch(failed), [(Failure::Length, 2)]);
+
+    // three big responses that wait for each other, two small ones after
+    // them that go first
+    let mix = Mix {
+        priority: Some(Priority {
+            urgency: 5,
+            incremental: false,
+        }),
+        urgent: Some(Urgent {
+            uri: uri("/small"),
+            reqs: 2,
+            priority: Priority {
+                urgency: 0,
+                incremental: false,
+            },
+            after: Duration::ZERO,
+        }),
+    };
+    let fetched = fetch(Workload::single(uri("/big"), Expect::default()), 3, mix)
+        .await
+        .ok()
+        .unwrap();
+    assert_eq!(fetched.requests, 5);
+    assert_eq!(fetched.bytes, (3 * large.len() + 2 * small.len()) as u64);
+    let Fetched { finishes, .. } = fetched;
+    let (urgent, mut big) = finishes
+        .into_iter()
+        .partition::<Vec<_>, _>(|finish| finish.urgent);
+    big.sort_by(|a, b| a.finished.total_cmp(&b.finished));
+    assert_eq!((urgent.len(), big.len()), (2, 3));
+    assert!(
+        urgent
+            .iter()
+            .all(|finish| finish.finished < big[1].finished),
+        "{urgent:?} {big:?}"
+    );
+
+    // gm-quic clients drop the tickets the server sends, so asking to resume
+    // is refused and every connection does a full handshake
+    for resumption in [
+        ResumptionOptions {
+            resume: true,
+            ..Default::default()
+        },
+        ResumptionOptions {
+            early_data: true,
+            ..Default::default()
+        },
+    ] {
+        let refused = Connector::new(
+            Protocol::H3,
+            common::roots(),
+            None,
+            &resumption,
+            &congestion,
+        )
+        .err()
+        .unwrap();
+        assert!(refused.to_string().contains("resume"), "{refused}");
+    }
+    for _ in 0..3 {
+        let probe = fetch::probe(&connector, &addr.into(), &uri("/small"), true)
+            .await
+            .unwrap();
+        assert_eq!(probe.bytes, small.len() as u64);
+        assert_eq!(probe.handshake, Some(HandshakeKind::Full));
+    }
+
+    stop.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), serving)
+        .await
+        .expect("server didn't shut down")
+        .unwrap();
+    assert_eq!(stats.connections(), 7);
+}
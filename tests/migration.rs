@@ -0,0 +1,82 @@
+//! `client` moving its connection partway through the transfer. gm-quic runs
+//! one server per process, so this binary has one test, `rebinding.rs` has
+//! the moves of a `nat-proxy`.
+
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use big_file_test::{
+    congestion::CongestionOptions,
+    echo,
+    migration::MigrationOptions,
+    report::ServerStats,
+    sockets::Sockets,
+    tls::{ResumptionOptions, TicketOptions},
+};
+use qlog::telemetry::handy::NullLogger;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn transfers_survive_migration() {
+    common::install_crypto_provider();
+    let addr = common::udp_addr();
+    let congestion = CongestionOptions::default();
+    let server = echo::listen(
+        addr,
+        common::CERTS.1.cert_pem().as_bytes(),
+        common::CERTS.1.key_pem().as_bytes(),
+        None,
+        &TicketOptions::default(),
+        &congestion,
+        Arc::new(NullLogger),
+    )
+    .unwrap();
+
+    let stats = Arc::new(ServerStats::default());
+    let (stop, shutdown) = common::shutdown();
+    let serving = tokio::spawn({
+        let server = server.clone();
+        let stats = stats.clone();
+        async move { echo::serve(&server, stats, shutdown).await }
+    });
+
+    let sockets = Sockets::default();
+    let client = echo::client(
+        common::roots(),
+        None,
+        &sockets,
+        &ResumptionOptions::default(),
+        &congestion,
+        Arc::new(NullLogger),
+    )
+    .unwrap();
+    let file = Arc::new(common::payload(1, 2_000_000));
+    let streams = 4;
+
+    // halfway through the client moves to a new port itself
+    let options = MigrationOptions {
+        migrate_at: Some((streams * file.len() / 2) as u64),
+        ..Default::default()
+    };
+    let connection = client.connect("localhost", addr).unwrap();
+    let migration = echo::echo_migrating(
+        &connection,
+        &sockets,
+        addr,
+        file.clone(),
+        common::progress(streams, file.len()),
+        &options,
+    )
+    .await
+    .unwrap();
+    connection.close("no error".into(), 0);
+    assert!(migration.latency().is_some());
+
+    stop.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), serving)
+        .await
+        .expect("server didn't shut down")
+        .unwrap();
+    assert_eq!(stats.connections(), 1);
+    assert_eq!(stats.bytes(), (streams * file.len()) as u64);
+}
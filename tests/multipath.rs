@@ -0,0 +1,127 @@
+//! `client` spreading the transfer over a direct path and two more through
+//! `nat-proxy` relays impaired differently. gm-quic runs one server per
+//! process, so this binary has one test.
+
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use big_file_test::{
+    congestion::CongestionOptions,
+    echo,
+    multipath::{MultipathOptions, PathSpec},
+    nat::{self, Impairment, NatStats},
+    report::ServerStats,
+    sockets::Sockets,
+    tls::{ResumptionOptions, TicketOptions},
+};
+use qlog::telemetry::handy::NullLogger;
+use tokio::net::UdpSocket;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn transfers_spread_over_paths() {
+    common::install_crypto_provider();
+    let addr = common::udp_addr();
+    let congestion = CongestionOptions::default();
+    let server = echo::listen(
+        addr,
+        common::CERTS.1.cert_pem().as_bytes(),
+        common::CERTS.1.key_pem().as_bytes(),
+        None,
+        &TicketOptions::default(),
+        &congestion,
+        Arc::new(NullLogger),
+    )
+    .unwrap();
+
+    let stats = Arc::new(ServerStats::default());
+    let (stop, shutdown) = common::shutdown();
+    let serving = tokio::spawn({
+        let server = server.clone();
+        let stats = stats.clone();
+        async move { echo::serve(&server, stats, shutdown).await }
+    });
+
+    // a fast path with some delay and a slow one that also loses
+    let impairments = [
+        Impairment {
+            delay: 5.0,
+            ..Default::default()
+        },
+        Impairment {
+            delay: 20.0,
+            rate: Some(2_000_000.0),
+            loss: 0.01,
+            ..Default::default()
+        },
+    ];
+    let mut relays = vec![];
+    let mut paths = vec![];
+    for impairment in impairments {
+        let listener = UdpSocket::bind("[::1]:0").await.unwrap();
+        let relay_addr = listener.local_addr().unwrap();
+        let nat_stats = Arc::new(NatStats::default());
+        let (stop_relay, relay_shutdown) = common::shutdown();
+        let relaying = tokio::spawn(nat::relay(
+            listener,
+            addr,
+            None,
+            impairment,
+            nat_stats.clone(),
+            relay_shutdown,
+        ));
+        relays.push((stop_relay, relaying, nat_stats));
+        paths.push(PathSpec {
+            local: common::udp_addr(),
+            remote: Some(relay_addr),
+        });
+    }
+
+    let sockets = Sockets::default();
+    let client = echo::client(
+        common::roots(),
+        None,
+        &sockets,
+        &ResumptionOptions::default(),
+        &congestion,
+        Arc::new(NullLogger),
+    )
+    .unwrap();
+    let file = Arc::new(common::payload(1, 2_000_000));
+    let streams = 4;
+    let connection = client.connect("localhost", addr).unwrap();
+    let carried = echo::echo_multipath(
+        &connection,
+        &sockets,
+        addr,
+        file.clone(),
+        common::progress(streams, file.len()),
+        &MultipathOptions { paths },
+    )
+    .await
+    .unwrap();
+    connection.close("no error".into(), 0);
+
+    let carried = carried.iter().collect::<Vec<_>>();
+    assert_eq!(carried.len(), 3, "{carried:?}");
+    for (path, traffic) in carried {
+        assert!(traffic.sent_bytes > 0, "{path} {traffic:?}");
+        assert!(traffic.received_packets > 0, "{path} {traffic:?}");
+    }
+
+    for (stop_relay, relaying, nat_stats) in relays {
+        stop_relay.send(()).unwrap();
+        relaying.await.unwrap().unwrap();
+        assert_eq!(nat_stats.clients(), 1);
+        assert!(nat_stats.forwarded() > 0);
+        assert!(nat_stats.returned() > 0);
+    }
+
+    stop.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), serving)
+        .await
+        .expect("server didn't shut down")
+        .unwrap();
+    assert_eq!(stats.connections(), 1);
+    assert_eq!(stats.bytes(), (streams * file.len()) as u64);
+}
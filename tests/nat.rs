@@ -0,0 +1,140 @@
+//! `nat-proxy` over loopback, in front of a UDP echo that notes where every
+//! datagram came from.
+
+mod common;
+
+use std::{
+    collections::BTreeSet,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use big_file_test::nat::{self, Impairment, NatStats};
+use tokio::{
+    net::UdpSocket,
+    time::{self, Instant},
+};
+
+/// A UDP echo on loopback and the addresses datagrams came to it from.
+async fn echo() -> (SocketAddr, Arc<Mutex<BTreeSet<SocketAddr>>>) {
+    let echo = UdpSocket::bind("[::1]:0").await.unwrap();
+    let addr = echo.local_addr().unwrap();
+    let sources = Arc::new(Mutex::new(BTreeSet::new()));
+    tokio::spawn({
+        let sources = sources.clone();
+        async move {
+            let mut buf = [0; 1500];
+            while let Ok((len, from)) = echo.recv_from(&mut buf).await {
+                sources.lock().unwrap().insert(from);
+                _ = echo.send_to(&buf[..len], from).await;
+            }
+        }
+    });
+    (addr, sources)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rebinding_moves_clients_to_new_ports() {
+    let (echo_addr, sources) = echo().await;
+
+    let listener = UdpSocket::bind("[::1]:0").await.unwrap();
+    let relay_addr = listener.local_addr().unwrap();
+    let stats = Arc::new(NatStats::default());
+    let (stop, shutdown) = common::shutdown();
+    let relaying = tokio::spawn(nat::relay(
+        listener,
+        echo_addr,
+        Some(Duration::from_millis(50)),
+        Impairment::default(),
+        stats.clone(),
+        shutdown,
+    ));
+
+    let client = UdpSocket::bind("[::1]:0").await.unwrap();
+    client.connect(relay_addr).await.unwrap();
+    let datagrams = 30u32;
+    let mut buf = [0; 1500];
+    for idx in 0..datagrams {
+        // a reply on its way to a port given up in between is lost
+        let mut echoed = None;
+        for _ in 0..3 {
+            client.send(&idx.to_be_bytes()).await.unwrap();
+            if let Ok(len) = time::timeout(Duration::from_millis(200), client.recv(&mut buf)).await
+            {
+                echoed = Some(buf[..len.unwrap()].to_vec());
+                break;
+            }
+        }
+        assert_eq!(echoed.as_deref(), Some(&idx.to_be_bytes()[..]));
+        time::sleep(Duration::from_millis(10)).await;
+    }
+
+    stop.send(()).unwrap();
+    relaying.await.unwrap().unwrap();
+    assert_eq!(stats.clients(), 1);
+    // 30 round trips of at least 10ms each span several 50ms rebinds, each a
+    // new source port to the echo unless no datagram went out in between
+    let sources = sources.lock().unwrap();
+    assert!(sources.len() >= 4, "{sources:?}");
+    assert!(sources.len() as u64 <= stats.rebinds() + 1, "{sources:?}");
+    assert!(
+        sources
+            .iter()
+            .all(|source| source.port() != relay_addr.port())
+    );
+    assert!(stats.returned() >= 4 * datagrams as u64);
+    assert!(stats.returned() <= stats.forwarded());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn impairment_delays_paces_and_drops() {
+    let (echo_addr, _) = echo().await;
+    let listener = UdpSocket::bind("[::1]:0").await.unwrap();
+    let relay_addr = listener.local_addr().unwrap();
+    let stats = Arc::new(NatStats::default());
+    let (stop, shutdown) = common::shutdown();
+    // a datagram of 1000 bytes takes 10ms, 6 of them fit the queue
+    let impairment = Impairment {
+        delay: 30.0,
+        rate: Some(100_000.0),
+        loss: 0.0,
+        queue: 55.0,
+    };
+    let relaying = tokio::spawn(nat::relay(
+        listener,
+        echo_addr,
+        None,
+        impairment,
+        stats.clone(),
+        shutdown,
+    ));
+
+    let client = UdpSocket::bind("[::1]:0").await.unwrap();
+    client.connect(relay_addr).await.unwrap();
+    let start = Instant::now();
+    for idx in 0..20u8 {
+        client.send(&[idx; 1000]).await.unwrap();
+    }
+    let mut buf = [0; 1500];
+    let mut arrivals = vec![];
+    while let Ok(len) = time::timeout(Duration::from_millis(300), client.recv(&mut buf)).await {
+        assert_eq!(len.unwrap(), 1000);
+        arrivals.push((buf[0], start.elapsed()));
+    }
+
+    stop.send(()).unwrap();
+    relaying.await.unwrap().unwrap();
+    // at least the first 6 go through in order, delayed both ways and paced
+    // twice. more fit the queue the slower the sends were.
+    let delivered = arrivals.len();
+    assert!((6..20).contains(&delivered), "{arrivals:?}");
+    assert_eq!(stats.dropped() + delivered as u64, 20);
+    assert!(arrivals.is_sorted_by_key(|(idx, _)| *idx));
+    assert_eq!(arrivals[0].0, 0);
+    for (nth, (_, arrival)) in arrivals.iter().enumerate() {
+        assert!(*arrival >= Duration::from_millis(60 + 20 + 10 * nth as u64));
+    }
+    assert_eq!(stats.returned(), 1000 * delivered as u64);
+    assert!(stats.forwarded() >= stats.returned());
+}
@@ -0,0 +1,93 @@
+//! `client` staying put behind a `nat-proxy` that moves it. gm-quic runs one
+//! server per process, so this binary has one test.
+
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use big_file_test::{
+    congestion::CongestionOptions,
+    echo,
+    nat::{self, Impairment, NatStats},
+    report::ServerStats,
+    sockets::Sockets,
+    tls::{ResumptionOptions, TicketOptions},
+};
+use qlog::telemetry::handy::NullLogger;
+use tokio::net::UdpSocket;
+
+// gm-quic 0.2.2 keeps sending on the path the NAT forgot next to the new one,
+// and only sends again what it lost there once that path idles out
+#[ignore = "gm-quic 0.2.2 stalls until the forgotten path idles out"]
+#[tokio::test(flavor = "multi_thread")]
+async fn transfers_survive_rebinding() {
+    common::install_crypto_provider();
+    let addr = common::udp_addr();
+    let congestion = CongestionOptions::default();
+    let server = echo::listen(
+        addr,
+        common::CERTS.1.cert_pem().as_bytes(),
+        common::CERTS.1.key_pem().as_bytes(),
+        None,
+        &TicketOptions::default(),
+        &congestion,
+        Arc::new(NullLogger),
+    )
+    .unwrap();
+
+    let stats = Arc::new(ServerStats::default());
+    let (stop, shutdown) = common::shutdown();
+    let serving = tokio::spawn({
+        let server = server.clone();
+        let stats = stats.clone();
+        async move { echo::serve(&server, stats, shutdown).await }
+    });
+
+    let sockets = Sockets::default();
+    let client = echo::client(
+        common::roots(),
+        None,
+        &sockets,
+        &ResumptionOptions::default(),
+        &congestion,
+        Arc::new(NullLogger),
+    )
+    .unwrap();
+    let file = Arc::new(common::payload(1, 2_000_000));
+    let streams = 4;
+
+    // the relay moves it every 20ms without the client knowing
+    let listener = UdpSocket::bind("[::1]:0").await.unwrap();
+    let relay_addr = listener.local_addr().unwrap();
+    let nat_stats = Arc::new(NatStats::default());
+    let (stop_relay, relay_shutdown) = common::shutdown();
+    let relaying = tokio::spawn(nat::relay(
+        listener,
+        addr,
+        Some(Duration::from_millis(20)),
+        Impairment::default(),
+        nat_stats.clone(),
+        relay_shutdown,
+    ));
+    let connection = client.connect("localhost", relay_addr).unwrap();
+    echo::echo_streams(
+        &connection,
+        file.clone(),
+        common::progress(streams, file.len()),
+    )
+    .await
+    .unwrap();
+    connection.close("no error".into(), 0);
+    stop_relay.send(()).unwrap();
+    relaying.await.unwrap().unwrap();
+    assert_eq!(nat_stats.clients(), 1);
+    assert!(nat_stats.rebinds() >= 1);
+
+    stop.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), serving)
+        .await
+        .expect("server didn't shut down")
+        .unwrap();
+    assert_eq!(stats.connections(), 1);
+    assert_eq!(stats.bytes(), (streams * file.len()) as u64);
+}
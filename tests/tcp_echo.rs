@@ -0,0 +1,226 @@
+//! `tcp-server` and `tcp-client` over loopback, in the clear, over TLS and
+//! over mutual TLS.
+
+mod common;
+
+use std::{io, sync::Arc, time::Duration};
+
+use big_file_test::{
+    certs::{Ca, CertSpec, Issued, KeyType},
+    report::ServerStats,
+    tcp::TcpOptions,
+    tcp_echo::{self, Client, Echoed, Server},
+    tls::{self, ClientAuth, Identity, TlsOptions},
+};
+use rustls::{AlertDescription, pki_types::ServerName};
+use time::OffsetDateTime;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+/// Echo over TLS if `tls` is given, with the server certificate of `certs`.
+async fn echo(tls: Option<(TlsOptions, &(Ca, Issued))>) {
+    let acceptor = tls.as_ref().map(|(tls, (_, server))| {
+        let config = tls
+            .server_config(vec![server.cert_der()], server.key_der(), None)
+            .unwrap();
+        TlsAcceptor::from(Arc::new(config))
+    });
+    let connector = tls.as_ref().map(|(tls, (ca, _))| {
+        let config = tls.client_config(ca.roots().unwrap(), None).unwrap();
+        let server_name = ServerName::try_from("localhost").unwrap();
+        (TlsConnector::from(Arc::new(config)), server_name)
+    });
+    let connections = 3;
+    let file_len = 300_000;
+    let (echoed, stats) = run(acceptor, connector, connections, file_len).await;
+    // fails on the first byte that differs or a connection cut short
+    let echoed = echoed.unwrap();
+    assert_eq!(echoed.len(), connections);
+    assert!(
+        echoed
+            .iter()
+            .all(|echoed| echoed.tls.is_some() == tls.is_some())
+    );
+    assert_eq!(stats.connections(), connections as u64);
+    assert_eq!(stats.bytes(), (connections * file_len) as u64);
+}
+
+/// Echo `connections` times a file of `file_len` bytes through a server that
+/// is shut down afterwards, whatever the echo came to.
+async fn run(
+    acceptor: Option<TlsAcceptor>,
+    connector: Option<(TlsConnector, ServerName<'static>)>,
+    connections: usize,
+    file_len: usize,
+) -> (io::Result<Vec<Echoed>>, Arc<ServerStats>) {
+    let listener = tcp_echo::listen(&TcpOptions::default(), "[::1]:0".parse().unwrap()).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server = Arc::new(Server {
+        tcp: TcpOptions::default(),
+        tls: acceptor,
+        buffer_size: 4096,
+    });
+    let stats = Arc::new(ServerStats::default());
+    let (stop, shutdown) = common::shutdown();
+    let serving = tokio::spawn(tcp_echo::serve(listener, server, stats.clone(), shutdown));
+
+    let client = Arc::new(Client {
+        tcp: TcpOptions::default(),
+        tls: connector,
+    });
+    let file = Arc::new(common::payload(2, file_len));
+    let echoed = tcp_echo::echo_connections(
+        addr,
+        client,
+        file.clone(),
+        common::progress(connections, file.len()),
+    )
+    .await;
+
+    stop.send(()).unwrap();
+    let tcp_infos = tokio::time::timeout(Duration::from_secs(5), serving)
+        .await
+        .expect("server didn't shut down")
+        .unwrap()
+        .unwrap();
+    assert!(tcp_infos.len() <= connections);
+    (echoed, stats)
+}
+
+/// Echo once over TLS with the server asking for a certificate the way `auth`
+/// says and the client presenting `identity`, returning the alert the server
+/// turned the client away with.
+async fn mutual_tls(auth: ClientAuth, identity: Option<&Issued>) -> Result<(), AlertDescription> {
+    let (ca, server) = &*common::CERTS;
+    let tls = TlsOptions {
+        tls: true,
+        ..TlsOptions::default()
+    };
+    let verifier = tls::client_verifier(ca.roots().unwrap(), auth).unwrap();
+    let config = tls
+        .server_config(vec![server.cert_der()], server.key_der(), verifier)
+        .unwrap();
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+
+    let identity = identity.map(|issued| Identity {
+        cert_chain: vec![issued.cert_der()],
+        key: issued.key_der(),
+    });
+    let config = tls
+        .client_config(ca.roots().unwrap(), identity.as_ref())
+        .unwrap();
+    let server_name = ServerName::try_from("localhost").unwrap();
+    let connector = (TlsConnector::from(Arc::new(config)), server_name);
+
+    let file_len = 100_000;
+    let (echoed, stats) = run(Some(acceptor), Some(connector), 1, file_len).await;
+    assert_eq!(stats.connections(), 1);
+    match echoed {
+        Ok(_) => {
+            assert_eq!(stats.bytes(), file_len as u64);
+            Ok(())
+        }
+        Err(error) => {
+            // the server never echoes for a client it turned away
+            assert_eq!(stats.bytes(), 0);
+            match error.get_ref().and_then(|e| e.downcast_ref()) {
+                Some(rustls::Error::AlertReceived(alert)) => Err(*alert),
+                _ => panic!("not turned away by the server: {error}"),
+            }
+        }
+    }
+}
+
+fn client_spec() -> CertSpec {
+    CertSpec::new(KeyType::P256, &["test-client"], time::Duration::days(1))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn echoes_every_connection() {
+    echo(None).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn echoes_every_connection_over_tls() {
+    let tls = TlsOptions {
+        tls: true,
+        tls_record_size: Some(1200),
+        ..TlsOptions::default()
+    };
+    echo(Some((tls, &common::CERTS))).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn echoes_over_tls_with_every_key_type() {
+    // RSA-4096 only takes longer to make
+    for key_type in [KeyType::P256, KeyType::Ed25519, KeyType::Rsa2048] {
+        let tls = TlsOptions {
+            tls: true,
+            ..TlsOptions::default()
+        };
+        echo(Some((tls, &common::certs(key_type)))).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn mutual_tls_accepts_clients_of_the_ca() {
+    let client = common::CERTS.0.issue_client(&client_spec()).unwrap();
+    assert_eq!(
+        mutual_tls(ClientAuth::Required, Some(&client)).await,
+        Ok(())
+    );
+    assert_eq!(
+        mutual_tls(ClientAuth::Optional, Some(&client)).await,
+        Ok(())
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn optional_client_auth_lets_clients_without_a_certificate_in() {
+    assert_eq!(mutual_tls(ClientAuth::Optional, None).await, Ok(()));
+    assert_eq!(
+        mutual_tls(ClientAuth::Required, None).await,
+        Err(AlertDescription::CertificateRequired)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn mutual_tls_rejects_an_unknown_ca() {
+    let spec = CertSpec::new(KeyType::P256, &[], time::Duration::days(1));
+    let other_ca = Ca::named("unknown CA", &spec).unwrap();
+    let client = other_ca.issue_client(&client_spec()).unwrap();
+    assert_eq!(
+        mutual_tls(ClientAuth::Required, Some(&client)).await,
+        Err(AlertDescription::UnknownCA)
+    );
+    // a certificate that doesn't verify is turned away even if it's optional
+    assert_eq!(
+        mutual_tls(ClientAuth::Optional, Some(&client)).await,
+        Err(AlertDescription::UnknownCA)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn mutual_tls_rejects_an_expired_certificate() {
+    let now = OffsetDateTime::now_utc();
+    let spec = CertSpec {
+        not_before: now - time::Duration::days(2),
+        not_after: now - time::Duration::days(1),
+        ..client_spec()
+    };
+    let client = common::CERTS.0.issue_client(&spec).unwrap();
+    assert_eq!(
+        mutual_tls(ClientAuth::Required, Some(&client)).await,
+        Err(AlertDescription::CertificateExpired)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn mutual_tls_rejects_a_certificate_not_for_clients() {
+    // the server certificate is only good for server authentication
+    let (_, server) = &*common::CERTS;
+    assert_eq!(
+        mutual_tls(ClientAuth::Required, Some(server)).await,
+        Err(AlertDescription::UnsupportedCertificate)
+    );
+}
@@ -0,0 +1,82 @@
+//! What `trace-analyze` reads back from the JSON tracing output is what was
+//! emitted.
+
+use std::{
+    io,
+    sync::{Arc, Mutex},
+};
+
+use big_file_test::trace_events::{Event, Record, RuntimeMetrics};
+
+/// The lines the JSON layer wrote.
+#[derive(Clone, Default)]
+struct Output(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn events_round_trip() {
+    let events = [
+        Event::BurstQuota { quota: 12_000 },
+        Event::BurstLoadResult { bytes: Some(4_800) },
+        Event::BurstLoadResult { bytes: None },
+        Event::BurstLoaded,
+        Event::BurstSentAll,
+        Event::WaitFor {
+            signals: "CONGESTION | FLOW_CONTROL".to_owned(),
+        },
+        Event::WokenBy {
+            signals: "CONGESTION".to_owned(),
+        },
+    ];
+    let metrics = RuntimeMetrics {
+        workers: 4,
+        alive_tasks: 17,
+        busy_ratio: 0.25,
+        polls: 1_000,
+        ..Default::default()
+    };
+
+    let output = Output::default();
+    let subscriber = tracing_subscriber::fmt()
+        .json()
+        .with_max_level(tracing::Level::DEBUG)
+        .with_writer({
+            let output = output.clone();
+            move || output.clone()
+        })
+        .finish();
+    tracing::subscriber::with_default(subscriber, || {
+        // records that only look like ones are left alone
+        tracing::debug!(quota = 1, "burst: get quota");
+        for event in &events {
+            event.emit();
+        }
+        metrics.emit();
+    });
+
+    let output = output.0.lock().unwrap();
+    let records = output
+        .split(|&byte| byte == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| serde_json::from_slice::<Record>(line).unwrap())
+        .collect::<Vec<_>>();
+    let read = records
+        .iter()
+        .filter_map(Event::from_record)
+        .collect::<Vec<_>>();
+    assert_eq!(read, events);
+    let samples = records
+        .iter()
+        .filter_map(RuntimeMetrics::from_record)
+        .collect::<Vec<_>>();
+    assert_eq!(samples, [metrics]);
+}
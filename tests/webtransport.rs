@@ -0,0 +1,137 @@
+//! `h3-client`'s WebTransport sessions to `h3-server`, echoing and sinking
+//! streams and datagrams. gm-quic runs one server per process, so this binary
+//! has one test.
+
+mod common;
+
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use big_file_test::{
+    congestion::CongestionOptions,
+    fetch::{Connector, Protocol},
+    files,
+    report::ServerStats,
+    tls::{ResumptionOptions, TicketOptions},
+    webtransport::{Session, WebTransportOptions},
+};
+use http::Uri;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn echoes_and_sinks_sessions() {
+    common::install_crypto_provider();
+    let addr = common::udp_addr();
+    let congestion = CongestionOptions::default();
+    let server = files::listen_h3(
+        &[addr],
+        common::CERTS.1.cert_pem().as_bytes(),
+        common::CERTS.1.key_pem().as_bytes(),
+        None,
+        &TicketOptions::default(),
+        &congestion,
+    )
+    .unwrap();
+
+    let stats = Arc::new(ServerStats::default());
+    let (stop, shutdown) = common::shutdown();
+    let root = Arc::new(common::scratch_dir("webtransport"));
+    let serving = tokio::spawn({
+        let server = server.clone();
+        let stats = stats.clone();
+        async move { files::serve_h3(&server, root, 0, true, true, stats, shutdown).await }
+    });
+
+    let Connector::Quic(client) = Connector::new(
+        Protocol::H3,
+        common::roots(),
+        None,
+        &ResumptionOptions::default(),
+        &congestion,
+    )
+    .unwrap() else {
+        unreachable!()
+    };
+    let file = Arc::new(common::payload(1, 300_000));
+    let options = WebTransportOptions {
+        webtransport: true,
+        wt_file: PathBuf::new(),
+        wt_bidi: 2,
+        wt_uni: 1,
+        wt_datagrams: Some(100),
+        wt_datagram_size: 512,
+        // slow enough for loopback to lose none
+        wt_datagram_rate: Some(2000.0),
+    };
+    let uri = |path: &str| {
+        format!("https://localhost:{}{path}", addr.port())
+            .parse::<Uri>()
+            .unwrap()
+    };
+
+    // gm-quic 0.2.2 only packs datagrams into what room stream data leaves,
+    // so the reflected ones are checked on a session of their own
+    let session = Session::open(&client, &addr.into(), &uri("/echo"))
+        .await
+        .unwrap();
+    let reflected = session
+        .run(
+            file.clone(),
+            common::progress(0, file.len()),
+            &WebTransportOptions {
+                wt_uni: 0,
+                ..options.clone()
+            },
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    session.close();
+    assert_eq!(reflected.received(), 100);
+
+    let session = Session::open(&client, &addr.into(), &uri("/echo"))
+        .await
+        .unwrap();
+    let echoed = session
+        .run(
+            file.clone(),
+            common::progress(2, file.len()),
+            &WebTransportOptions {
+                wt_datagrams: None,
+                wt_datagram_rate: None,
+                ..options.clone()
+            },
+        )
+        .await
+        .unwrap();
+    session.close();
+    assert!(echoed.is_none());
+
+    // the sink answers the bidirectional streams with what it got
+    let session = Session::open(&client, &addr.into(), &uri("/sink"))
+        .await
+        .unwrap();
+    let sunk = session
+        .run(file.clone(), common::progress(2, file.len()), &options)
+        .await
+        .unwrap()
+        .unwrap();
+    session.close();
+    assert_eq!(sunk.received(), 0);
+
+    stop.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), serving)
+        .await
+        .expect("server didn't shut down")
+        .unwrap();
+    assert_eq!(stats.connections(), 3);
+    assert_eq!(stats.sessions(), 3);
+    // both got all of the bidirectional streams, the client doesn't wait
+    // for the unidirectional ones to be read, let alone echoed
+    let (bytes, len) = (stats.bytes(), file.len() as u64);
+    assert!(
+        (4..=6).contains(&(bytes / len)) && bytes % len == 0,
+        "{stats:?}"
+    );
+    // all reflected ones got there, but the sink's wait for room between the
+    // stream data and may still be on their way when the client closes
+    assert!((100..=200).contains(&stats.datagrams()), "{stats:?}");
+}